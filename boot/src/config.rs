@@ -1,18 +1,59 @@
-// TODO: use no_std serde crate to parse
+//! Parser of `rboot.conf`
+//!
+//! The config file consists of `key=value` lines. Blank lines and lines
+//! starting with `#` are ignored, and a `#` after an unquoted value starts
+//! a trailing comment. Values may be quoted with `"` to keep spaces or `#`.
+//!
+//! Numbers are given in decimal or hex (`0x`), may contain `_` separators and
+//! may end with a size suffix `K`, `M`, `G` or `T` (powers of 1024).
+//!
+//! A line `[name]` starts a boot menu entry. Entry keys (`kernel_path`,
+//! `kernel_sha256`, `kernel_signature`, `cmdline`, `initramfs`, `resolution`)
+//! before the first entry are defaults
+//! inherited by entries declared after them. Without any entry, the defaults
+//! form a single implicit entry.
 
-use core::str::FromStr;
+use crate::verify::parse_hex;
+use arrayvec::ArrayVec;
+use core::fmt;
+
+/// The maximum number of boot menu entries
+pub const MAX_ENTRIES: usize = 9;
 
 /// Config for the bootloader
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Config<'a> {
     /// The address at which the kernel stack is placed
     pub kernel_stack_address: u64,
     /// The size of the kernel stack, given in number of 4KiB pages
     pub kernel_stack_size: u64,
+    /// The maximum number of processors to start, including the BSP
+    pub max_cpus: usize,
     /// The offset into the virtual address space where the physical memory is mapped
     pub physical_memory_offset: u64,
+    /// Consoles used by the bootloader and the kernel
+    pub console: Consoles,
+    /// Seconds to wait in the boot menu before booting the default entry
+    pub timeout: u64,
+    /// The name of the default entry, the first entry if not given
+    pub default_entry: Option<&'a str>,
+    /// Settings inherited by entries
+    pub defaults: BootEntry<'a>,
+    /// Boot menu entries
+    pub entries: ArrayVec<BootEntry<'a>, MAX_ENTRIES>,
+}
+
+/// A boot menu entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootEntry<'a> {
+    /// The name shown in boot menu
+    pub name: &'a str,
     /// The path of kernel ELF
     pub kernel_path: &'a str,
+    /// The expected SHA-256 digest of kernel ELF
+    pub kernel_sha256: Option<[u8; 32]>,
+    /// The path of the detached Ed25519 signature of kernel ELF
+    pub kernel_signature: Option<&'a str>,
     /// The resolution of graphic output
     pub resolution: Option<(usize, usize)>,
     /// The path of initramfs
@@ -21,55 +62,723 @@ pub struct Config<'a> {
     pub cmdline: &'a str,
 }
 
-const DEFAULT_CONFIG: Config = Config {
-    kernel_stack_address: 0xFFFF_FF01_0000_0000,
-    kernel_stack_size: 512,
-    physical_memory_offset: 0xFFFF_8000_0000_0000,
+/// Consoles to output to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Consoles {
+    /// The serial port COM1
+    pub serial: bool,
+    /// The text console drawn on the framebuffer
+    pub framebuffer: bool,
+}
+
+pub const DEFAULT_ENTRY: BootEntry<'static> = BootEntry {
+    name: "default",
     kernel_path: "\\EFI\\rCore\\kernel.elf",
+    kernel_sha256: None,
+    kernel_signature: None,
     resolution: None,
     initramfs: None,
     cmdline: "",
 };
 
+pub const DEFAULT_CONFIG: Config<'static> = Config {
+    kernel_stack_address: 0xFFFF_FF01_0000_0000,
+    kernel_stack_size: 512,
+    max_cpus: 1,
+    physical_memory_offset: 0xFFFF_8000_0000_0000,
+    console: Consoles {
+        serial: false,
+        framebuffer: true,
+    },
+    timeout: 3,
+    default_entry: None,
+    defaults: DEFAULT_ENTRY,
+    entries: ArrayVec::new_const(),
+};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Error found when parsing or validating the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigError {
+    /// 1-based line number, 0 if the error is not bound to a line
+    pub line: usize,
+    /// 1-based column number, 0 if the error is not bound to a line
+    pub column: usize,
+    pub kind: ConfigErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigErrorKind {
+    /// The file is not valid UTF-8
+    InvalidUtf8,
+    /// The line has no `=`
+    MissingEquals,
+    /// The key is empty
+    EmptyKey,
+    /// The key is not recognized
+    UnknownKey,
+    /// A quoted value has no closing quote
+    UnterminatedQuote,
+    /// Unexpected characters after a quoted value
+    TrailingCharacters,
+    /// The value is not a valid number
+    InvalidNumber,
+    /// The number does not fit in 64 bits
+    NumberOverflow,
+    /// The value is not a valid boolean
+    InvalidBool,
+    /// The value is not a valid resolution like `800x600`
+    InvalidResolution,
+    /// The value is not a SHA-256 digest of 64 hex digits
+    InvalidDigest,
+    /// The value is not a list of consoles like `serial,fb`
+    InvalidConsole,
+    /// The address is not aligned to 4KiB
+    Misaligned,
+    /// The address is not canonical
+    NonCanonical,
+    /// The value must not be zero
+    Zero,
+    /// The value is out of the supported range
+    OutOfRange,
+    /// The entry name in `[name]` is empty or has no closing `]`
+    InvalidEntryName,
+    /// Two entries have the same name
+    DuplicateEntry,
+    /// More than `MAX_ENTRIES` entries are declared
+    TooManyEntries,
+    /// The key can not be set inside an entry
+    GlobalKeyInEntry,
+    /// The default entry does not exist
+    UnknownEntry,
+    /// The kernel stack overlaps the physical memory mapping
+    StackOverlapsPhysicalMemory,
+}
+
+impl ConfigError {
+    fn new(line: usize, column: usize, kind: ConfigErrorKind) -> Self {
+        Self { line, column, kind }
+    }
+}
+
+impl fmt::Display for ConfigErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ConfigErrorKind::*;
+        f.write_str(match self {
+            InvalidUtf8 => "config is not valid UTF-8",
+            MissingEquals => "expected `key=value`",
+            EmptyKey => "empty key",
+            UnknownKey => "unknown key",
+            UnterminatedQuote => "unterminated quoted value",
+            TrailingCharacters => "unexpected characters after quoted value",
+            InvalidNumber => "invalid number",
+            NumberOverflow => "number too large",
+            InvalidBool => "invalid boolean, expected true/false/yes/no/on/off/1/0",
+            InvalidResolution => "invalid resolution, expected `<width>x<height>`",
+            InvalidDigest => "invalid SHA-256 digest, expected 64 hex digits",
+            InvalidConsole => "invalid console, expected a list of `serial` and `fb`",
+            Misaligned => "address is not aligned to 4KiB",
+            NonCanonical => "address is not canonical",
+            Zero => "value must not be zero",
+            OutOfRange => "value is out of the supported range",
+            InvalidEntryName => "invalid entry, expected `[name]`",
+            DuplicateEntry => "duplicate entry name",
+            TooManyEntries => "too many entries",
+            GlobalKeyInEntry => "key must be set before the first entry",
+            UnknownEntry => "default entry does not exist",
+            StackOverlapsPhysicalMemory => "kernel stack overlaps the physical memory mapping",
+        })
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "config error: {}", self.kind)
+        } else {
+            write!(
+                f,
+                "config error at line {}, column {}: {}",
+                self.line, self.column, self.kind
+            )
+        }
+    }
+}
+
+/// Parse a number with optional `0x` prefix, `_` separators and size suffix
+pub fn parse_number(value: &str) -> Result<u64, ConfigErrorKind> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'K') | Some(b'k') => (&value[..value.len() - 1], 10),
+        Some(b'M') | Some(b'm') => (&value[..value.len() - 1], 20),
+        Some(b'G') | Some(b'g') => (&value[..value.len() - 1], 30),
+        Some(b'T') | Some(b't') => (&value[..value.len() - 1], 40),
+        _ => (value, 0),
+    };
+    let (digits, radix) = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => (hex, 16),
+        None => (digits, 10),
+    };
+    if digits.is_empty() || digits.starts_with('_') {
+        return Err(ConfigErrorKind::InvalidNumber);
+    }
+    let mut result = 0u64;
+    for c in digits.chars().filter(|c| *c != '_') {
+        let digit = c.to_digit(radix).ok_or(ConfigErrorKind::InvalidNumber)?;
+        result = result
+            .checked_mul(radix as u64)
+            .and_then(|r| r.checked_add(digit as u64))
+            .ok_or(ConfigErrorKind::NumberOverflow)?;
+    }
+    result
+        .checked_mul(1 << shift)
+        .ok_or(ConfigErrorKind::NumberOverflow)
+}
+
+/// Parse a boolean value
+pub fn parse_bool(value: &str) -> Result<bool, ConfigErrorKind> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(ConfigErrorKind::InvalidBool),
+    }
+}
+
+/// Parse a resolution like `800x600`
+pub fn parse_resolution(value: &str) -> Result<(usize, usize), ConfigErrorKind> {
+    let (x, y) = value
+        .split_once('x')
+        .ok_or(ConfigErrorKind::InvalidResolution)?;
+    let x = x.parse().map_err(|_| ConfigErrorKind::InvalidResolution)?;
+    let y = y.parse().map_err(|_| ConfigErrorKind::InvalidResolution)?;
+    if x == 0 || y == 0 {
+        return Err(ConfigErrorKind::InvalidResolution);
+    }
+    Ok((x, y))
+}
+
+/// Parse a comma separated list of consoles, e.g. `serial,fb`
+pub fn parse_consoles(value: &str) -> Result<Consoles, ConfigErrorKind> {
+    let mut consoles = Consoles {
+        serial: false,
+        framebuffer: false,
+    };
+    for name in value.split(',') {
+        match name.trim() {
+            "serial" => consoles.serial = true,
+            "fb" => consoles.framebuffer = true,
+            _ => return Err(ConfigErrorKind::InvalidConsole),
+        }
+    }
+    Ok(consoles)
+}
+
+fn parse_address(value: &str) -> Result<u64, ConfigErrorKind> {
+    let addr = parse_number(value)?;
+    if addr % PAGE_SIZE != 0 {
+        return Err(ConfigErrorKind::Misaligned);
+    }
+    // bits 48..64 must be copies of bit 47
+    let high = addr >> 47;
+    if high != 0 && high != 0x1FFFF {
+        return Err(ConfigErrorKind::NonCanonical);
+    }
+    Ok(addr)
+}
+
+/// Parse the stack size; plain numbers are pages while sizes with suffix are bytes
+fn parse_pages(value: &str) -> Result<u64, ConfigErrorKind> {
+    let has_suffix = value
+        .chars()
+        .last()
+        .map_or(false, |c| "KkMmGgTt".contains(c));
+    let number = parse_number(value)?;
+    let pages = if has_suffix {
+        if number % PAGE_SIZE != 0 {
+            return Err(ConfigErrorKind::Misaligned);
+        }
+        number / PAGE_SIZE
+    } else {
+        number
+    };
+    if pages == 0 {
+        return Err(ConfigErrorKind::Zero);
+    }
+    Ok(pages)
+}
+
+fn parse_cpus(value: &str) -> Result<usize, ConfigErrorKind> {
+    match parse_number(value)? {
+        0 => Err(ConfigErrorKind::Zero),
+        n if n as usize > crate::MAX_CPUS => Err(ConfigErrorKind::OutOfRange),
+        n => Ok(n as usize),
+    }
+}
+
+fn is_global_key(key: &str) -> bool {
+    matches!(
+        key,
+        "kernel_stack_address"
+            | "kernel_stack_size"
+            | "max_cpus"
+            | "physical_memory_offset"
+            | "console"
+            | "timeout"
+            | "default"
+    )
+}
+
+/// Column (1-based, in characters) of `part` inside `line`
+fn column_of(line: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - line.as_ptr() as usize;
+    line[..offset].chars().count() + 1
+}
+
+/// Strip quotes and trailing comment of the raw value
+fn unquote(raw: &str) -> Result<&str, (usize, ConfigErrorKind)> {
+    if let Some(rest) = raw.strip_prefix('"') {
+        let end = rest
+            .find('"')
+            .ok_or((0, ConfigErrorKind::UnterminatedQuote))?;
+        let tail = rest[end + 1..].trim_start();
+        if !tail.is_empty() && !tail.starts_with('#') {
+            return Err((raw.len() - tail.len(), ConfigErrorKind::TrailingCharacters));
+        }
+        Ok(&rest[..end])
+    } else {
+        let end = raw.find('#').unwrap_or(raw.len());
+        Ok(raw[..end].trim_end())
+    }
+}
+
 impl<'a> Config<'a> {
-    pub fn parse(content: &'a [u8]) -> Self {
-        let content = core::str::from_utf8(content).expect("failed to parse config as utf8");
+    /// Parse the content of config file
+    pub fn parse(content: &'a [u8]) -> Result<Self, ConfigError> {
+        let content = core::str::from_utf8(content)
+            .map_err(|_| ConfigError::new(0, 0, ConfigErrorKind::InvalidUtf8))?;
         let mut config = DEFAULT_CONFIG;
-        for line in content.split('\n') {
-            let line = line.trim();
+        // position of the value of `default` key
+        let mut default_at = (0, 0);
+        for (lineno, line) in content.lines().enumerate() {
+            let lineno = lineno + 1;
+            let trimmed = line.trim();
             // skip empty and comment
-            if line.is_empty() || line.starts_with('#') {
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            // parse '[name]'
+            if let Some(header) = trimmed.strip_prefix('[') {
+                let column = column_of(line, trimmed);
+                let name = header
+                    .strip_suffix(']')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| {
+                        ConfigError::new(lineno, column, ConfigErrorKind::InvalidEntryName)
+                    })?;
+                config
+                    .add_entry(name)
+                    .map_err(|kind| ConfigError::new(lineno, column, kind))?;
                 continue;
             }
             // parse 'key=value'
-            let mut iter = line.splitn(2, '=');
-            let key = iter.next().expect("failed to parse key");
-            let value = iter.next().expect("failed to parse value");
-            config.process(key, value);
+            let (key, raw) = trimmed.split_once('=').ok_or_else(|| {
+                ConfigError::new(
+                    lineno,
+                    column_of(line, trimmed),
+                    ConfigErrorKind::MissingEquals,
+                )
+            })?;
+            let key = key.trim_end();
+            if key.is_empty() {
+                return Err(ConfigError::new(
+                    lineno,
+                    column_of(line, trimmed),
+                    ConfigErrorKind::EmptyKey,
+                ));
+            }
+            let raw = raw.trim_start();
+            let value = unquote(raw).map_err(|(offset, kind)| {
+                ConfigError::new(lineno, column_of(line, &raw[offset..]), kind)
+            })?;
+            if key == "default" {
+                default_at = (lineno, column_of(line, raw));
+            }
+            config.process(key, value).map_err(|kind| {
+                let at = match kind {
+                    ConfigErrorKind::UnknownKey | ConfigErrorKind::GlobalKeyInEntry => key,
+                    _ => raw,
+                };
+                ConfigError::new(lineno, column_of(line, at), kind)
+            })?;
         }
-        config
+        if config.default_index().is_none() {
+            return Err(ConfigError::new(
+                default_at.0,
+                default_at.1,
+                ConfigErrorKind::UnknownEntry,
+            ));
+        }
+        Ok(config)
+    }
+
+    fn add_entry(&mut self, name: &'a str) -> Result<(), ConfigErrorKind> {
+        if self.entries.iter().any(|e| e.name == name) {
+            return Err(ConfigErrorKind::DuplicateEntry);
+        }
+        let entry = BootEntry {
+            name,
+            ..self.defaults.clone()
+        };
+        self.entries
+            .try_push(entry)
+            .map_err(|_| ConfigErrorKind::TooManyEntries)
     }
 
-    fn process(&mut self, key: &str, value: &'a str) {
-        info!("parse = {}", value);
-        let r10 = u64::from_str(&value);
-        let r16 = u64::from_str_radix(&value[2..], 16);
+    fn process(&mut self, key: &str, value: &'a str) -> Result<(), ConfigErrorKind> {
+        // keys of the current entry, or the defaults before the first entry
+        let entry = self.entries.last_mut().unwrap_or(&mut self.defaults);
         match key {
-            "kernel_stack_address" => self.kernel_stack_address = r16.unwrap(),
-            "kernel_stack_size" => self.kernel_stack_size = r10.unwrap(),
-            "physical_memory_offset" => {
-                self.physical_memory_offset = r16.unwrap();
+            "kernel_path" => entry.kernel_path = value,
+            "kernel_sha256" => {
+                entry.kernel_sha256 = Some(parse_hex(value).ok_or(ConfigErrorKind::InvalidDigest)?)
             }
-            "kernel_path" => self.kernel_path = value,
-            "resolution" => {
-                let mut iter = value.split('x');
-                let x = iter.next().unwrap().parse::<usize>().unwrap();
-                let y = iter.next().unwrap().parse::<usize>().unwrap();
-                self.resolution = Some((x, y));
+            "kernel_signature" => entry.kernel_signature = Some(value),
+            "resolution" => entry.resolution = Some(parse_resolution(value)?),
+            "initramfs" => entry.initramfs = Some(value),
+            "cmdline" => entry.cmdline = value,
+            _ if !self.entries.is_empty() && is_global_key(key) => {
+                return Err(ConfigErrorKind::GlobalKeyInEntry)
             }
-            "initramfs" => self.initramfs = Some(value),
-            "cmdline" => self.cmdline = value,
-            _ => warn!("undefined config key: {}", key),
+            "kernel_stack_address" => self.kernel_stack_address = parse_address(value)?,
+            "kernel_stack_size" => self.kernel_stack_size = parse_pages(value)?,
+            "max_cpus" => self.max_cpus = parse_cpus(value)?,
+            "physical_memory_offset" => self.physical_memory_offset = parse_address(value)?,
+            "console" => self.console = parse_consoles(value)?,
+            "timeout" => self.timeout = parse_number(value)?,
+            "default" => self.default_entry = Some(value),
+            _ => return Err(ConfigErrorKind::UnknownKey),
+        }
+        Ok(())
+    }
+
+    /// Boot menu entries; the defaults form the only entry if none is declared
+    pub fn entries(&self) -> &[BootEntry<'a>] {
+        if self.entries.is_empty() {
+            core::slice::from_ref(&self.defaults)
+        } else {
+            &self.entries
         }
     }
+
+    /// Index of the default entry in `entries()`
+    pub fn default_index(&self) -> Option<usize> {
+        match self.default_entry {
+            Some(name) => self.entries().iter().position(|e| e.name == name),
+            None => Some(0),
+        }
+    }
+
+    /// The bottom address of the kernel stack of the `cpu`-th processor.
+    ///
+    /// Stacks are placed one after another from `kernel_stack_address`,
+    /// with an unmapped guard page between two stacks.
+    pub fn stack_address(&self, cpu: usize) -> u64 {
+        self.kernel_stack_address + cpu as u64 * (self.kernel_stack_size + 1) * PAGE_SIZE
+    }
+
+    /// The top address of the kernel stack of the `cpu`-th processor
+    pub fn stack_top(&self, cpu: usize) -> u64 {
+        self.stack_address(cpu) + self.kernel_stack_size * PAGE_SIZE
+    }
+
+    /// Check that the kernel stacks do not overlap physical memory mapped at
+    /// `[physical_memory_offset, physical_memory_offset + max_phys_addr)`
+    pub fn validate(&self, max_phys_addr: u64) -> Result<(), ConfigError> {
+        let stack_start = self.kernel_stack_address;
+        let stack_end = (self.kernel_stack_size + 1)
+            .checked_mul(self.max_cpus as u64)
+            .and_then(|pages| pages.checked_mul(PAGE_SIZE))
+            .and_then(|size| stack_start.checked_add(size))
+            .ok_or_else(|| ConfigError::new(0, 0, ConfigErrorKind::NumberOverflow))?;
+        let phys_start = self.physical_memory_offset;
+        let phys_end = phys_start.saturating_add(max_phys_addr);
+        if stack_start < phys_end && phys_start < stack_end {
+            return Err(ConfigError::new(
+                0,
+                0,
+                ConfigErrorKind::StackOverlapsPhysicalMemory,
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("512"), Ok(512));
+        assert_eq!(parse_number("0x1000"), Ok(0x1000));
+        assert_eq!(
+            parse_number("0xFFFF_FF01_0000_0000"),
+            Ok(0xFFFF_FF01_0000_0000)
+        );
+        assert_eq!(parse_number("16M"), Ok(16 << 20));
+        assert_eq!(parse_number("4k"), Ok(4096));
+        assert_eq!(parse_number("0x10K"), Ok(16 << 10));
+        assert_eq!(parse_number(""), Err(ConfigErrorKind::InvalidNumber));
+        assert_eq!(parse_number("0x"), Err(ConfigErrorKind::InvalidNumber));
+        assert_eq!(parse_number("M"), Err(ConfigErrorKind::InvalidNumber));
+        assert_eq!(parse_number("12a"), Err(ConfigErrorKind::InvalidNumber));
+        assert_eq!(
+            parse_number("0x1_0000_0000_0000_0000"),
+            Err(ConfigErrorKind::NumberOverflow)
+        );
+        assert_eq!(parse_number("16T"), Ok(16 << 40));
+        assert_eq!(
+            parse_number("0xFFFFFFFFFFFFT"),
+            Err(ConfigErrorKind::NumberOverflow)
+        );
+    }
+
+    #[test]
+    fn bools_and_resolution() {
+        assert_eq!(parse_bool("yes"), Ok(true));
+        assert_eq!(parse_bool("off"), Ok(false));
+        assert_eq!(parse_bool("maybe"), Err(ConfigErrorKind::InvalidBool));
+        assert_eq!(parse_resolution("800x600"), Ok((800, 600)));
+        assert_eq!(
+            parse_resolution("800"),
+            Err(ConfigErrorKind::InvalidResolution)
+        );
+        assert_eq!(
+            parse_resolution("0x600"),
+            Err(ConfigErrorKind::InvalidResolution)
+        );
+        assert_eq!(
+            parse_consoles("serial"),
+            Ok(Consoles {
+                serial: true,
+                framebuffer: false
+            })
+        );
+        assert_eq!(
+            parse_consoles("fb,vga"),
+            Err(ConfigErrorKind::InvalidConsole)
+        );
+    }
+
+    #[test]
+    fn parse_file() {
+        let config = Config::parse(
+            b"# comment
+kernel_stack_address=0xFFFFFF0100000000
+kernel_stack_size = 2M
+max_cpus=4
+physical_memory_offset=0xFFFF800000000000  # trailing comment
+console=serial, fb
+kernel_path=\"\\KERNEL.ELF\"
+resolution=800x600
+cmdline=\"log=debug # not a comment\"
+",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                kernel_stack_address: 0xFFFF_FF01_0000_0000,
+                kernel_stack_size: 512,
+                max_cpus: 4,
+                physical_memory_offset: 0xFFFF_8000_0000_0000,
+                console: Consoles {
+                    serial: true,
+                    framebuffer: true,
+                },
+                timeout: 3,
+                default_entry: None,
+                defaults: BootEntry {
+                    name: "default",
+                    kernel_path: "\\KERNEL.ELF",
+                    kernel_sha256: None,
+                    kernel_signature: None,
+                    resolution: Some((800, 600)),
+                    initramfs: None,
+                    cmdline: "log=debug # not a comment",
+                },
+                entries: ArrayVec::new(),
+            }
+        );
+        assert_eq!(config.entries(), core::slice::from_ref(&config.defaults));
+        assert_eq!(Config::parse(b"").unwrap(), DEFAULT_CONFIG);
+    }
+
+    #[test]
+    fn parse_entries() {
+        let config = Config::parse(
+            b"timeout=5
+default=debug
+resolution=800x600
+
+[release]
+kernel_path=\\KERNEL.ELF
+kernel_sha256=BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD
+kernel_signature=\\KERNEL.SIG
+
+[ debug ]
+kernel_path=\\KERNEL-DEBUG.ELF
+cmdline=log=trace
+resolution=1024x768
+",
+        )
+        .unwrap();
+        assert_eq!(config.timeout, 5);
+        assert_eq!(config.default_index(), Some(1));
+        assert_eq!(
+            config.entries(),
+            &[
+                BootEntry {
+                    name: "release",
+                    kernel_path: "\\KERNEL.ELF",
+                    kernel_sha256: parse_hex(
+                        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                    ),
+                    kernel_signature: Some("\\KERNEL.SIG"),
+                    resolution: Some((800, 600)),
+                    initramfs: None,
+                    cmdline: "",
+                },
+                BootEntry {
+                    name: "debug",
+                    kernel_path: "\\KERNEL-DEBUG.ELF",
+                    kernel_sha256: None,
+                    kernel_signature: None,
+                    resolution: Some((1024, 768)),
+                    initramfs: None,
+                    cmdline: "log=trace",
+                },
+            ]
+        );
+
+        let err = |content: &[u8]| Config::parse(content).unwrap_err();
+        assert_eq!(
+            err(b"[a]\n[a]"),
+            ConfigError::new(2, 1, ConfigErrorKind::DuplicateEntry)
+        );
+        assert_eq!(
+            err(b"[a\n"),
+            ConfigError::new(1, 1, ConfigErrorKind::InvalidEntryName)
+        );
+        assert_eq!(
+            err(b"[ ]"),
+            ConfigError::new(1, 1, ConfigErrorKind::InvalidEntryName)
+        );
+        assert_eq!(
+            err(b"[a]\nmax_cpus=2"),
+            ConfigError::new(2, 1, ConfigErrorKind::GlobalKeyInEntry)
+        );
+        assert_eq!(
+            err(b"default=b\n[a]"),
+            ConfigError::new(1, 9, ConfigErrorKind::UnknownEntry)
+        );
+        assert_eq!(
+            err(b"[1]\n[2]\n[3]\n[4]\n[5]\n[6]\n[7]\n[8]\n[9]\n[10]"),
+            ConfigError::new(10, 1, ConfigErrorKind::TooManyEntries)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |content: &[u8]| Config::parse(content).unwrap_err();
+        assert_eq!(
+            err(b"\nkernel_stack_size"),
+            ConfigError::new(2, 1, ConfigErrorKind::MissingEquals)
+        );
+        assert_eq!(
+            err(b"  foo=1"),
+            ConfigError::new(1, 3, ConfigErrorKind::UnknownKey)
+        );
+        assert_eq!(
+            err(b"kernel_stack_size=0x"),
+            ConfigError::new(1, 19, ConfigErrorKind::InvalidNumber)
+        );
+        assert_eq!(
+            err(b"kernel_stack_address=0x1001"),
+            ConfigError::new(1, 22, ConfigErrorKind::Misaligned)
+        );
+        assert_eq!(
+            err(b"physical_memory_offset=0x1000000000000000"),
+            ConfigError::new(1, 24, ConfigErrorKind::NonCanonical)
+        );
+        assert_eq!(
+            err(b"kernel_path=\"\\KERNEL.ELF"),
+            ConfigError::new(1, 13, ConfigErrorKind::UnterminatedQuote)
+        );
+        assert_eq!(
+            err(b"kernel_path=\"a\" b"),
+            ConfigError::new(1, 17, ConfigErrorKind::TrailingCharacters)
+        );
+        assert_eq!(
+            err(b"kernel_stack_size=0"),
+            ConfigError::new(1, 19, ConfigErrorKind::Zero)
+        );
+        assert_eq!(
+            err(b"max_cpus=1000"),
+            ConfigError::new(1, 10, ConfigErrorKind::OutOfRange)
+        );
+        assert_eq!(
+            err(b"console=serial,"),
+            ConfigError::new(1, 9, ConfigErrorKind::InvalidConsole)
+        );
+        assert_eq!(
+            err(b"kernel_sha256=abc"),
+            ConfigError::new(1, 15, ConfigErrorKind::InvalidDigest)
+        );
+        assert_eq!(err(b"=1").kind, ConfigErrorKind::EmptyKey);
+        assert_eq!(err(&[0xFF]).kind, ConfigErrorKind::InvalidUtf8);
+        assert_eq!(
+            err(b"resolution=1x").to_string(),
+            "config error at line 1, column 12: invalid resolution, expected `<width>x<height>`"
+        );
+    }
+
+    #[test]
+    fn validate_layout() {
+        let mut config = DEFAULT_CONFIG;
+        assert_eq!(config.validate(0x1_0000_0000), Ok(()));
+        config.kernel_stack_address = 0xFFFF_8000_1000_0000;
+        assert_eq!(
+            config.validate(0x1_0000_0000),
+            Err(ConfigError::new(
+                0,
+                0,
+                ConfigErrorKind::StackOverlapsPhysicalMemory
+            ))
+        );
+        // the stack (and its guard page) ends right before the physical memory mapping
+        config.kernel_stack_address = 0xFFFF_8000_0000_0000 - 513 * 0x1000;
+        assert_eq!(config.validate(0x1_0000_0000), Ok(()));
+        // stacks of other processors must be checked as well
+        config.max_cpus = 2;
+        assert_eq!(
+            config.validate(0x1_0000_0000).unwrap_err().kind,
+            ConfigErrorKind::StackOverlapsPhysicalMemory
+        );
+    }
+
+    #[test]
+    fn cpu_stacks() {
+        let config = DEFAULT_CONFIG;
+        assert_eq!(config.stack_address(0), 0xFFFF_FF01_0000_0000);
+        assert_eq!(config.stack_top(0), 0xFFFF_FF01_0020_0000);
+        assert_eq!(config.stack_address(1), 0xFFFF_FF01_0020_1000);
+        assert_eq!(config.stack_top(1), 0xFFFF_FF01_0040_1000);
+    }
 }
@@ -1,6 +1,9 @@
 #![no_std]
 #![deny(warnings)]
 
+#[cfg(test)]
+extern crate std;
+
 use core::fmt;
 pub use uefi::data_types::chars::*;
 pub use uefi::data_types::*;
@@ -11,6 +14,10 @@ pub use uefi::table::runtime::*;
 pub use uefi::table::Runtime;
 pub use uefi::Status as UefiStatus;
 
+pub mod config;
+pub mod serial;
+pub mod verify;
+
 /// This structure represents the information that the bootloader passes to the kernel.
 #[repr(C)]
 pub struct BootInfo {
@@ -25,6 +32,49 @@ pub struct BootInfo {
 
     /// UEFI SystemTable
     pub system_table: SystemTable<Runtime>,
+
+    /// Physical address of ACPI RSDP
+    pub acpi_rsdp_addr: u64,
+
+    /// Physical address of SMBIOS entry point, zero if not present
+    pub smbios_addr: u64,
+
+    /// Processors to be used by the kernel, the first one is the BSP
+    pub cpus: arrayvec::ArrayVec<CpuInfo, MAX_CPUS>,
+
+    /// Physical address of a page below 1MiB reserved for the AP startup
+    /// trampoline, zero if there is no application processor
+    pub ap_trampoline_addr: u64,
+
+    /// Consoles the kernel should output to
+    pub console: config::Consoles,
+
+    /// Kernel command line of the selected boot entry
+    pub cmdline: arrayvec::ArrayString<MAX_CMDLINE_LEN>,
+
+    /// Physical address of the initramfs, zero if not present
+    pub initramfs_addr: u64,
+
+    /// Size of the initramfs in bytes
+    pub initramfs_size: u64,
+}
+
+/// The maximum length of the kernel command line
+pub const MAX_CMDLINE_LEN: usize = 256;
+
+/// The maximum number of processors supported
+pub const MAX_CPUS: usize = 64;
+
+/// Processor information
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct CpuInfo {
+    /// Local APIC ID
+    pub apic_id: u32,
+    /// Whether this is the bootstrap processor
+    pub is_bsp: bool,
+    /// Top of the kernel stack mapped for this processor
+    pub stack_top: u64,
 }
 
 pub struct MemoryMap {
@@ -0,0 +1,83 @@
+//! Logger, allocator and panic handler of the bootloader
+//!
+//! Messages go to the UEFI console until boot services are exited, and also to
+//! COM1 once serial output is enabled by `console=serial`, so that they can be
+//! followed in a headless QEMU, including a panic after `ExitBootServices`.
+
+use boot::serial::{SerialPort, COM1};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use log::{LevelFilter, Log, Metadata, Record};
+use uefi::proto::console::text::Output;
+use uefi::table::{Boot, SystemTable};
+
+#[global_allocator]
+static ALLOCATOR: uefi::alloc::Allocator = uefi::alloc::Allocator;
+
+static LOGGER: BootLogger = BootLogger;
+
+/// The UEFI console, null after boot services are exited
+static STDOUT: AtomicPtr<Output<'static>> = AtomicPtr::new(ptr::null_mut());
+/// Whether messages are also written to COM1
+static SERIAL: AtomicBool = AtomicBool::new(false);
+
+struct BootLogger;
+
+impl Log for BootLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        // the UEFI boot environment only uses one processor
+        if let Some(stdout) = unsafe { STDOUT.load(Ordering::Relaxed).as_mut() } {
+            let _ = writeln!(stdout, "[{:>5}]: {}", record.level(), record.args());
+        }
+        if SERIAL.load(Ordering::Relaxed) {
+            let mut serial = unsafe { SerialPort::new(COM1) };
+            let _ = writeln!(serial, "[{:>5}]: {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Set up logging to the UEFI console and memory allocation from boot services
+///
+/// # Safety
+///
+/// [`exit_boot_services`] must be called once boot services are exited.
+pub unsafe fn init(st: &mut SystemTable<Boot>) {
+    uefi::alloc::init(st.boot_services());
+    STDOUT.store(st.stdout() as *mut Output as *mut _, Ordering::Relaxed);
+    log::set_logger(&LOGGER).expect("logger already set");
+    log::set_max_level(LevelFilter::Info);
+}
+
+/// Also write messages to COM1 from now on
+pub fn enable_serial() {
+    let mut serial = unsafe { SerialPort::new(COM1) };
+    serial.init();
+    SERIAL.store(true, Ordering::Relaxed);
+}
+
+/// Stop using boot services, only the serial port is left for messages
+pub fn exit_boot_services() {
+    STDOUT.store(ptr::null_mut(), Ordering::Relaxed);
+    uefi::alloc::exit_boot_services();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: core::alloc::Layout) -> ! {
+    panic!("allocation error: {:?}", layout)
+}
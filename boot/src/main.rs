@@ -1,15 +1,16 @@
 //! Simple ELF OS Loader on UEFI
 //!
 //! 1. Load config from "\EFI\Boot\rboot.conf"
-//! 2. Load kernel ELF file
+//! 2. Select a boot entry from the menu, then load and verify its kernel ELF file
 //! 3. Map ELF segments to virtual memory
-//! 4. Map kernel stack and all physical memory
-//! 5. Startup all processors
+//! 4. Map kernel stacks of all processors and all physical memory
+//! 5. Collect processors and reserve a page for the AP startup trampoline
 //! 6. Exit boot and jump to ELF entry
 
 #![no_std]
 #![no_main]
 #![feature(abi_efiapi)]
+#![feature(alloc_error_handler)]
 #![deny(warnings)]
 
 #[macro_use]
@@ -19,65 +20,110 @@ extern crate log;
 
 extern crate rlibc;
 
+mod logger;
+
 use alloc::boxed::Box;
-use boot::{BootInfo, GraphicInfo, MemoryMap};
+use arrayvec::{ArrayString, ArrayVec};
+use boot::config::{BootEntry, Config, ConfigError};
+use boot::verify;
+use boot::{BootInfo, CpuInfo, GraphicInfo, MemoryMap, MAX_CPUS};
+use core::fmt::Write;
 use uefi::prelude::*;
 use uefi::proto::console::gop::GraphicsOutput;
+use uefi::proto::console::text::{Key, ScanCode};
+use uefi::proto::loaded_image::LoadedImage;
 use uefi::proto::media::file::*;
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::proto::pi::mp::MpServices;
 use uefi::table::boot::*;
-use uefi::table::cfg::{ACPI2_GUID, SMBIOS_GUID};
+use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID, SMBIOS_GUID};
 use x86_64::registers::control::*;
 use x86_64::structures::paging::*;
 use x86_64::{PhysAddr, VirtAddr};
 use xmas_elf::ElfFile;
 
-mod config;
-
 const CONFIG_PATH: &str = "\\EFI\\BOOT\\rboot.conf";
 
 #[entry]
 fn efi_main(image: uefi::Handle, mut st: SystemTable<Boot>) -> Status {
-    // Initialize utilities (logging, memory allocation...)
-    uefi_services::init(&mut st).expect("failed to initialize utilities");
+    // Initialize logging and memory allocation
+    unsafe { logger::init(&mut st) };
 
     info!("bootloader is running");
     let bs = st.boot_services();
     let config = {
-        let mut file = open_file(bs, CONFIG_PATH);
+        let mut file = open_file(bs, image, CONFIG_PATH);
         let buf = load_file(bs, &mut file);
-        config::Config::parse(buf)
+        Config::parse(buf).unwrap_or_else(config_error)
     };
-
-    let graphic_info = init_graphic(bs, config.resolution);
     info!("config: {:#x?}", config);
 
-    let acpi2_addr = st
+    if config.console.serial {
+        logger::enable_serial();
+    }
+
+    let entry = select_entry(unsafe { st.unsafe_clone() }, &config);
+    info!("boot entry: {}", entry.name);
+
+    let graphic_info = init_graphic(bs, entry.resolution);
+
+    // prefer ACPI 2.0 RSDP (with XSDT), fallback to ACPI 1.0 RSDP
+    let acpi_addr = st
         .config_table()
         .iter()
         .find(|entry| entry.guid == ACPI2_GUID)
-        .expect("failed to find ACPI 2 RSDP")
+        .or_else(|| {
+            st.config_table()
+                .iter()
+                .find(|entry| entry.guid == ACPI_GUID)
+        })
+        .expect("failed to find ACPI RSDP")
         .address;
-    info!("acpi2: {:?}", acpi2_addr);
+    info!("acpi: {:?}", acpi_addr);
 
     let smbios_addr = st
         .config_table()
         .iter()
         .find(|entry| entry.guid == SMBIOS_GUID)
-        .expect("failed to find SMBIOS")
-        .address;
+        .map(|entry| entry.address);
     info!("smbios: {:?}", smbios_addr);
 
     let elf = {
-        let mut file = open_file(bs, config.kernel_path);
+        let mut file = open_file(bs, image, entry.kernel_path);
         let buf = load_file(bs, &mut file);
+        verify_kernel(bs, image, entry, buf);
         ElfFile::new(buf).expect("failed to parse ELF")
     };
+    let initramfs = entry.initramfs.map(|path| {
+        let mut file = open_file(bs, image, path);
+        load_file(bs, &mut file)
+    });
+    let mut cmdline = ArrayString::new();
+    cmdline
+        .try_push_str(entry.cmdline)
+        .expect("kernel command line is too long");
     unsafe {
         ENTRY = elf.header.pt2.entry_point() as usize;
     }
 
+    let cpus = collect_cpus(bs, &config);
+    let ap_trampoline_addr = if cpus.len() > 1 {
+        // SIPI can only start processors at a 4KiB aligned address below 1MiB
+        bs.allocate_pages(
+            AllocateType::MaxAddress(0x9F000),
+            MemoryType::LOADER_CODE,
+            1,
+        )
+        .expect("failed to allocate AP trampoline")
+    } else {
+        0
+    };
+    info!(
+        "{} processors, trampoline at {:#x}",
+        cpus.len(),
+        ap_trampoline_addr
+    );
+
     let max_mmap_size = st.boot_services().memory_map_size();
     let mmap_storage = Box::leak(
         vec![0; max_mmap_size.map_size + 10 * max_mmap_size.entry_size].into_boxed_slice(),
@@ -92,6 +138,7 @@ fn efi_main(image: uefi::Handle, mut st: SystemTable<Boot>) -> Status {
         .max()
         .unwrap()
         .max(0x1_0000_0000); // include IOAPIC MMIO area
+    config.validate(max_phys_addr).unwrap_or_else(config_error);
 
     let mut page_table = current_page_table();
     // root page table is readonly
@@ -100,15 +147,26 @@ fn efi_main(image: uefi::Handle, mut st: SystemTable<Boot>) -> Status {
         Cr0::update(|f| f.remove(Cr0Flags::WRITE_PROTECT));
         Efer::update(|f| f.insert(EferFlags::NO_EXECUTE_ENABLE));
     }
-    elf_loader::map_elf(&elf, &mut page_table, &mut UEFIFrameAllocator(bs))
-        .expect("failed to map ELF");
-    elf_loader::map_stack(
-        config.kernel_stack_address,
-        config.kernel_stack_size,
+    // UEFI identity-maps physical memory
+    elf_loader::map_elf(
+        &elf,
+        PhysAddr::new(elf.input.as_ptr() as u64),
+        0,
         &mut page_table,
         &mut UEFIFrameAllocator(bs),
+        false,
     )
-    .expect("failed to map stack");
+    .expect("failed to map ELF");
+    for cpu in 0..cpus.len() {
+        elf_loader::map_stack(
+            config.stack_address(cpu),
+            config.kernel_stack_size,
+            &mut page_table,
+            &mut UEFIFrameAllocator(bs),
+            false,
+        )
+        .expect("failed to map stack");
+    }
     elf_loader::map_physical_memory(
         config.physical_memory_offset,
         max_phys_addr,
@@ -120,11 +178,6 @@ fn efi_main(image: uefi::Handle, mut st: SystemTable<Boot>) -> Status {
         Cr0::update(|f| f.insert(Cr0Flags::WRITE_PROTECT));
     }
 
-    // FIXME: multi-core
-    //  All application processors will be shutdown after ExitBootService.
-    //  Disable now.
-    // start_aps(bs);
-
     let mmap_iter = st
         .boot_services()
         .memory_map(mmap_storage)
@@ -138,7 +191,9 @@ fn efi_main(image: uefi::Handle, mut st: SystemTable<Boot>) -> Status {
     let (rt, _mmap_iter) = st
         .exit_boot_services(image, mmap_storage)
         .expect("Failed to exit boot services");
-    // NOTE: alloc & log can no longer be used
+    // NOTE: alloc can no longer be used, log only reaches the serial port
+    logger::exit_boot_services();
+    info!("booting {}", entry.kernel_path);
 
     // construct BootInfo
     let bootinfo = BootInfo {
@@ -146,19 +201,143 @@ fn efi_main(image: uefi::Handle, mut st: SystemTable<Boot>) -> Status {
         physical_memory_offset: config.physical_memory_offset,
         graphic_info,
         system_table: rt,
+        acpi_rsdp_addr: acpi_addr as u64,
+        smbios_addr: smbios_addr.map_or(0, |addr| addr as u64),
+        cpus,
+        ap_trampoline_addr,
+        console: config.console,
+        cmdline,
+        initramfs_addr: initramfs.as_ref().map_or(0, |buf| buf.as_ptr() as u64),
+        initramfs_size: initramfs.as_ref().map_or(0, |buf| buf.len() as u64),
     };
-    let stacktop = config.kernel_stack_address + config.kernel_stack_size * 0x1000;
+    let stacktop = config.stack_top(0);
     unsafe {
         jump_to_entry(&bootinfo, stacktop);
     }
 }
 
-/// Open file at `path`
-fn open_file(bs: &BootServices, path: &str) -> RegularFile {
+/// Report the config error on the UEFI console and halt
+fn config_error(err: ConfigError) -> ! {
+    error!("{}", err);
+    error!("please fix {} and reboot", CONFIG_PATH);
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Check the kernel image against the digest and signature of `entry`,
+/// halt if verification fails
+///
+/// With a public key embedded, the signature is required whatever the config
+/// says.
+fn verify_kernel(bs: &BootServices, image: Handle, entry: &BootEntry, kernel: &[u8]) {
+    if verify::EMBEDDED_PUBLIC_KEY.is_none()
+        && entry.kernel_sha256.is_none()
+        && entry.kernel_signature.is_none()
+    {
+        return;
+    }
+    let signature = entry.kernel_signature.map(|path| {
+        let mut file = open_file(bs, image, path);
+        &*load_file(bs, &mut file)
+    });
+    let result = verify::embedded_public_key().and_then(|key| {
+        verify::verify_kernel(
+            kernel,
+            entry.kernel_sha256.as_ref(),
+            signature,
+            key.as_ref(),
+        )
+    });
+    match result {
+        Ok(()) => info!("kernel {} verified", entry.kernel_path),
+        Err(err) => {
+            error!("kernel {} rejected: {}", entry.kernel_path, err);
+            error!("refusing to boot entry {}", entry.name);
+            loop {
+                x86_64::instructions::hlt();
+            }
+        }
+    }
+}
+
+/// Let the user choose a boot entry on the UEFI console.
+///
+/// The default entry is booted when `timeout` seconds pass without a key press.
+/// The menu is skipped if there is only one entry or the timeout is zero.
+fn select_entry<'a>(mut st: SystemTable<Boot>, config: &'a Config<'a>) -> &'a BootEntry<'a> {
+    let entries = config.entries();
+    let mut selected = config.default_index().unwrap_or_else(|| {
+        warn!("default entry not found, boot the first one");
+        0
+    });
+    if entries.len() == 1 || config.timeout == 0 {
+        return &entries[selected];
+    }
+
+    let _ = writeln!(st.stdout(), "rboot menu:");
+    for (i, entry) in entries.iter().enumerate() {
+        let _ = writeln!(st.stdout(), "  {}. {}", i + 1, entry.name);
+    }
+
+    // count down in ticks of 100ms, stopped by any key
+    let mut ticks = Some(config.timeout * 10);
+    loop {
+        if let Some(remain) = ticks {
+            if remain == 0 {
+                break;
+            }
+            if remain % 10 == 0 {
+                let _ = write!(
+                    st.stdout(),
+                    "\rboot {} in {}s... ",
+                    entries[selected].name,
+                    remain / 10
+                );
+            }
+            ticks = Some(remain - 1);
+        }
+        match st.stdin().read_key().expect("failed to read key") {
+            Some(Key::Printable(c)) => {
+                let c = char::from(c);
+                if c == '\r' {
+                    break;
+                }
+                match c.to_digit(10).map(|d| d as usize) {
+                    Some(n) if (1..=entries.len()).contains(&n) => selected = n - 1,
+                    _ => (),
+                }
+            }
+            Some(Key::Special(ScanCode::UP)) => {
+                selected = (selected + entries.len() - 1) % entries.len();
+            }
+            Some(Key::Special(ScanCode::DOWN)) => selected = (selected + 1) % entries.len(),
+            Some(Key::Special(_)) => (),
+            None => {
+                st.boot_services().stall(100_000);
+                continue;
+            }
+        }
+        ticks = None;
+        let _ = write!(
+            st.stdout(),
+            "\rboot {}? press Enter  ",
+            entries[selected].name
+        );
+    }
+    let _ = writeln!(st.stdout());
+    &entries[selected]
+}
+
+/// Open file at `path` on the device this image is loaded from
+fn open_file(bs: &BootServices, image: Handle, path: &str) -> RegularFile {
     info!("opening file: {}", path);
-    // FIXME: use LoadedImageProtocol to get the FileSystem of this image
+    let loaded_image = bs
+        .handle_protocol::<LoadedImage>(image)
+        .expect("failed to get LoadedImage");
+    let device = unsafe { &*loaded_image.get() }.device();
     let fs = bs
-        .locate_protocol::<SimpleFileSystem>()
+        .handle_protocol::<SimpleFileSystem>(device)
         .expect("failed to get FileSystem");
     let fs = unsafe { &mut *fs.get() };
 
@@ -247,57 +426,48 @@ unsafe impl FrameAllocator<Size4KiB> for UEFIFrameAllocator<'_> {
     }
 }
 
-/// Startup all application processors
-#[allow(dead_code)]
-fn start_aps(bs: &BootServices) {
-    info!("starting application processors");
-    let mp = bs
-        .locate_protocol::<MpServices>()
-        .expect("failed to get MpServices");
-    let mp = mp.get();
-
-    // this event will never be signaled
-    let event = unsafe {
-        bs.create_event(EventType::empty(), Tpl::APPLICATION, None, None)
-            .expect("failed to create event")
+/// Collect at most `config.max_cpus` enabled processors, the BSP comes first.
+///
+/// Application processors are shut down by the firmware after
+/// `ExitBootServices`, so they are not started here. Instead the kernel wakes
+/// them up with INIT-SIPI-SIPI through the reserved trampoline page.
+fn collect_cpus(bs: &BootServices, config: &Config) -> ArrayVec<CpuInfo, MAX_CPUS> {
+    let mut cpus = ArrayVec::new();
+    let mp = match bs.locate_protocol::<MpServices>() {
+        Ok(mp) => unsafe { &*mp.get() },
+        Err(_) => {
+            warn!("MpServices not found, assuming single processor");
+            // CPUID.01H:EBX[31:24] is the initial APIC ID
+            let apic_id = unsafe { core::arch::x86_64::__cpuid(1).ebx >> 24 };
+            cpus.push(CpuInfo {
+                apic_id,
+                is_bsp: true,
+                stack_top: config.stack_top(0),
+            });
+            return cpus;
+        }
     };
-
-    // workaround as uefi crate do not implement non-blocking call
-    use core::ffi::c_void;
-    use uefi::proto::pi::mp::Procedure;
-    type StartupAllAps = extern "efiapi" fn(
-        this: *const MpServices,
-        procedure: Procedure,
-        single_thread: bool,
-        wait_event: *mut c_void,
-        timeout_in_micro_seconds: usize,
-        procedure_argument: *mut c_void,
-        failed_cpu_list: *mut *mut usize,
-    ) -> Status;
-    let startup_all_aps = unsafe { *((mp as *const usize).add(2) as *const StartupAllAps) };
-    let event_ptr = unsafe { core::mem::transmute(event) };
-    let status = startup_all_aps(
-        mp,
-        ap_main,
-        false,
-        event_ptr,
-        0,
-        core::ptr::null_mut(),
-        core::ptr::null_mut(),
+    let count = mp
+        .get_number_of_processors()
+        .expect("failed to get number of processors");
+    info!(
+        "processors: total={} enabled={}",
+        count.total, count.enabled
     );
-    if !status.is_success() {
-        warn!(
-            "failed to startup all application processors with {:?}",
-            status
-        );
-    }
-}
 
-/// Main function for application processors
-extern "efiapi" fn ap_main(_arg: *mut core::ffi::c_void) {
-    unsafe {
-        jump_to_entry(core::ptr::null(), 0);
+    let infos = (0..count.total).filter_map(|i| mp.get_processor_info(i).ok());
+    // BSP first, then enabled APs
+    let bsp = infos.clone().find(|info| info.is_bsp());
+    let aps = infos.filter(|info| !info.is_bsp() && info.is_enabled());
+    for info in bsp.into_iter().chain(aps).take(config.max_cpus) {
+        let cpu = cpus.len();
+        cpus.push(CpuInfo {
+            apic_id: info.processor_id as u32,
+            is_bsp: info.is_bsp(),
+            stack_top: config.stack_top(cpu),
+        });
     }
+    cpus
 }
 
 /// Jump to ELF entry according to global variable `ENTRY`
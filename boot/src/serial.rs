@@ -0,0 +1,96 @@
+//! 16550 UART driver
+//!
+//! Used as an early console by both the bootloader and the kernel, since it
+//! does not depend on the framebuffer. Under QEMU, `-serial stdio` connects
+//! COM1 to the terminal.
+//!
+//! Reference: [OSDev Wiki](https://wiki.osdev.org/Serial_Ports)
+
+use core::fmt;
+use x86_64::instructions::port::Port;
+
+/// I/O port base of COM1
+pub const COM1: u16 = 0x3F8;
+
+/// Line status: data ready
+const LSR_DATA_READY: u8 = 1 << 0;
+/// Line status: transmitter holding register empty
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+#[derive(Debug)]
+pub struct SerialPort {
+    data: Port<u8>,
+    int_en: Port<u8>,
+    fifo_ctrl: Port<u8>,
+    line_ctrl: Port<u8>,
+    modem_ctrl: Port<u8>,
+    line_status: Port<u8>,
+}
+
+impl SerialPort {
+    /// Create a port at I/O `base` without touching the hardware.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the base of a 16550 compatible UART.
+    pub unsafe fn new(base: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            int_en: Port::new(base + 1),
+            fifo_ctrl: Port::new(base + 2),
+            line_ctrl: Port::new(base + 3),
+            modem_ctrl: Port::new(base + 4),
+            line_status: Port::new(base + 5),
+        }
+    }
+
+    /// Set 115200 baud, 8N1, enable FIFO and disable interrupts
+    pub fn init(&mut self) {
+        unsafe {
+            self.int_en.write(0x00);
+            // enable DLAB to set the baud rate divisor
+            self.line_ctrl.write(0x80);
+            self.data.write(0x01);
+            self.int_en.write(0x00);
+            // 8 bits, no parity, one stop bit, DLAB off
+            self.line_ctrl.write(0x03);
+            // enable and clear FIFO, 14 bytes threshold
+            self.fifo_ctrl.write(0xC7);
+            // DTR, RTS and OUT2
+            self.modem_ctrl.write(0x0B);
+        }
+    }
+
+    /// Send a byte, waiting for the transmitter to be ready
+    pub fn send(&mut self, byte: u8) {
+        unsafe {
+            while self.line_status.read() & LSR_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.data.write(byte);
+        }
+    }
+
+    /// Receive a byte if there is one
+    pub fn receive(&mut self) -> Option<u8> {
+        unsafe {
+            if self.line_status.read() & LSR_DATA_READY != 0 {
+                Some(self.data.read())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! Kernel image verification
+//!
+//! An entry of `rboot.conf` may pin the kernel with `kernel_sha256`, and/or
+//! require a detached Ed25519 signature with `kernel_signature`. Signatures
+//! are checked against the public key embedded into the bootloader at build
+//! time through the `RBOOT_PUBLIC_KEY` environment variable (64 hex digits).
+//! Once a key is embedded, every kernel must carry a valid signature, so
+//! editing the config cannot turn verification off.
+
+use core::fmt;
+use ed25519_compact::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+
+/// Hex encoded Ed25519 public key embedded at build time
+pub const EMBEDDED_PUBLIC_KEY: Option<&str> = option_env!("RBOOT_PUBLIC_KEY");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The SHA-256 digest of the image differs from the expected one
+    DigestMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+    /// A signature is required but no public key is embedded
+    NoPublicKey,
+    /// A public key is embedded but the entry has no signature
+    MissingSignature,
+    /// The embedded public key is malformed
+    InvalidPublicKey,
+    /// The signature file is neither 64 raw bytes nor 128 hex digits
+    InvalidSignature,
+    /// The signature does not match the image
+    BadSignature,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::DigestMismatch { expected, actual } => write!(
+                f,
+                "SHA-256 mismatch, expected {} but got {}",
+                Hex(expected),
+                Hex(actual)
+            ),
+            VerifyError::NoPublicKey => {
+                f.write_str("signature required, but no public key is embedded")
+            }
+            VerifyError::MissingSignature => {
+                f.write_str("a public key is embedded, but the kernel is not signed")
+            }
+            VerifyError::InvalidPublicKey => f.write_str("embedded public key is invalid"),
+            VerifyError::InvalidSignature => f.write_str("malformed signature file"),
+            VerifyError::BadSignature => f.write_str("signature verification failed"),
+        }
+    }
+}
+
+/// Display bytes as lowercase hex digits
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse exactly `2 * N` hex digits
+pub fn parse_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    let digits = value.as_bytes();
+    if digits.len() != 2 * N {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+        let hi = (pair[0] as char).to_digit(16)?;
+        let lo = (pair[1] as char).to_digit(16)?;
+        *byte = (hi << 4 | lo) as u8;
+    }
+    Some(bytes)
+}
+
+/// Parse a detached signature given in raw bytes or hex digits
+fn parse_signature(data: &[u8]) -> Result<Signature, VerifyError> {
+    if data.len() == Signature::BYTES {
+        return Signature::from_slice(data).map_err(|_| VerifyError::InvalidSignature);
+    }
+    core::str::from_utf8(data)
+        .ok()
+        .and_then(|s| parse_hex::<{ Signature::BYTES }>(s.trim()))
+        .map(Signature::new)
+        .ok_or(VerifyError::InvalidSignature)
+}
+
+/// The public key embedded into the bootloader, if any
+pub fn embedded_public_key() -> Result<Option<[u8; 32]>, VerifyError> {
+    EMBEDDED_PUBLIC_KEY
+        .map(|key| parse_hex(key.trim()).ok_or(VerifyError::InvalidPublicKey))
+        .transpose()
+}
+
+/// Check `image` against the expected SHA-256 digest and detached signature.
+///
+/// The digest is skipped if `sha256` is `None`. The signature is required
+/// whenever `public_key` is given, and skipped otherwise only if `signature`
+/// is `None`.
+pub fn verify_kernel(
+    image: &[u8],
+    sha256: Option<&[u8; 32]>,
+    signature: Option<&[u8]>,
+    public_key: Option<&[u8; 32]>,
+) -> Result<(), VerifyError> {
+    if let Some(expected) = sha256 {
+        let mut actual = [0; 32];
+        actual.copy_from_slice(&Sha256::digest(image));
+        if &actual != expected {
+            return Err(VerifyError::DigestMismatch {
+                expected: *expected,
+                actual,
+            });
+        }
+    }
+    if public_key.is_some() && signature.is_none() {
+        return Err(VerifyError::MissingSignature);
+    }
+    if let Some(signature) = signature {
+        let public_key = PublicKey::new(*public_key.ok_or(VerifyError::NoPublicKey)?);
+        let signature = parse_signature(signature)?;
+        public_key
+            .verify(image, &signature)
+            .map_err(|_| VerifyError::BadSignature)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    // RFC 8032, section 7.1, test 1 (empty message)
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex::<2>("0aFf"), Some([0x0a, 0xff]));
+        assert_eq!(parse_hex::<2>("0aF"), None);
+        assert_eq!(parse_hex::<2>("0aFf0"), None);
+        assert_eq!(parse_hex::<2>("0aFg"), None);
+        assert_eq!(parse_hex::<1>("+f"), None);
+    }
+
+    #[test]
+    fn sha256() {
+        let digest = parse_hex(ABC_SHA256).unwrap();
+        assert_eq!(verify_kernel(b"abc", Some(&digest), None, None), Ok(()));
+        assert!(matches!(
+            verify_kernel(b"abd", Some(&digest), None, None),
+            Err(VerifyError::DigestMismatch { expected, .. }) if expected == digest
+        ));
+        assert_eq!(verify_kernel(b"anything", None, None, None), Ok(()));
+    }
+
+    #[test]
+    fn signature() {
+        let key = parse_hex(PUBLIC_KEY).unwrap();
+        let raw: [u8; 64] = parse_hex(SIGNATURE).unwrap();
+        let hex = std::format!("{}\n", SIGNATURE);
+
+        assert_eq!(verify_kernel(b"", None, Some(&raw), Some(&key)), Ok(()));
+        assert_eq!(
+            verify_kernel(b"", None, Some(hex.as_bytes()), Some(&key)),
+            Ok(())
+        );
+        assert_eq!(
+            verify_kernel(b"x", None, Some(&raw), Some(&key)),
+            Err(VerifyError::BadSignature)
+        );
+        assert_eq!(
+            verify_kernel(b"", None, Some(&raw[..63]), Some(&key)),
+            Err(VerifyError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_kernel(b"", None, Some(&raw), None),
+            Err(VerifyError::NoPublicKey)
+        );
+        // an embedded key cannot be bypassed by leaving out the signature
+        assert_eq!(
+            verify_kernel(b"", None, None, Some(&key)),
+            Err(VerifyError::MissingSignature)
+        );
+        let digest = parse_hex(ABC_SHA256).unwrap();
+        assert_eq!(
+            verify_kernel(b"abc", Some(&digest), None, Some(&key)),
+            Err(VerifyError::MissingSignature)
+        );
+    }
+}
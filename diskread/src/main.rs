@@ -12,8 +12,8 @@ use sys_device::SysDevice;
 
 mod sys_device;
 
-#[export_name = "_start"]
-pub extern "C" fn __impl_start() -> ! {
+#[no_mangle]
+pub fn main() -> i32 {
     println!("DISK DEBUGGER");
     let dev = SysDevice;
 
@@ -83,5 +83,5 @@ pub extern "C" fn __impl_start() -> ! {
         println!("invalid id.");
     }
 
-    xlibr::sys_exit()
+    0
 }
@@ -12,7 +12,7 @@ impl Device for SysDevice {
         size: usize,
         buf: &mut [u8],
     ) -> Result<(), fatpart::BlockError> {
-        xlibr::sys_read_disk(offset as u64, buf);
-        Ok(())
+        xlibr::sys_read_disk(offset as u64, buf)
+            .map_err(|errno| fatpart::BlockError::WithStatus(errno as usize))
     }
 }
@@ -13,23 +13,40 @@ use xmas_elf::{program, ElfFile};
 ///
 /// 遍历 ELF 的每个段，然后将代码加载到新的帧，并设置当前的页表
 /// 不对 ELF 文件的加载地址做出假设
+///
+/// `kernel_start` 为 ELF 文件所在的物理地址，文件需要占据连续的物理帧；
+/// 物理内存在当前地址空间中映射到 `physical_offset`，用于读写新分配的帧
+///
+/// `user_access` 为真时页面对用户态可见，用于加载用户程序
 pub fn map_elf(
     elf: &ElfFile,
+    kernel_start: PhysAddr,
+    physical_offset: u64,
     page_table: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    user_access: bool,
 ) -> Result<(), MapToError<Size4KiB>> {
     debug!("mapping ELF");
-    let kernel_start = PhysAddr::new(elf.input.as_ptr() as u64);
     for segment in elf.program_iter() {
-        map_segment(&segment, kernel_start, page_table, frame_allocator)?;
+        map_segment(
+            &segment,
+            kernel_start,
+            physical_offset,
+            page_table,
+            frame_allocator,
+            user_access,
+        )?;
     }
     Ok(())
 }
 
-/// 卸载 ELF 文件
-pub fn unmap_elf(elf: &ElfFile, page_table: &mut impl Mapper<Size4KiB>) -> Result<(), UnmapError> {
+/// 卸载 ELF 文件，`kernel_start` 与 [`map_elf`] 相同
+pub fn unmap_elf(
+    elf: &ElfFile,
+    kernel_start: PhysAddr,
+    page_table: &mut impl Mapper<Size4KiB>,
+) -> Result<(), UnmapError> {
     debug!("unmapping ELF");
-    let kernel_start = PhysAddr::new(elf.input.as_ptr() as u64);
     for segment in elf.program_iter() {
         unmap_segment(&segment, kernel_start, page_table)?;
     }
@@ -42,13 +59,17 @@ pub fn map_stack(
     pages: u64,
     page_table: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    user_access: bool,
 ) -> Result<(), MapToError<Size4KiB>> {
     debug!("mapping stack at {:#x}", addr);
     // create a stack
     let stack_start = Page::containing_address(VirtAddr::new(addr));
     let stack_end = stack_start + pages;
 
-    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    if user_access {
+        flags |= PageTableFlags::USER_ACCESSIBLE;
+    }
 
     for page in Page::range(stack_start, stack_end) {
         let frame = frame_allocator
@@ -67,8 +88,10 @@ pub fn map_stack(
 fn map_segment(
     segment: &program::ProgramHeader,
     kernel_start: PhysAddr,
+    physical_offset: u64,
     page_table: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    user_access: bool,
 ) -> Result<(), MapToError<Size4KiB>> {
     if segment.get_type().unwrap() != program::Type::Load {
         return Ok(());
@@ -92,6 +115,9 @@ fn map_segment(
     if flags.is_write() {
         page_table_flags |= PageTableFlags::WRITABLE
     };
+    if user_access {
+        page_table_flags |= PageTableFlags::USER_ACCESSIBLE
+    };
 
     for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
         let offset = frame - start_frame;
@@ -119,12 +145,23 @@ fn map_segment(
             type PageArray = [u64; Size4KiB::SIZE as usize / 8];
 
             let last_page = Page::containing_address(virt_start_addr + file_size - 1u64);
-            let last_page_ptr = end_frame.start_address().as_u64() as *mut PageArray;
-            let temp_page_ptr = new_frame.start_address().as_u64() as *mut PageArray;
+            let last_page_ptr =
+                (physical_offset + end_frame.start_address().as_u64()) as *mut PageArray;
+            let temp_page_ptr =
+                (physical_offset + new_frame.start_address().as_u64()) as *mut PageArray;
 
             unsafe {
                 // copy contents
                 temp_page_ptr.write(last_page_ptr.read());
+                // zero the part after the file content, the page may not be
+                // mapped in the current address space
+                let offset = zero_start.as_u64() & 0xfff;
+                let len = (zero_end.as_u64() - zero_start.as_u64()).min(Size4KiB::SIZE - offset);
+                core::ptr::write_bytes(
+                    (temp_page_ptr as *mut u8).add(offset as usize),
+                    0,
+                    len as usize,
+                );
             }
 
             // remap last page
@@ -152,20 +189,17 @@ fn map_segment(
                 .allocate_frame()
                 .ok_or(MapToError::FrameAllocationFailed)?;
             unsafe {
+                // zero bss through the frame, like the copy above
+                core::ptr::write_bytes(
+                    (physical_offset + frame.start_address().as_u64()) as *mut u8,
+                    0,
+                    Size4KiB::SIZE as usize,
+                );
                 page_table
                     .map_to(page, frame, page_table_flags, frame_allocator)?
                     .flush();
             }
         }
-
-        // zero bss
-        unsafe {
-            core::ptr::write_bytes(
-                zero_start.as_mut_ptr::<u8>(),
-                0,
-                (mem_size - file_size) as usize,
-            );
-        }
     }
     Ok(())
 }
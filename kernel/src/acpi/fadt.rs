@@ -0,0 +1,99 @@
+//! Fixed ACPI Description Table
+//!
+//! Reference: ACPI Specification 6.4, Section 5.2.9
+
+use super::sdt::{read_u16, read_u32, read_u64, Sdt};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    /// DSDT 物理地址
+    pub dsdt_addr: u64,
+    pub sci_int: u16,
+    pub smi_cmd: u32,
+    pub acpi_enable: u8,
+    pub pm1a_cnt_blk: u32,
+    pub pm1b_cnt_blk: u32,
+    /// ACPI PM Timer 端口，为 0 表示不存在
+    pub pm_tmr_blk: u32,
+    pub pm_tmr_len: u8,
+    /// RTC CMOS 中世纪字段的索引，为 0 表示不存在
+    pub century: u8,
+    pub flags: u32,
+}
+
+/// PM1 Control 中的 SCI_EN 位
+pub const SCI_EN: u16 = 1 << 0;
+/// PM1 Control 中的 SLP_EN 位
+pub const SLP_EN: u16 = 1 << 13;
+/// FADT flags 中的 TMR_VAL_EXT 位，表示 PM Timer 为 32 位
+pub const TMR_VAL_EXT: u32 = 1 << 8;
+
+impl Fadt {
+    pub fn parse(sdt: &Sdt) -> Self {
+        let data = sdt.data();
+        // 字段偏移相对于表头之后
+        let field = |offset: usize| offset - super::sdt::SDT_HEADER_SIZE;
+        let get_u8 = |offset: usize| data.get(field(offset)).copied().unwrap_or(0);
+        let has = |offset: usize, size: usize| field(offset) + size <= data.len();
+
+        let mut dsdt_addr = read_u32(data, field(40)) as u64;
+        // ACPI 2.0+ 中优先使用 X_DSDT
+        if has(140, 8) {
+            let x_dsdt = read_u64(data, field(140));
+            if x_dsdt != 0 {
+                dsdt_addr = x_dsdt;
+            }
+        }
+
+        Fadt {
+            dsdt_addr,
+            sci_int: read_u16(data, field(46)),
+            smi_cmd: read_u32(data, field(48)),
+            acpi_enable: get_u8(52),
+            pm1a_cnt_blk: read_u32(data, field(64)),
+            pm1b_cnt_blk: read_u32(data, field(68)),
+            pm_tmr_blk: read_u32(data, field(76)),
+            pm_tmr_len: get_u8(91),
+            century: get_u8(108),
+            flags: if has(112, 4) {
+                read_u32(data, field(112))
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// 在 DSDT 的 AML 中查找 `\_S5` 对象，返回 `(SLP_TYPa, SLP_TYPb)`
+///
+/// 这里并不实现 AML 解释器，只按照 `Name(_S5, Package() {a, b, ...})`
+/// 的常见编码进行匹配
+pub fn find_s5(dsdt: &Sdt) -> Option<(u16, u16)> {
+    const NAME_OP: u8 = 0x08;
+    const PACKAGE_OP: u8 = 0x12;
+    const BYTE_PREFIX: u8 = 0x0A;
+
+    let aml = dsdt.data();
+    let pos = aml.windows(4).position(|w| w == b"_S5_")?;
+    let is_name = (pos >= 1 && aml[pos - 1] == NAME_OP)
+        || (pos >= 2 && aml[pos - 2] == NAME_OP && aml[pos - 1] == b'\\');
+    if !is_name || aml.get(pos + 4) != Some(&PACKAGE_OP) {
+        return None;
+    }
+
+    // 跳过 PkgLength 及 NumElements
+    let mut p = pos + 5;
+    p += ((*aml.get(p)? & 0xC0) >> 6) as usize + 2;
+
+    let mut read_value = || -> Option<u16> {
+        if *aml.get(p)? == BYTE_PREFIX {
+            p += 1;
+        }
+        let v = *aml.get(p)? as u16;
+        p += 1;
+        Some(v)
+    };
+    let slp_typa = read_value()?;
+    let slp_typb = read_value()?;
+    Some((slp_typa, slp_typb))
+}
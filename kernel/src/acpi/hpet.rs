@@ -0,0 +1,34 @@
+//! IA-PC HPET (High Precision Event Timers) Description Table
+//!
+//! Reference: IA-PC HPET Specification 1.0a, Section 3.2.4
+
+use super::sdt::{read_u16, read_u32, read_u64, Sdt};
+
+/// 表头之后至少包含到 `min_tick` 为止的字段
+const MIN_DATA_SIZE: usize = 19;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub event_timer_block_id: u32,
+    /// HPET 寄存器的物理地址
+    pub base_addr: u64,
+    pub hpet_number: u8,
+    pub min_tick: u16,
+}
+
+impl Hpet {
+    /// 解析 HPET 表，表过短时返回 `None`
+    pub fn parse(sdt: &Sdt) -> Option<Self> {
+        let data = sdt.data();
+        if data.len() < MIN_DATA_SIZE {
+            return None;
+        }
+        Some(Hpet {
+            event_timer_block_id: read_u32(data, 0),
+            // Generic Address Structure: space_id, bit_width, bit_offset, access_size, address
+            base_addr: read_u64(data, 8),
+            hpet_number: data[16],
+            min_tick: read_u16(data, 17),
+        })
+    }
+}
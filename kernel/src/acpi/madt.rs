@@ -0,0 +1,112 @@
+//! Multiple APIC Description Table
+//!
+//! Reference: ACPI Specification 6.4, Section 5.2.12
+
+use super::sdt::{read_u16, read_u32, read_u64, Sdt};
+
+/// 最多支持的处理器数量
+pub const MAX_CPUS: usize = 64;
+/// 最多记录的中断源重定向数量
+pub const MAX_OVERRIDES: usize = 16;
+
+/// ISA 中断到 GSI 的重定向
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IrqOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    /// 低电平有效
+    pub active_low: bool,
+    /// 电平触发
+    pub level_triggered: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    /// Local APIC 物理地址
+    pub lapic_addr: u64,
+    /// I/O APIC 物理地址（仅记录第一个 I/O APIC）
+    pub ioapic_addr: Option<u64>,
+    /// I/O APIC 负责的第一个 GSI
+    pub ioapic_gsi_base: u32,
+    /// 可用处理器的 APIC ID
+    pub cpus: [u8; MAX_CPUS],
+    pub cpu_count: usize,
+    pub overrides: [IrqOverride; MAX_OVERRIDES],
+    pub override_count: usize,
+}
+
+const ENTRY_LAPIC: u8 = 0;
+const ENTRY_IOAPIC: u8 = 1;
+const ENTRY_ISO: u8 = 2;
+const ENTRY_LAPIC_OVERRIDE: u8 = 5;
+
+impl Madt {
+    pub fn parse(sdt: &Sdt) -> Self {
+        let data = sdt.data();
+        let mut madt = Madt {
+            lapic_addr: read_u32(data, 0) as u64,
+            ioapic_addr: None,
+            ioapic_gsi_base: 0,
+            cpus: [0; MAX_CPUS],
+            cpu_count: 0,
+            overrides: [IrqOverride::default(); MAX_OVERRIDES],
+            override_count: 0,
+        };
+
+        // 跳过 Local APIC 地址和 flags
+        let mut offset = 8;
+        while offset + 2 <= data.len() {
+            let ty = data[offset];
+            let len = data[offset + 1] as usize;
+            if len < 2 || offset + len > data.len() {
+                warn!("malformed MADT entry at offset {}", offset);
+                break;
+            }
+            let entry = &data[offset..offset + len];
+            match ty {
+                ENTRY_LAPIC => {
+                    let apic_id = entry[3];
+                    let flags = read_u32(entry, 4);
+                    // bit 0: enabled, bit 1: online capable
+                    if flags & 0b11 != 0 && madt.cpu_count < MAX_CPUS {
+                        madt.cpus[madt.cpu_count] = apic_id;
+                        madt.cpu_count += 1;
+                    }
+                }
+                ENTRY_IOAPIC => {
+                    if madt.ioapic_addr.is_none() {
+                        madt.ioapic_addr = Some(read_u32(entry, 4) as u64);
+                        madt.ioapic_gsi_base = read_u32(entry, 8);
+                    }
+                }
+                ENTRY_ISO => {
+                    let flags = read_u16(entry, 8);
+                    if madt.override_count < MAX_OVERRIDES {
+                        madt.overrides[madt.override_count] = IrqOverride {
+                            irq: entry[3],
+                            gsi: read_u32(entry, 4),
+                            active_low: flags & 0b11 == 0b11,
+                            level_triggered: (flags >> 2) & 0b11 == 0b11,
+                        };
+                        madt.override_count += 1;
+                    }
+                }
+                ENTRY_LAPIC_OVERRIDE => {
+                    madt.lapic_addr = read_u64(entry, 4);
+                }
+                _ => (),
+            }
+            offset += len;
+        }
+
+        madt
+    }
+
+    pub fn cpus(&self) -> &[u8] {
+        &self.cpus[..self.cpu_count]
+    }
+
+    pub fn overrides(&self) -> &[IrqOverride] {
+        &self.overrides[..self.override_count]
+    }
+}
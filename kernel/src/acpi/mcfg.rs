@@ -0,0 +1,63 @@
+//! PCI Express Memory-mapped Configuration Space Base Address Description Table
+//!
+//! Reference: PCI Firmware Specification 3.0, Section 4.1.2
+
+use super::sdt::{read_u16, read_u64, Sdt};
+
+/// 最多记录的 PCI 段数量
+pub const MAX_SEGMENTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct McfgEntry {
+    /// ECAM 区域的物理地址
+    pub base_addr: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mcfg {
+    pub entries: [McfgEntry; MAX_SEGMENTS],
+    pub count: usize,
+}
+
+const ENTRY_SIZE: usize = 16;
+
+impl Mcfg {
+    pub fn parse(sdt: &Sdt) -> Self {
+        let data = sdt.data();
+        let mut mcfg = Mcfg {
+            entries: [McfgEntry::default(); MAX_SEGMENTS],
+            count: 0,
+        };
+        // 跳过 8 字节保留字段
+        for entry in data[8..].chunks_exact(ENTRY_SIZE).take(MAX_SEGMENTS) {
+            mcfg.entries[mcfg.count] = McfgEntry {
+                base_addr: read_u64(entry, 0),
+                segment: read_u16(entry, 8),
+                start_bus: entry[10],
+                end_bus: entry[11],
+            };
+            mcfg.count += 1;
+        }
+        mcfg
+    }
+
+    pub fn entries(&self) -> &[McfgEntry] {
+        &self.entries[..self.count]
+    }
+
+    /// 获得 PCI 设备配置空间的物理地址
+    pub fn config_addr(&self, segment: u16, bus: u8, device: u8, function: u8) -> Option<u64> {
+        self.entries()
+            .iter()
+            .find(|e| e.segment == segment && e.start_bus <= bus && bus <= e.end_bus)
+            .map(|e| {
+                e.base_addr
+                    + (((bus - e.start_bus) as u64) << 20
+                        | (device as u64) << 15
+                        | (function as u64) << 12)
+            })
+    }
+}
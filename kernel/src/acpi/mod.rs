@@ -0,0 +1,191 @@
+//! ACPI 表解析
+//!
+//! 从引导程序传入的 RSDP 出发，遍历 RSDT/XSDT，解析 MADT、FADT、HPET、MCFG，
+//! 用于获得 APIC 地址、处理器列表、HPET 地址，并实现 ACPI 关机。
+//!
+//! Reference: [OSDev Wiki](https://wiki.osdev.org/RSDP)
+#![allow(dead_code)]
+
+mod fadt;
+mod hpet;
+mod madt;
+mod mcfg;
+mod sdt;
+
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::{IrqOverride, Madt};
+pub use mcfg::{Mcfg, McfgEntry};
+
+use sdt::{Rsdp, Sdt};
+use x86_64::instructions::port::Port;
+
+/// 从 ACPI 表中获得的平台信息
+#[derive(Debug)]
+pub struct Acpi {
+    pub revision: u8,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+    pub mcfg: Option<Mcfg>,
+    /// `\_S5` 睡眠类型，用于关机
+    pub s5: Option<(u16, u16)>,
+}
+
+pub static ACPI: spin::Once<Acpi> = spin::Once::new();
+
+/// 等待固件启用 ACPI 模式时读取 `PM1a_CNT` 的最大次数，每次端口读取约 1 微秒，共约 3 秒
+const SCI_EN_TIMEOUT_SPINS: u32 = 3_000_000;
+
+pub fn acpi() -> Option<&'static Acpi> {
+    ACPI.get()
+}
+
+/// 解析 ACPI 表
+///
+/// 需要保证物理内存已经映射到 `PHYSICAL_OFFSET`
+pub unsafe fn init(rsdp_addr: u64) {
+    let rsdp = match unsafe { Rsdp::load(rsdp_addr) } {
+        Some(rsdp) => rsdp,
+        None => {
+            warn!("invalid ACPI RSDP at {:#x}", rsdp_addr);
+            return;
+        }
+    };
+
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_addr != 0 {
+        (unsafe { Sdt::load(rsdp.xsdt_addr) }, 8)
+    } else {
+        (unsafe { Sdt::load(rsdp.rsdt_addr as u64) }, 4)
+    };
+    let root = match root {
+        Some(root) => root,
+        None => {
+            warn!("invalid ACPI root table");
+            return;
+        }
+    };
+
+    let mut acpi = Acpi {
+        revision: rsdp.revision,
+        madt: None,
+        fadt: None,
+        hpet: None,
+        mcfg: None,
+        s5: None,
+    };
+
+    for sdt in sdt::iter_root(root, entry_size) {
+        debug!(
+            "ACPI table {} at {:#x}",
+            core::str::from_utf8(sdt.signature()).unwrap_or("????"),
+            sdt.addr
+        );
+        match sdt.signature() {
+            b"APIC" => acpi.madt = Some(Madt::parse(&sdt)),
+            b"FACP" => acpi.fadt = Some(Fadt::parse(&sdt)),
+            b"HPET" => {
+                acpi.hpet = Hpet::parse(&sdt);
+                if acpi.hpet.is_none() {
+                    warn!("HPET table is too short, ignored");
+                }
+            }
+            b"MCFG" => acpi.mcfg = Some(Mcfg::parse(&sdt)),
+            _ => (),
+        }
+    }
+
+    if let Some(fadt) = &acpi.fadt {
+        acpi.s5 = unsafe { Sdt::load(fadt.dsdt_addr) }
+            .as_ref()
+            .and_then(fadt::find_s5);
+    }
+
+    info!(
+        "ACPI {} parsed: lapic={:#x?} ioapic={:#x?} cpus={:?} hpet={:#x?}",
+        acpi.revision,
+        acpi.madt.as_ref().map(|m| m.lapic_addr),
+        acpi.madt.as_ref().and_then(|m| m.ioapic_addr),
+        acpi.madt.as_ref().map(|m| m.cpus()),
+        acpi.hpet.as_ref().map(|h| h.base_addr),
+    );
+
+    ACPI.call_once(|| acpi);
+}
+
+/// Local APIC 物理地址，若 ACPI 不可用则使用默认地址
+pub fn lapic_addr() -> u64 {
+    acpi()
+        .and_then(|a| a.madt.as_ref())
+        .map(|m| m.lapic_addr)
+        .unwrap_or(crate::interrupts::LAPIC_ADDR as u64)
+}
+
+/// I/O APIC 物理地址，若 ACPI 不可用则使用默认地址
+pub fn ioapic_addr() -> u64 {
+    acpi()
+        .and_then(|a| a.madt.as_ref())
+        .and_then(|m| m.ioapic_addr)
+        .unwrap_or(crate::interrupts::IOAPIC_ADDR as u64)
+}
+
+/// I/O APIC 处理的第一个 GSI，若 ACPI 不可用则为 0
+pub fn ioapic_gsi_base() -> u32 {
+    acpi()
+        .and_then(|a| a.madt.as_ref())
+        .map_or(0, |m| m.ioapic_gsi_base)
+}
+
+/// 获得 ISA 中断的重定向信息，若未被重定向则 GSI 与 IRQ 相同
+pub fn irq_override(irq: u8) -> IrqOverride {
+    acpi()
+        .and_then(|a| a.madt.as_ref())
+        .and_then(|m| m.overrides().iter().find(|o| o.irq == irq).copied())
+        .unwrap_or(IrqOverride {
+            irq,
+            gsi: irq as u32,
+            active_low: false,
+            level_triggered: false,
+        })
+}
+
+/// HPET 物理地址
+pub fn hpet_addr() -> Option<u64> {
+    acpi().and_then(|a| a.hpet.as_ref()).map(|h| h.base_addr)
+}
+
+/// 通过 ACPI 关机，仅在失败时返回
+pub fn power_off() {
+    let (fadt, (slp_typa, slp_typb)) = match acpi().and_then(|a| Some((a.fadt?, a.s5?))) {
+        Some(v) => v,
+        None => {
+            warn!("ACPI power off is not supported");
+            return;
+        }
+    };
+
+    unsafe {
+        let mut pm1a_cnt = Port::<u16>::new(fadt.pm1a_cnt_blk as u16);
+        // 若固件尚未启用 ACPI 模式，则通过 SMI 启用
+        if pm1a_cnt.read() & fadt::SCI_EN == 0 && fadt.smi_cmd != 0 && fadt.acpi_enable != 0 {
+            Port::<u8>::new(fadt.smi_cmd as u16).write(fadt.acpi_enable);
+            // 有缺陷的固件可能永远不置位，超时后仍然尝试关机
+            let mut spins = 0;
+            while pm1a_cnt.read() & fadt::SCI_EN == 0 {
+                if spins == SCI_EN_TIMEOUT_SPINS {
+                    warn!("ACPI mode not enabled by firmware, trying to power off anyway");
+                    break;
+                }
+                spins += 1;
+                core::hint::spin_loop();
+            }
+        }
+
+        pm1a_cnt.write((slp_typa << 10) | fadt::SLP_EN);
+        if fadt.pm1b_cnt_blk != 0 {
+            Port::<u16>::new(fadt.pm1b_cnt_blk as u16).write((slp_typb << 10) | fadt::SLP_EN);
+        }
+    }
+
+    warn!("ACPI power off failed");
+}
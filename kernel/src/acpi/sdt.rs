@@ -0,0 +1,142 @@
+use crate::memory::physical_to_virtual;
+use core::ptr::read_unaligned;
+
+/// ACPI 表的通用头部（System Description Table Header）
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+pub const SDT_HEADER_SIZE: usize = core::mem::size_of::<SdtHeader>();
+
+/// Root System Description Pointer
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_addr: u32,
+    // 以下字段仅在 revision >= 2 时有效
+    pub length: u32,
+    pub xsdt_addr: u64,
+    pub ext_checksum: u8,
+    _reserved: [u8; 3],
+}
+
+/// ACPI 1.0 RSDP 的长度
+const RSDP_V1_SIZE: usize = 20;
+
+#[inline(always)]
+pub fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+#[inline(always)]
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+#[inline(always)]
+pub fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+/// 计算 `[addr, addr + len)` 物理内存的字节和
+fn checksum(addr: u64, len: usize) -> u8 {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(physical_to_virtual(addr as usize) as *const u8, len)
+    };
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// 读取物理地址处的对象
+///
+/// 调用者需要保证物理地址处确实存放着对应的结构
+pub unsafe fn read_phys<T: Copy>(addr: u64) -> T {
+    unsafe { read_unaligned(physical_to_virtual(addr as usize) as *const T) }
+}
+
+impl Rsdp {
+    /// 从物理地址读取并校验 RSDP
+    pub unsafe fn load(addr: u64) -> Option<Self> {
+        let rsdp: Rsdp = unsafe { read_phys(addr) };
+        if &rsdp.signature != b"RSD PTR " || checksum(addr, RSDP_V1_SIZE) != 0 {
+            return None;
+        }
+        if rsdp.revision >= 2 && checksum(addr, rsdp.length as usize) != 0 {
+            return None;
+        }
+        Some(rsdp)
+    }
+}
+
+/// 一张位于物理内存中的 ACPI 表
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    pub addr: u64,
+    pub header: SdtHeader,
+}
+
+impl Sdt {
+    /// 从物理地址读取并校验 ACPI 表
+    pub unsafe fn load(addr: u64) -> Option<Self> {
+        let header: SdtHeader = unsafe { read_phys(addr) };
+        if (header.length as usize) < SDT_HEADER_SIZE || checksum(addr, header.length as usize) != 0
+        {
+            return None;
+        }
+        Some(Self { addr, header })
+    }
+
+    pub fn signature(&self) -> &[u8; 4] {
+        &self.header.signature
+    }
+
+    /// 表头之后数据的长度
+    pub fn data_len(&self) -> usize {
+        self.header.length as usize - SDT_HEADER_SIZE
+    }
+
+    /// 表头之后的数据
+    pub fn data(&self) -> &'static [u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                physical_to_virtual(self.addr as usize + SDT_HEADER_SIZE) as *const u8,
+                self.data_len(),
+            )
+        }
+    }
+}
+
+/// 遍历 RSDT（`entry_size == 4`）或 XSDT（`entry_size == 8`）中的表
+pub fn iter_root(root: Sdt, entry_size: usize) -> impl Iterator<Item = Sdt> {
+    let data = root.data();
+    (0..data.len() / entry_size).filter_map(move |i| {
+        let entry = &data[i * entry_size..(i + 1) * entry_size];
+        let addr = if entry_size == 8 {
+            read_u64(entry, 0)
+        } else {
+            read_u32(entry, 0) as u64
+        };
+        let sdt = unsafe { Sdt::load(addr) };
+        if sdt.is_none() {
+            warn!("invalid ACPI table at {:#x}", addr);
+        }
+        sdt
+    })
+}
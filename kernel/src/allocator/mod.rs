@@ -0,0 +1,277 @@
+//! 内核堆分配器
+//!
+//! 堆从 `HEAP_START` 开始，启动时映射 `HEAP_INITIAL_SIZE`，空间不足时映射新的帧向上扩展，
+//! 最大到 `HEAP_MAX_SIZE`。不超过 2KiB 的小对象由 `slab` 缓存分配，其余由
+//! `linked_list_allocator` 分配。
+//!
+//! 扩展需要页表和帧分配器，持有它们时（如进程创建中的 `map_to`）无法扩展，因此每次分配后
+//! 保持至少 `HEAP_RESERVE` 的空闲空间供这些路径使用，保留空间用尽时直接 panic 而不是返回空指针。
+//!
+//! 启用 `heap_debug` feature 时，释放的内存会被填充为 `POISON`，并检测重复释放和释放后写入。
+
+mod slab;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use linked_list_allocator::Heap;
+use slab::Slab;
+use spin::Mutex;
+use x86_64::structures::paging::{
+    mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+pub const HEAP_START: usize = 0xFFFF_FF80_0000_0000;
+pub const HEAP_INITIAL_SIZE: usize = 1024 * 1024; // 1 MiB
+pub const HEAP_MAX_SIZE: usize = 256 * 1024 * 1024; // 256 MiB
+/// 每次扩展的最小大小
+const HEAP_GROW_SIZE: usize = 256 * 1024;
+/// 提前扩展堆所保持的空闲空间，供持有页表或帧分配器时的分配使用
+const HEAP_RESERVE: usize = 128 * 1024;
+
+/// 填充释放内存的字节
+#[cfg(feature = "heap_debug")]
+const POISON: u8 = 0xDF;
+
+/// 释放的大对象中记录已释放标记的偏移，位于链表分配器写入的节点之后
+#[cfg(feature = "heap_debug")]
+const FREED_MARK_OFFSET: usize = core::mem::size_of::<usize>() * 2;
+/// 已释放标记与块地址异或，不同地址的块的标记互不相同
+#[cfg(feature = "heap_debug")]
+const FREED_MARK: u64 = 0xF4EE_D0B1_0C4D_EAD5;
+
+#[global_allocator]
+pub static ALLOCATOR: KernelAllocator = KernelAllocator::new();
+
+pub struct KernelAllocator {
+    heap: Mutex<Heap>,
+    slabs: [Mutex<Slab>; slab::SIZES.len()],
+}
+
+/// 堆的使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// 已映射的堆大小
+    pub size: usize,
+    /// 堆中已分配的字节数，包括 slab 缓存占用的页
+    pub used: usize,
+    /// slab 缓存中空闲对象的字节数
+    pub slab_free: usize,
+}
+
+impl KernelAllocator {
+    const fn new() -> Self {
+        Self {
+            heap: Mutex::new(Heap::empty()),
+            slabs: [
+                Mutex::new(Slab::new(slab::SIZES[0])),
+                Mutex::new(Slab::new(slab::SIZES[1])),
+                Mutex::new(Slab::new(slab::SIZES[2])),
+                Mutex::new(Slab::new(slab::SIZES[3])),
+                Mutex::new(Slab::new(slab::SIZES[4])),
+                Mutex::new(Slab::new(slab::SIZES[5])),
+                Mutex::new(Slab::new(slab::SIZES[6])),
+                Mutex::new(Slab::new(slab::SIZES[7])),
+            ],
+        }
+    }
+
+    /// 统计堆的使用情况
+    ///
+    /// `alloc_small` 持有 slab 时会锁住堆，因此先释放堆的锁再读取 slab，避免相反的加锁顺序。
+    pub fn stats(&self) -> HeapStats {
+        let (size, used) = {
+            let heap = self.heap.lock();
+            (heap.size(), heap.used())
+        };
+        HeapStats {
+            size,
+            used,
+            slab_free: self.slabs.iter().map(|s| s.lock().free_bytes()).sum(),
+        }
+    }
+
+    fn alloc_small(&self, class: usize) -> *mut u8 {
+        let mut slab = self.slabs[class].lock();
+        if let Some(ptr) = slab.pop() {
+            return ptr;
+        }
+        let page =
+            self.alloc_large(Layout::from_size_align(slab::SLAB_PAGE, slab::SLAB_PAGE).unwrap());
+        if page.is_null() {
+            return page;
+        }
+        unsafe { slab.add_page(page) };
+        slab.pop().unwrap_or(ptr::null_mut())
+    }
+
+    fn alloc_large(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.heap.lock();
+        loop {
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                // 空闲空间不足时提前扩展，此时无法扩展则由之后的分配重试
+                if heap.size() - heap.used() < HEAP_RESERVE {
+                    let _ = grow(&mut heap, HEAP_RESERVE);
+                }
+                return ptr.as_ptr();
+            }
+            match grow(&mut heap, layout.size() + layout.align()) {
+                Ok(()) => (),
+                Err(GrowError::Locked) => {
+                    drop(heap);
+                    panic!(
+                        "kernel heap reserve exhausted while page table or frame allocator is held"
+                    );
+                }
+                Err(GrowError::Exhausted) => return ptr::null_mut(),
+            }
+        }
+    }
+}
+
+/// 扩展堆失败的原因
+enum GrowError {
+    /// 页表或帧分配器正被持有
+    Locked,
+    /// 达到 `HEAP_MAX_SIZE` 或物理内存耗尽
+    Exhausted,
+}
+
+/// 映射新的帧扩展堆，至少扩展 `size` 字节
+fn grow(heap: &mut Heap, size: usize) -> Result<(), GrowError> {
+    let size = size.max(HEAP_GROW_SIZE);
+    let size = (size + 0xFFF) & !0xFFF;
+    let top = heap.top();
+    if top + size > HEAP_START + HEAP_MAX_SIZE {
+        return Err(GrowError::Exhausted);
+    }
+    let (mut page_table, mut frame_alloc) = match (
+        crate::memory::get_page_table(),
+        crate::memory::get_frame_alloc(),
+    ) {
+        (Some(page_table), Some(frame_alloc)) => (page_table, frame_alloc),
+        _ => return Err(GrowError::Locked),
+    };
+    if map_heap(top, size, &mut *page_table, &mut *frame_alloc).is_err() {
+        return Err(GrowError::Exhausted);
+    }
+    trace!("kernel heap grows to {:#x}", heap.size() + size);
+    unsafe { heap.extend(size) };
+    Ok(())
+}
+
+fn map_heap(
+    start: usize,
+    size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let page_range = {
+        let heap_start = VirtAddr::new(start as u64);
+        let heap_end = heap_start + size - 1u64;
+        let heap_start_page = Page::containing_address(heap_start);
+        let heap_end_page = Page::containing_address(heap_end);
+        Page::range_inclusive(heap_start_page, heap_end_page)
+    };
+
+    for page in page_range {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+    Ok(())
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match slab::class_of(&layout) {
+            Some(class) => self.alloc_small(class),
+            None => {
+                let ptr = self.alloc_large(layout);
+                #[cfg(feature = "heap_debug")]
+                if !ptr.is_null() {
+                    unsafe { set_freed_mark(ptr, layout, false) };
+                }
+                ptr
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match slab::class_of(&layout) {
+            Some(class) => unsafe { self.slabs[class].lock().push(ptr) },
+            None => {
+                #[cfg(feature = "heap_debug")]
+                unsafe {
+                    check_large_free(ptr, layout);
+                    ptr.write_bytes(POISON, layout.size());
+                    set_freed_mark(ptr, layout, true);
+                }
+                unsafe {
+                    self.heap
+                        .lock()
+                        .deallocate(NonNull::new_unchecked(ptr), layout)
+                };
+            }
+        }
+    }
+}
+
+/// 大对象中已释放标记的位置，块太小放不下标记时为 `None`
+#[cfg(feature = "heap_debug")]
+fn freed_mark(ptr: *mut u8, layout: Layout) -> Option<*mut u64> {
+    (layout.size() >= FREED_MARK_OFFSET + 8)
+        .then(|| unsafe { ptr.add(FREED_MARK_OFFSET) } as *mut u64)
+}
+
+/// 释放时写入已释放标记，分配时清除
+///
+/// 标记只由分配器写入，未写入过数据的存活块不会被误认为已释放。
+#[cfg(feature = "heap_debug")]
+unsafe fn set_freed_mark(ptr: *mut u8, layout: Layout, freed: bool) {
+    if let Some(mark) = freed_mark(ptr, layout) {
+        let value = if freed { FREED_MARK ^ ptr as u64 } else { 0 };
+        unsafe { mark.write_unaligned(value) };
+    }
+}
+
+/// 检测大对象的重复释放
+#[cfg(feature = "heap_debug")]
+unsafe fn check_large_free(ptr: *mut u8, layout: Layout) {
+    if let Some(mark) = freed_mark(ptr, layout) {
+        assert!(
+            unsafe { mark.read_unaligned() } != FREED_MARK ^ ptr as u64,
+            "double free of {:p}, {:?}",
+            ptr,
+            layout
+        );
+    }
+}
+
+pub fn init_heap(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    map_heap(HEAP_START, HEAP_INITIAL_SIZE, mapper, frame_allocator)?;
+
+    unsafe {
+        ALLOCATOR.heap.lock().init(HEAP_START, HEAP_INITIAL_SIZE);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(test))]
+#[alloc_error_handler]
+fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
+    panic!(
+        "allocation error: {:?}, heap: {:?}",
+        layout,
+        ALLOCATOR
+            .heap
+            .try_lock()
+            .map(|heap| (heap.size(), heap.used()))
+    )
+}
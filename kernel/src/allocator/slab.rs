@@ -0,0 +1,117 @@
+//! 按大小分级的 slab 缓存
+//!
+//! 每一级缓存从堆中取得整页内存，切分为相同大小的对象，用空闲链表管理。
+//! 对象大小均为 2 的幂，且页按页大小对齐，因此对象按其大小对齐。
+//! 释放的对象留在缓存中，不归还给堆。
+
+use core::alloc::Layout;
+use core::ptr;
+
+/// 各级对象大小
+pub const SIZES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// 每次从堆中取得的内存大小
+pub const SLAB_PAGE: usize = 4096;
+
+/// 空闲对象中存放的魔数，用于检测重复释放
+#[cfg(feature = "heap_debug")]
+const FREE_MAGIC: usize = 0x5AB_F4EE_DEAD_BEEF;
+
+/// 空闲链表节点，存放在空闲对象的开头
+struct FreeObject {
+    next: *mut FreeObject,
+    #[cfg(feature = "heap_debug")]
+    magic: usize,
+}
+
+pub struct Slab {
+    size: usize,
+    free_list: *mut FreeObject,
+    /// 空闲对象数
+    free: usize,
+}
+
+// 空闲链表只在持有锁时访问
+unsafe impl Send for Slab {}
+
+/// 获得能容纳 `layout` 的最小一级，过大时返回 `None`
+pub fn class_of(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    SIZES.iter().position(|&s| s >= size)
+}
+
+impl Slab {
+    pub const fn new(size: usize) -> Self {
+        Self {
+            size,
+            free_list: ptr::null_mut(),
+            free: 0,
+        }
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        self.free * self.size
+    }
+
+    /// 取出一个空闲对象
+    pub fn pop(&mut self) -> Option<*mut u8> {
+        if self.free_list.is_null() {
+            return None;
+        }
+        let obj = self.free_list;
+        unsafe {
+            self.free_list = (*obj).next;
+            #[cfg(feature = "heap_debug")]
+            {
+                // 检查释放后是否被写入
+                let poisoned = core::slice::from_raw_parts(
+                    (obj as *const u8).add(core::mem::size_of::<FreeObject>()),
+                    self.size - core::mem::size_of::<FreeObject>(),
+                );
+                assert!(
+                    (*obj).magic == FREE_MAGIC && poisoned.iter().all(|&b| b == super::POISON),
+                    "use after free of slab object {:p}",
+                    obj
+                );
+                (*obj).magic = 0;
+            }
+        }
+        self.free -= 1;
+        Some(obj as *mut u8)
+    }
+
+    /// 放回一个对象
+    ///
+    /// # Safety
+    ///
+    /// `ptr` 需要是由本级缓存分配且尚未释放的对象
+    pub unsafe fn push(&mut self, ptr: *mut u8) {
+        let obj = ptr as *mut FreeObject;
+        #[cfg(feature = "heap_debug")]
+        unsafe {
+            if (*obj).magic == FREE_MAGIC {
+                let mut cur = self.free_list;
+                while !cur.is_null() {
+                    assert!(cur != obj, "double free of slab object {:p}", obj);
+                    cur = (*cur).next;
+                }
+            }
+            ptr.write_bytes(super::POISON, self.size);
+            (*obj).magic = FREE_MAGIC;
+        }
+        unsafe { (*obj).next = self.free_list };
+        self.free_list = obj;
+        self.free += 1;
+    }
+
+    /// 将一页内存切分为对象加入空闲链表
+    ///
+    /// # Safety
+    ///
+    /// `page` 需要是按 `SLAB_PAGE` 对齐、大小为 `SLAB_PAGE` 的未使用内存
+    pub unsafe fn add_page(&mut self, page: *mut u8) {
+        for i in (0..SLAB_PAGE / self.size).rev() {
+            unsafe { self.push(page.add(i * self.size)) };
+        }
+    }
+}
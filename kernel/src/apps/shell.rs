@@ -1,11 +1,8 @@
 use crate::drivers::{fs, OsDevice, OsFile};
+use alloc::string::String;
 use alloc::vec::Vec;
-use boot::BootInfo;
 use fatpart::{Entry, File};
-use x86_64::{
-    instructions::interrupts, registers::rflags::RFlags, structures::paging::FrameAllocator,
-    VirtAddr,
-};
+use x86_64::instructions::interrupts;
 
 fn list() -> Vec<File<'static, OsDevice>> {
     fs().root_directory()
@@ -24,64 +21,10 @@ fn run_program_prepare() {
     interrupts::disable();
 }
 
-fn run_program(file: &OsFile, boot_info: &'static BootInfo) {
-    info!("loading file {} to memory", file.entry.stem());
-    let sectors = file.sectors();
-    let buf = {
-        let pages = (sectors.len() + 7) / 8;
-        // 分配内存帧
-        let mem_start = crate::memory::get_frame_alloc_sure()
-            .allocate_frame()
-            .unwrap()
-            .start_address()
-            .as_u64();
-        trace!("alloc = {}", mem_start);
-        for _ in 1..pages {
-            let addr = crate::memory::get_frame_alloc_sure()
-                .allocate_frame()
-                .unwrap()
-                .start_address()
-                .as_u64();
-            trace!("alloc = {}", addr);
-        }
-        // 加载磁盘内容
-        let mut buf =
-            unsafe { core::slice::from_raw_parts_mut(mem_start as *mut u8, pages * 0x1000) };
-
-        file.load_to(&mut buf).unwrap();
-        &mut buf[..pages * 0x1000]
-    };
-
-    // 解析 ELF 文件
-    let elf = xmas_elf::ElfFile::new(&buf).unwrap();
-
-    const STACK_BOT: u64 = 0x0000_2000_0000_0000;
-    const STACK_PAGES: u64 = 512;
-    const STACK_TOP: u64 = STACK_BOT + STACK_PAGES * 0x1000;
-
-    crate::process::spawn_process(
-        VirtAddr::new_truncate(elf.header.pt2.entry_point()),
-        VirtAddr::new_truncate(STACK_TOP),
-    );
-
-    let mut list = crate::process::get_process_list_sure();
-    let proc = list.last_mut().unwrap();
-
-    // 映射到页表
-    elf_loader::map_elf(
-        &elf,
-        proc.page_table_mut(),
-        &mut *crate::memory::get_frame_alloc_sure(),
-    )
-    .unwrap();
-
-    elf_loader::map_stack(
-        STACK_BOT,
-        STACK_PAGES,
-        proc.page_table_mut(),
-        &mut *crate::memory::get_frame_alloc_sure(),
-    )
-    .expect("failed to map stack");
+fn run_program(file: &OsFile, args: Vec<String>) {
+    if let Err(err) = crate::process::spawn(file, args, 0) {
+        println!("failed to run {}: {}", file.entry.stem(), err);
+    }
 }
 
 fn run_program_launch() {
@@ -93,25 +36,18 @@ fn run_program_launch() {
                 int {id}
                 pop rbp",
             id = const 0x80,
-            in("rax") crate::interrupts::Syscall::SpawnProcess as u64,
+            inlateout("rax") crate::interrupts::Syscall::SpawnProcess as u64 => _,
             in("rdi") 0,
             in("rsi") 0,
         );
     }
 }
 
-fn run_process(entry: u64, stacktop: u64) {
-    unsafe {
-        core::arch::asm!("
-            push rbp
-            int {id}
-            pop rbp",
-            id = const 0x80,
-            in("rax") crate::interrupts::Syscall::SpawnProcess as u64,
-            in("rdi") entry,
-            in("rsi") stacktop,
-        );
-    }
+/// 按文件名查找程序，不区分大小写
+fn find_program<'a>(progs: &'a [OsFile], name: &str) -> Option<&'a OsFile> {
+    progs
+        .iter()
+        .find(|p| p.entry.stem().trim().eq_ignore_ascii_case(name))
 }
 
 fn print_help(progs: &[OsFile]) {
@@ -128,16 +64,120 @@ fn print_help(progs: &[OsFile]) {
     println!(
         "Run processes by id, ids grouped together will be runned concurrently
 While groups separated by space will run sequentially
+Run a program with arguments by name, e.g. `sampleio a b`
 Others:
+ps - list processes
+kill <pid> - terminate a process
+nice <pid> <n> - set the priority of a process, from -20 (highest) to 19
+Ctrl+C - pause running programs and return here, an empty line resumes them
+m - memory usage
 q - quit
 h - help"
     )
 }
 
-fn main_iter(boot_info: &'static BootInfo, progs: &[OsFile]) -> bool {
+fn print_memory() {
+    let stats = crate::memory::get_frame_alloc_sure().stats();
+    println!(
+        "frames: {} used, {} free, {} total ({} KiB free)",
+        stats.used(),
+        stats.free,
+        stats.total,
+        stats.free * 4
+    );
+    let heap = crate::allocator::ALLOCATOR.stats();
+    println!(
+        "kernel heap: {} KiB used of {} KiB, {} KiB cached in slabs",
+        heap.used / 1024,
+        heap.size / 1024,
+        heap.slab_free / 1024
+    );
+    print_processes();
+}
+
+fn print_processes() {
+    println!(
+        "{:>5} {:>5} {:<16} {:<9} {:>4} {:>11} {:>9}",
+        "pid", "ppid", "name", "state", "nice", "cpu", "rss"
+    );
+    // 时钟中断会访问进程列表，需要关中断
+    let procs = interrupts::without_interrupts(crate::process::process_info);
+    for p in procs {
+        println!(
+            "{:>5} {:>5} {:<16} {:<9} {:>4} {:>7}.{:02}s {:>5} KiB",
+            p.pid,
+            p.parent,
+            p.name(),
+            p.state_name(),
+            p.nice,
+            p.cpu_time_ns / 1_000_000_000,
+            p.cpu_time_ns / 10_000_000 % 100,
+            p.memory / 1024
+        );
+    }
+}
+
+/// `kill <pid>`
+fn kill<'a>(mut args: impl Iterator<Item = &'a str>) {
+    let pid = match args.next().and_then(|arg| arg.parse().ok()) {
+        Some(pid) => pid,
+        None => {
+            println!("usage: kill <pid>");
+            return;
+        }
+    };
+    if let Err(err) = interrupts::without_interrupts(|| crate::process::kill(pid)) {
+        println!("kill: {}: {}", pid, err);
+    }
+}
+
+/// `nice <pid> <n>`
+fn nice<'a>(mut args: impl Iterator<Item = &'a str>) {
+    let pid = args.next().and_then(|arg| arg.parse().ok());
+    let nice = args.next().and_then(|arg| arg.parse().ok());
+    let (pid, nice) = match pid.zip(nice) {
+        Some(args) => args,
+        None => {
+            println!("usage: nice <pid> <n>");
+            return;
+        }
+    };
+    if let Err(err) = interrupts::without_interrupts(|| crate::process::set_priority(pid, nice)) {
+        println!("nice: {}: {}", pid, err);
+    }
+}
+
+fn main_iter(progs: &[OsFile]) -> bool {
     print!("> ");
     let prog = crate::drivers::keyboard::getline_block();
 
+    // 内置命令不启动程序，Ctrl+C 暂停的程序保持暂停
+    let mut words = prog.split_whitespace();
+    match words.next() {
+        Some("ps") => {
+            print_processes();
+            return true;
+        }
+        Some("kill") => {
+            kill(words);
+            return true;
+        }
+        Some("nice") => {
+            nice(words);
+            return true;
+        }
+        // 以程序名开头时，之后的单词作为参数
+        Some(name) => {
+            if let Some(file) = find_program(progs, name) {
+                run_program_prepare();
+                run_program(file, words.map(String::from).collect());
+                run_program_launch();
+                return true;
+            }
+        }
+        None => (),
+    }
+
     for part in prog.split(' ') {
         run_program_prepare();
         println!("run processes [{}]", part);
@@ -146,12 +186,13 @@ fn main_iter(boot_info: &'static BootInfo, progs: &[OsFile]) -> bool {
                 '0'..='9' => {
                     let id = c as u32 - '0' as u32;
                     if (id as usize) < progs.len() {
-                        run_program(&progs[id as usize], boot_info);
+                        run_program(&progs[id as usize], Vec::new());
                     } else {
                         println!("unknown process {}", id)
                     }
                 }
                 'h' => print_help(progs),
+                'm' => print_memory(),
                 'q' => return false,
                 _ => (),
             }
@@ -162,12 +203,12 @@ fn main_iter(boot_info: &'static BootInfo, progs: &[OsFile]) -> bool {
     true
 }
 
-pub fn main(boot_info: &'static BootInfo) -> u8 {
+pub fn main() -> u8 {
     let progs = list();
 
     print_help(&progs);
 
-    while main_iter(boot_info, &progs) {}
+    while main_iter(&progs) {}
 
     0
 }
@@ -140,7 +140,12 @@ pub fn _print(args: Arguments) {
     use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
-        get_console_sure().write_fmt(args).unwrap();
+        if let Some(mut serial) = crate::serial::get_serial() {
+            serial.write_fmt(args).unwrap();
+        }
+        if let Some(mut console) = get_console() {
+            console.write_fmt(args).unwrap();
+        }
     });
 }
 
@@ -148,6 +153,10 @@ pub fn _print(args: Arguments) {
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    println!("[PANIC] {}", info);
+    // 串口的锁可能在 panic 时被持有，绕过锁输出
+    crate::serial::force_print(format_args!("[PANIC] {}\n", info));
+    if let Some(mut console) = get_console() {
+        let _ = writeln!(console, "[PANIC] {}", info);
+    }
     loop {}
 }
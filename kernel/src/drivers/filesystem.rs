@@ -16,3 +16,21 @@ pub fn init() {
     let [p0, _p1, _p2, _p3] = fatpart::Disk::new(device()).partitions();
     FS.call_once(|| FATPartition::new(p0));
 }
+
+/// 在根目录中按文件名查找文件，忽略大小写，可以省略扩展名
+pub fn find_file(name: &str) -> Option<OsFile> {
+    let (stem, ext) = name.split_once('.').unwrap_or((name, ""));
+    fs().root_directory()
+        .load_childs()
+        .ok()?
+        .into_iter()
+        .find_map(|e| match e {
+            Entry::File(f)
+                if f.entry.stem().trim().eq_ignore_ascii_case(stem)
+                    && (ext.is_empty() || f.entry.ext().trim().eq_ignore_ascii_case(ext)) =>
+            {
+                Some(f)
+            }
+            _ => None,
+        })
+}
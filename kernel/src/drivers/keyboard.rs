@@ -1,9 +1,12 @@
+use crate::process::WaitQueue;
 use alloc::string::String;
 use crossbeam_queue::ArrayQueue;
 use pc_keyboard::DecodedKey;
 use spin::Once;
 
 pub static KEY_BUFFER: Once<ArrayQueue<DecodedKey>> = Once::new();
+/// 等待按键的进程
+pub static KEY_WAIT: WaitQueue = WaitQueue::new();
 
 const DEFAULT_CAPACITY: usize = 80;
 
@@ -35,7 +38,7 @@ pub fn get_key_block() -> DecodedKey {
 
 /// 读取一行输入
 pub fn getline_block() -> String {
-    use crate::console::get_console_sure;
+    use crate::console::get_console;
 
     let mut s = String::with_capacity(DEFAULT_CAPACITY);
     while let DecodedKey::Unicode(k) = get_key_block() {
@@ -44,9 +47,14 @@ pub fn getline_block() -> String {
             // backspace
             '\x08' => {
                 if !s.is_empty() {
-                    crate::console::get_console_sure().move_cursor(-1, 0);
-                    print!("  "); // draw a char more to clear hint
-                    crate::console::get_console_sure().move_cursor(-2, 0);
+                    if let Some(mut console) = get_console() {
+                        console.move_cursor(-1, 0);
+                        console.write("  "); // draw a char more to clear hint
+                        console.move_cursor(-2, 0);
+                    }
+                    if let Some(mut serial) = crate::serial::get_serial() {
+                        b"\x08 \x08".iter().for_each(|&b| serial.send(b));
+                    }
                     s.pop(); // remove previous char
                 }
             }
@@ -55,7 +63,9 @@ pub fn getline_block() -> String {
                 s.push(c)
             }
         }
-        get_console_sure().draw_hint();
+        if let Some(mut console) = get_console() {
+            console.draw_hint();
+        }
     }
     println!();
     s
@@ -3,7 +3,7 @@ pub mod ide;
 mod ide_wrap;
 pub mod keyboard;
 
-pub use filesystem::{fs, OsDevice, OsDir, OsEntry, OsFile, FS};
+pub use filesystem::{find_file, fs, OsDevice, OsDir, OsEntry, OsFile, FS};
 pub use ide::IDE;
 pub use ide::{device, drive, drive_sure};
 pub use ide_wrap::MutexIDE;
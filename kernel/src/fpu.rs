@@ -0,0 +1,59 @@
+//! x87 FPU 与 SSE 状态
+//!
+//! 内核以软浮点编译，不会修改 FPU 和 XMM 寄存器，因此用户程序的浮点状态在进入内核后保持不变，
+//! 只需要在切换进程时通过 `FXSAVE`/`FXRSTOR` 保存和恢复。用户程序只使用 SSE，不需要 `XSAVE`。
+
+use core::arch::asm;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+/// 允许使用 SSE 指令并保存其状态，需要在运行用户程序之前调用
+pub fn init() {
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+        });
+        Cr4::update(|flags| {
+            flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE);
+        });
+        asm!("fninit", options(nomem, nostack));
+    }
+}
+
+/// `FXSAVE` 保存的 512 字节状态
+#[repr(C, align(16))]
+#[derive(Clone)]
+pub struct FpuState([u8; 512]);
+
+impl FpuState {
+    /// 与 `fninit` 之后相同的初始状态：屏蔽所有浮点异常，舍入到最近
+    pub fn new() -> Self {
+        let mut area = [0; 512];
+        // FCW 位于偏移 0，MXCSR 位于偏移 24
+        area[0..2].copy_from_slice(&0x037F_u16.to_le_bytes());
+        area[24..28].copy_from_slice(&0x1F80_u32.to_le_bytes());
+        Self(area)
+    }
+
+    /// 保存当前处理器的状态
+    pub fn save(&mut self) {
+        unsafe { asm!("fxsave64 [{}]", in(reg) self.0.as_mut_ptr(), options(nostack)) };
+    }
+
+    /// 加载到当前处理器
+    pub fn restore(&self) {
+        unsafe { asm!("fxrstor64 [{}]", in(reg) self.0.as_ptr(), options(nostack, readonly)) };
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for FpuState {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("FpuState").finish_non_exhaustive()
+    }
+}
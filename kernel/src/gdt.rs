@@ -3,44 +3,67 @@ use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+/// 可能在任意栈上发生的异常使用独立的 IST 栈，相互之间不会覆盖
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
-pub const CONTEXT_SWITCH: u16 = 0;
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+/// `init_ist_stacks` 分配的 IST 栈的页数
+const IST_STACK_PAGES: u64 = 4;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+/// 任务状态段，`privilege_stack_table[0]` 会在切换进程时被修改，因此不能放在 `lazy_static` 中
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss.interrupt_stack_table[CONTEXT_SWITCH as usize] = {
-            const STACK_SIZE: usize = 4096;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+/// 内存初始化之前使用的 IST 栈，没有保护页
+fn init_tss() {
+    const STACK_SIZE: usize = 4096;
+    static mut STACKS: [[u8; STACK_SIZE]; 3] = [[0; STACK_SIZE]; 3];
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
-    };
+    let tss = unsafe { &mut TSS };
+    for (index, stack) in [
+        DOUBLE_FAULT_IST_INDEX,
+        NMI_IST_INDEX,
+        MACHINE_CHECK_IST_INDEX,
+    ]
+    .iter()
+    .zip(unsafe { STACKS.iter() })
+    {
+        tss.interrupt_stack_table[*index as usize] = VirtAddr::from_ptr(stack) + STACK_SIZE;
+    }
+}
+
+/// 将 IST 栈替换为带保护页的内核栈，需要在内存初始化之后调用
+pub fn init_ist_stacks() {
+    let mut frame_alloc = crate::memory::get_frame_alloc_sure();
+    for index in [
+        DOUBLE_FAULT_IST_INDEX,
+        NMI_IST_INDEX,
+        MACHINE_CHECK_IST_INDEX,
+    ] {
+        let stack = crate::memory::KernelStack::new(IST_STACK_PAGES, &mut *frame_alloc)
+            .expect("cannot alloc IST stack");
+        unsafe { TSS.interrupt_stack_table[index as usize] = stack.top() };
+        // IST 栈在整个运行期间使用，不会释放
+        core::mem::forget(stack);
+    }
 }
 
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
+        // `SYSCALL` 要求内核数据段紧跟内核代码段，`SYSRET` 要求用户代码段紧跟用户数据段
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
         let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &TSS }));
         (
             gdt,
             Selectors {
                 code_selector,
+                data_selector,
                 tss_selector,
+                user_code_selector,
+                user_data_selector,
             },
         )
     };
@@ -48,7 +71,10 @@ lazy_static! {
 
 struct Selectors {
     code_selector: SegmentSelector,
+    data_selector: SegmentSelector,
     tss_selector: SegmentSelector,
+    user_code_selector: SegmentSelector,
+    user_data_selector: SegmentSelector,
 }
 
 pub fn init() {
@@ -56,6 +82,7 @@ pub fn init() {
     use x86_64::instructions::tables::load_tss;
     use x86_64::PrivilegeLevel;
 
+    init_tss();
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
@@ -67,3 +94,36 @@ pub fn init() {
         load_tss(GDT.1.tss_selector);
     }
 }
+
+/// 为应用处理器加载 GDT
+///
+/// TSS 在加载后会被标记为忙碌，不能被多个处理器共享，因此 AP 暂不加载 TSS
+pub fn init_ap() {
+    use x86_64::instructions::segmentation::{Segment, CS, DS, ES, SS};
+    use x86_64::PrivilegeLevel;
+
+    GDT.0.load();
+    unsafe {
+        CS::set_reg(GDT.1.code_selector);
+        DS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        SS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        ES::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+    }
+}
+
+/// 内核的代码段与栈段选择子
+pub fn kernel_selectors() -> (SegmentSelector, SegmentSelector) {
+    (GDT.1.code_selector, GDT.1.data_selector)
+}
+
+/// 用户态的代码段与栈段选择子，特权级为 3
+pub fn user_selectors() -> (SegmentSelector, SegmentSelector) {
+    (GDT.1.user_code_selector, GDT.1.user_data_selector)
+}
+
+/// 设置从用户态进入内核时使用的栈，即 `TSS.privilege_stack_table[0]`
+///
+/// 使用 IST 的异常（双重错误、NMI、机器检查）不受影响
+pub fn set_kernel_stack(stack_top: VirtAddr) {
+    unsafe { TSS.privilege_stack_table[0] = stack_top };
+}
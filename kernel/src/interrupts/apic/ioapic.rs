@@ -6,13 +6,16 @@ use bit_field::BitField;
 pub struct IoApic {
     reg: *mut u32,
     data: *mut u32,
+    /// The first GSI served by this I/O APIC
+    gsi_base: u32,
 }
 
 impl IoApic {
-    pub unsafe fn new(addr: usize) -> Self {
+    pub unsafe fn new(addr: usize, gsi_base: u32) -> Self {
         IoApic {
             reg: addr as *mut u32,
             data: (addr + 0x10) as *mut u32,
+            gsi_base,
         }
     }
     pub fn disable_all(&mut self) {
@@ -22,22 +25,26 @@ impl IoApic {
             self.write_irq(i, RedirectionEntry::DISABLED, 0);
         }
     }
-    unsafe fn read(&mut self, reg: u8) -> u32 {
+    unsafe fn read(&mut self, reg: u32) -> u32 {
         unsafe {
-            self.reg.write_volatile(reg as u32);
+            self.reg.write_volatile(reg);
             self.data.read_volatile()
         }
     }
-    unsafe fn write(&mut self, reg: u8, data: u32) {
+    unsafe fn write(&mut self, reg: u32, data: u32) {
         unsafe {
-            self.reg.write_volatile(reg as u32);
+            self.reg.write_volatile(reg);
             self.data.write_volatile(data);
         }
     }
     fn write_irq(&mut self, irq: u8, flags: RedirectionEntry, dest: u8) {
+        self.write_entry(irq as u32, irq, flags, dest);
+    }
+    /// Program redirection entry `pin` to deliver the vector of `irq`
+    fn write_entry(&mut self, pin: u32, irq: u8, flags: RedirectionEntry, dest: u8) {
         unsafe {
-            self.write(REG_TABLE + 2 * irq, (T_IRQ0 + irq) as u32 | flags.bits());
-            self.write(REG_TABLE + 2 * irq + 1, (dest as u32) << 24);
+            self.write(REG_TABLE + 2 * pin, (T_IRQ0 + irq) as u32 | flags.bits());
+            self.write(REG_TABLE + 2 * pin + 1, (dest as u32) << 24);
         }
     }
     pub fn enable(&mut self, irq: u8, cpunum: u8) {
@@ -46,6 +53,33 @@ impl IoApic {
         // which happens to be that cpu's APIC ID.
         self.write_irq(irq, RedirectionEntry::NONE, cpunum);
     }
+    /// Route the given GSI to the vector of `irq`, with polarity and
+    /// trigger mode taken from the ACPI interrupt source override.
+    ///
+    /// Returns `false` without touching any entry if the GSI is not served
+    /// by this I/O APIC.
+    pub fn enable_gsi(
+        &mut self,
+        gsi: u32,
+        irq: u8,
+        active_low: bool,
+        level_triggered: bool,
+        cpunum: u8,
+    ) -> bool {
+        let pin = match gsi.checked_sub(self.gsi_base) {
+            Some(pin) if pin <= self.maxintr() as u32 => pin,
+            _ => return false,
+        };
+        let mut flags = RedirectionEntry::NONE;
+        if active_low {
+            flags |= RedirectionEntry::ACTIVELOW;
+        }
+        if level_triggered {
+            flags |= RedirectionEntry::LEVEL;
+        }
+        self.write_entry(pin, irq, flags, cpunum);
+        true
+    }
     pub fn disable(&mut self, irq: u8) {
         self.write_irq(irq, RedirectionEntry::DISABLED, 0);
     }
@@ -63,11 +97,11 @@ impl IoApic {
 /// Default physical address of IO APIC
 pub const IOAPIC_ADDR: u32 = 0xFEC00000;
 /// Register index: ID
-const REG_ID: u8 = 0x00;
+const REG_ID: u32 = 0x00;
 /// Register index: version
-const REG_VER: u8 = 0x01;
+const REG_VER: u32 = 0x01;
 /// Redirection table base
-const REG_TABLE: u8 = 0x10;
+const REG_TABLE: u32 = 0x10;
 const T_IRQ0: u8 = 32;
 
 bitflags! {
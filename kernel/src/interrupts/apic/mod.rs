@@ -37,11 +37,18 @@ pub trait LocalApic {
     /// Acknowledge interrupt on the current CPU
     fn eoi(&mut self);
 
+    /// Restart the periodic timer counting down from `count` bus cycles
+    fn set_timer_count(&mut self, count: u32);
+
+    /// Current count of the timer
+    fn timer_count(&self) -> u32;
+
     /// Send an IPI to a remote CPU
     fn send_ipi(&mut self, apic_id: u8, int_id: u8) {
         self.set_icr((apic_id as u64) << 56 | int_id as u64);
     }
 
-    // Start an AP
-    // unsafe fn start_ap(&mut self, apic_id: Tid, addr: u32);
+    /// Start an AP, `addr` is the physical address of the startup code,
+    /// which must be 4KiB aligned and below 1MiB
+    unsafe fn start_ap(&mut self, apic_id: Tid, addr: u32);
 }
@@ -101,6 +101,38 @@ impl LocalApic for XApic {
             self.write(EOI, 0);
         }
     }
+
+    fn set_timer_count(&mut self, count: u32) {
+        unsafe {
+            self.write(TICR, count);
+        }
+    }
+
+    fn timer_count(&self) -> u32 {
+        unsafe { self.read(TCCR) }
+    }
+
+    unsafe fn start_ap(&mut self, apic_id: u8, addr: u32) {
+        // "Universal startup algorithm."
+        // Send INIT (level-triggered) interrupt to reset other CPU.
+        unsafe {
+            self.write(ICRHI, (apic_id as u32) << 24);
+            self.write(ICRLO, INIT | LEVEL | ASSERT);
+            microdelay(200);
+            self.write(ICRLO, INIT | LEVEL);
+            microdelay(10000);
+
+            // Send startup IPI (twice!) to enter code.
+            // Regular hardware is supposed to only accept a STARTUP
+            // when it is in the halted state due to an INIT. So the second
+            // should be ignored, but it is part of the official Intel algorithm.
+            for _ in 0..2 {
+                self.write(ICRHI, (apic_id as u32) << 24);
+                self.write(ICRLO, STARTUP | (addr >> 12));
+                microdelay(200);
+            }
+        }
+    }
 }
 
 impl Debug for XApic {
@@ -28,6 +28,8 @@ pub enum Interrupts {
 
     IRQ0 = 32,
     Syscall = 0x80,
+    /// 内核中阻塞时切换进程，只能由内核触发
+    KernelYield = 0x81,
 }
 
 #[repr(u8)]
@@ -0,0 +1,146 @@
+//! 通过 `SYSCALL`/`SYSRET` 进入和返回的快速系统调用
+//!
+//! 用户态执行 `syscall` 时，CPU 将返回地址存入 `rcx`、将 `rflags` 存入 `r11`，
+//! 并跳转到 `LSTAR` 指向的 `syscall_entry`，但不会切换栈。入口通过 `swapgs` 取得每个处理器的
+//! `CpuLocal`，切换到其中当前进程的内核栈，再在栈上构造与 `int 0x80` 相同的中断栈帧和寄存器列表，
+//! 因此系统调用的处理（包括进程切换）与中断路径完全相同。
+//!
+//! 若系统调用没有切换进程，则通过 `sysretq` 返回；否则需要恢复新进程的全部寄存器，通过 `iretq` 返回。
+//! 在系统调用中阻塞的进程被唤醒后回到自己的内核栈上继续执行，返回时页表未变，同样通过 `sysretq` 返回。
+//! 内核不使用 `GS`，入口只在读取 `CpuLocal` 期间通过 `swapgs` 换入 `KernelGsBase`，构造完中断栈帧后立即换回，
+//! 因此内核运行时 `GS` 始终是用户的基址。在系统调用中阻塞的进程经 `iretq` 切换到其他进程时，
+//! 不会把交换过的 `GS` 留给下一个进程，返回路径也不需要 `swapgs`。
+
+use super::handlers::Registers;
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::{Efer, EferFlags, KernelGsBase, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::VirtAddr;
+
+/// 运行用户程序前使用的内核栈大小，之后使用每个进程自己的内核栈
+const STACK_SIZE: usize = 4096 * 4;
+
+/// 每个处理器的数据，入口通过 `gs:[offset]` 访问，字段顺序不能改变
+#[repr(C)]
+struct CpuLocal {
+    /// 内核栈的栈顶
+    kernel_stack: u64,
+    /// 进入内核时保存的用户栈
+    user_stack: u64,
+}
+
+/// 只有 BSP 运行用户程序，因此暂时只有一份
+static mut CPU_LOCAL: CpuLocal = CpuLocal {
+    kernel_stack: 0,
+    user_stack: 0,
+};
+
+/// 设置 `SYSCALL` 相关的 MSR，需要在 GDT 加载之后调用
+pub fn init() {
+    static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+    let (code_selector, data_selector) = crate::gdt::kernel_selectors();
+    let (user_code_selector, user_data_selector) = crate::gdt::user_selectors();
+    unsafe {
+        CPU_LOCAL.kernel_stack = (VirtAddr::from_ptr(&STACK) + STACK_SIZE).as_u64();
+        KernelGsBase::write(VirtAddr::from_ptr(&CPU_LOCAL));
+        Star::write(
+            user_code_selector,
+            user_data_selector,
+            code_selector,
+            data_selector,
+        )
+        .expect("invalid segment selectors for SYSCALL");
+        LStar::write(VirtAddr::new(syscall_entry as usize as u64));
+        // 进入内核时关中断，与 `int 0x80` 的中断门一致
+        SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG | RFlags::DIRECTION_FLAG);
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+}
+
+/// 设置 `SYSCALL` 进入内核时使用的栈，与 `gdt::set_kernel_stack` 一起在切换进程时调用
+pub fn set_syscall_stack(stack_top: VirtAddr) {
+    unsafe { CPU_LOCAL.kernel_stack = stack_top.as_u64() };
+}
+
+/// `SYSCALL` 的入口，仅供用户态使用
+#[naked]
+unsafe extern "C" fn syscall_entry() -> ! {
+    unsafe {
+        core::arch::asm!(
+            "
+            swapgs
+            mov gs:[8], rsp     // 保存用户栈
+            mov rsp, gs:[0]     // 切换到内核栈
+            // 构造中断栈帧，段选择子由 `syscall_entry_handler` 填写
+            push 0              // ss
+            push qword ptr gs:[8] // rsp
+            swapgs              // 换回用户的 GS
+            push r11            // rflags
+            push 0              // cs
+            push rcx            // rip
+            push rbp
+            push rax
+            push rbx
+            push rcx
+            push rdx
+            push rsi
+            push rdi
+            push r8
+            push r9
+            push r10
+            push r11
+            push r12
+            push r13
+            push r14
+            push r15
+            mov rsi, rsp  // 第二个参数：寄存器列表
+            mov rdi, rsp
+            add rdi, 15*8 // 第一个参数：中断栈帧
+            call {}
+            test al, al   // `pop` 不影响标志位
+            pop r15
+            pop r14
+            pop r13
+            pop r12
+            pop r11
+            pop r10
+            pop r9
+            pop r8
+            pop rdi
+            pop rsi
+            pop rdx
+            pop rcx
+            pop rbx
+            pop rax
+            pop rbp
+            jz 2f
+            pop rcx       // rip
+            add rsp, 8    // cs
+            pop r11       // rflags
+            pop rsp       // rsp
+            sysretq
+        2:
+            iretq
+            ",
+            sym syscall_entry_handler,
+            options(noreturn)
+        );
+    }
+}
+
+/// 处理系统调用，返回能否通过 `sysretq` 返回
+extern "C" fn syscall_entry_handler(sf: &mut InterruptStackFrame, regs: &mut Registers) -> bool {
+    let (code_selector, data_selector) = crate::gdt::user_selectors();
+    unsafe {
+        sf.as_mut().update(|sf| {
+            sf.code_segment = code_selector.0 as u64;
+            sf.stack_segment = data_selector.0 as u64;
+        });
+    }
+    let page_table = Cr3::read();
+    super::handlers::syscall_handler_naked(sf, regs);
+    // 切换到其他进程后，`rcx`、`r11` 等寄存器需要恢复为新进程的值
+    Cr3::read() == page_table
+}
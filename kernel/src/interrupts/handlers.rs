@@ -7,22 +7,36 @@ pub fn reg_idt(idt: &mut InterruptDescriptorTable) {
         idt.double_fault
             .set_handler_fn(double_fault_handler)
             .set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.non_maskable_interrupt
+            .set_handler_fn(nmi_handler)
+            .set_stack_index(crate::gdt::NMI_IST_INDEX);
+        idt.machine_check
+            .set_handler_fn(machine_check_handler)
+            .set_stack_index(crate::gdt::MACHINE_CHECK_IST_INDEX);
     }
     idt.invalid_tss.set_handler_fn(invalid_tss_handler);
     idt.segment_not_present
         .set_handler_fn(segment_not_present_handler);
     idt.stack_segment_fault
         .set_handler_fn(stack_segment_fault_handler);
-    idt.page_fault.set_handler_fn(page_fault_handler);
+    // 时钟中断和系统调用在当前进程的内核栈上处理，不使用 IST，
+    // 因此可以嵌套，系统调用也可以在内核中阻塞
     unsafe {
         idt[(consts::Interrupts::IRQ0 as u8 + consts::IRQ::Timer as u8) as usize]
-            .set_handler_fn(unsafe { core::mem::transmute(clock_handler_wrapper as *mut fn()) })
-            .set_stack_index(crate::gdt::CONTEXT_SWITCH);
+            .set_handler_fn(unsafe { core::mem::transmute(clock_handler_wrapper as *mut fn()) });
+        // 允许用户态通过 `int 0x80` 进入
         idt[consts::Interrupts::Syscall as usize]
             .set_handler_fn(unsafe {
                 core::mem::transmute(syscall_handler_naked_wrapper as *mut fn())
             })
-            .set_stack_index(crate::gdt::CONTEXT_SWITCH);
+            .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
+        idt[consts::Interrupts::KernelYield as usize].set_handler_fn(unsafe {
+            core::mem::transmute(kernel_yield_handler_wrapper as *mut fn())
+        });
+        // 用户态的缺页可能切换到其他进程，需要保存全部寄存器
+        idt.page_fault.set_handler_fn(unsafe {
+            core::mem::transmute(page_fault_handler_wrapper as *mut fn())
+        });
     }
 }
 
@@ -71,21 +85,58 @@ pub extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) -> ! {
+    // 内核栈溢出时无法在原来的栈上压入缺页异常的栈帧，会直接产生双重错误
+    let addr = x86_64::registers::control::Cr2::read();
+    if crate::memory::is_stack_guard(addr) {
+        panic!(
+            "EXCEPTION: DOUBLE FAULT (kernel stack overflow at {:?})\n{:#?}",
+            addr, stack_frame
+        );
+    }
     panic!(
         "EXCEPTION: DOUBLE FAULT\n{:#?}, {}",
         stack_frame, error_code
     );
 }
 
-pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
+pub extern "x86-interrupt" fn nmi_handler(stack_frame: InterruptStackFrame) {
+    warn!("NON-MASKABLE INTERRUPT\n{:#?}", stack_frame);
+}
+
+pub extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    panic!("EXCEPTION: MACHINE CHECK\n{:#?}", stack_frame);
+}
+
+/// 用户态的缺页交给进程的地址空间处理，无法处理时结束进程；内核态的缺页仍然是错误
+pub extern "C" fn page_fault_handler(
+    sf: &mut InterruptStackFrame,
+    regs: &mut Registers,
+    error_code: u64,
 ) {
+    let error_code = PageFaultErrorCode::from_bits_truncate(error_code);
+    let addr = x86_64::registers::control::Cr2::read();
+    if error_code.contains(PageFaultErrorCode::USER_MODE) {
+        let access = if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            crate::process::Access::Execute
+        } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            crate::process::Access::Write
+        } else {
+            crate::process::Access::Read
+        };
+        if let Err(fault) = crate::process::handle_page_fault(addr, access) {
+            crate::process::kill_current_process(addr, fault, sf, regs);
+        }
+        return;
+    }
+    if crate::memory::is_stack_guard(addr) {
+        panic!(
+            "EXCEPTION: PAGE FAULT (kernel stack overflow at {:?})\n{:#?}",
+            addr, sf
+        );
+    }
     panic!(
         "EXCEPTION: PAGE FAULT\n{:#?}, {:?} addr={:?}",
-        stack_frame,
-        error_code,
-        x86_64::registers::control::Cr2::read()
+        sf, error_code, addr
     );
 }
 
@@ -109,6 +160,13 @@ pub struct Registers {
     rbp: usize,
 }
 
+impl Registers {
+    /// 设置系统调用的返回值
+    pub fn set_rax(&mut self, value: u64) {
+        self.rax = value as usize;
+    }
+}
+
 macro_rules! wrap {
     ($fn: ident => $w:ident) => {
         #[naked]
@@ -158,6 +216,59 @@ macro_rules! wrap {
             }
         }
     };
+    // 带错误码的异常，错误码作为第三个参数，返回前从栈上移除
+    ($fn: ident => $w:ident, error_code) => {
+        #[naked]
+        pub unsafe extern "C" fn $w() -> ! {
+            unsafe {
+                core::arch::asm!(
+                    "
+                push rbp
+                push rax
+                push rbx
+                push rcx
+                push rdx
+                push rsi
+                push rdi
+                push r8
+                push r9
+                push r10
+                push r11
+                push r12
+                push r13
+                push r14
+                push r15
+                mov rsi, rsp  // 第二个参数：寄存器列表
+                mov rdi, rsp
+                add rdi, 16*8 // 第一个参数：中断帧
+                mov rdx, [rsp + 15*8] // 第三个参数：错误码
+                sub rsp, 8    // 错误码使栈少对齐了 8 字节
+                call {}
+                add rsp, 8
+                pop r15
+                pop r14
+                pop r13
+                pop r12
+                pop r11
+                pop r10
+                pop r9
+                pop r8
+                pop rdi
+                pop rsi
+                pop rdx
+                pop rcx
+                pop rbx
+                pop rax
+                pop rbp
+                add rsp, 8    // 错误码
+                iretq
+                ",
+                sym $fn,
+                options(noreturn)
+                );
+            }
+        }
+    };
 }
 
 wrap!(syscall_handler_naked => syscall_handler_naked_wrapper);
@@ -173,10 +284,19 @@ pub extern "C" fn syscall_handler_naked(sf: &mut InterruptStackFrame, regs: &mut
     );
 }
 
+wrap!(kernel_yield_handler => kernel_yield_handler_wrapper);
+
+pub extern "C" fn kernel_yield_handler(sf: &mut InterruptStackFrame, regs: &mut Registers) {
+    unsafe { crate::process::handle_kernel_yield(regs.rdi as u64, sf, regs) };
+}
+
+wrap!(page_fault_handler => page_fault_handler_wrapper, error_code);
+
 wrap!(clock_handler => clock_handler_wrapper);
 
 pub extern "C" fn clock_handler(sf: &mut InterruptStackFrame, regs: &mut Registers) {
-    crate::process::switch_first_ready_process(sf, regs);
+    crate::time::tick();
+    crate::process::tick(sf, regs);
     // clock_draw();
     super::ack(consts::Interrupts::IRQ0 as u8);
 }
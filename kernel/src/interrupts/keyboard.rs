@@ -24,9 +24,12 @@ pub fn init() {
 /// Should be called on every interrupt
 pub fn receive() -> Option<DecodedKey> {
     lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
+        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> =
+            Mutex::new(Keyboard::new(
+                layouts::Us104Key,
+                ScancodeSet1,
+                HandleControl::MapLettersToUnicode
+            ));
     }
 
     let mut keyboard = KEYBOARD.lock();
@@ -47,8 +50,14 @@ pub extern "x86-interrupt" fn interrupt_handler(_stack_frame: InterruptStackFram
     super::ack(super::consts::IRQ::Keyboard as u8);
     if let Some(key) = receive() {
         trace!("key readed {:?}", key);
+        // Ctrl+C 暂停用户程序，回到 shell
+        if matches!(key, DecodedKey::Unicode('\u{3}')) {
+            crate::process::request_shell();
+            return;
+        }
         if let Some(buf) = crate::drivers::keyboard::KEY_BUFFER.get() {
             buf.push(key).unwrap();
+            crate::drivers::keyboard::KEY_WAIT.notify_all();
         } else {
             trace!(
                 "keyboard input ignored because of uninitialized keyboard driver {:?}",
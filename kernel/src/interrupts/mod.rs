@@ -3,10 +3,13 @@ use x86_64::structures::idt::InterruptDescriptorTable;
 
 mod apic;
 mod consts;
+mod fast_syscall;
 mod handlers;
 mod keyboard;
 mod syscall;
 
+pub use apic::{IOAPIC_ADDR, LAPIC_ADDR};
+pub use fast_syscall::set_syscall_stack;
 pub use handlers::Registers;
 pub use syscall::Syscall;
 
@@ -22,22 +25,80 @@ lazy_static! {
 /// 初始化中断及硬件系统；不会打开中断
 pub unsafe fn init() {
     IDT.load();
+    fast_syscall::init();
     keyboard::init();
     info!("xapic support = {}", apic::XApic::support());
     // info!("x2apic support = {}", apic::X2Apic::support());
-    let mut xapic = unsafe { XApic::new(crate::memory::physical_to_virtual(0xfee00000)) };
+    let mut xapic = unsafe { XApic::new(lapic_base()) };
     xapic.cpu_init();
 }
 
+/// 在应用处理器上加载 IDT；AP 的 Local APIC 定时器不会被启用
+pub fn init_ap() {
+    IDT.load();
+}
+
+/// 通过 INIT-SIPI-SIPI 启动应用处理器，`addr` 为启动代码的物理地址
+pub fn start_ap(apic_id: u8, addr: u32) {
+    let mut lapic = unsafe { XApic::new(lapic_base()) };
+    unsafe { lapic.start_ap(apic_id, addr) };
+}
+
+/// Local APIC 的虚拟地址
+#[inline(always)]
+fn lapic_base() -> usize {
+    crate::memory::physical_to_virtual(crate::acpi::lapic_addr() as usize)
+}
+
+/// I/O APIC 的虚拟地址
+#[inline(always)]
+fn ioapic_base() -> usize {
+    crate::memory::physical_to_virtual(crate::acpi::ioapic_addr() as usize)
+}
+
 #[inline(always)]
 pub fn enable_irq(irq: u8) {
-    let mut ioapic =
-        unsafe { IoApic::new(crate::memory::physical_to_virtual(IOAPIC_ADDR as usize)) };
-    ioapic.enable(irq, 0);
+    let mut ioapic = unsafe { IoApic::new(ioapic_base(), crate::acpi::ioapic_gsi_base()) };
+    let iso = crate::acpi::irq_override(irq);
+    if !ioapic.enable_gsi(iso.gsi, irq, iso.active_low, iso.level_triggered, 0) {
+        warn!(
+            "GSI {} of IRQ {} is not served by the I/O APIC",
+            iso.gsi, irq
+        );
+    }
+}
+
+/// 设置 Local APIC 定时器的初始计数并重新开始计数，时钟中断的周期为 `count` 个总线周期
+pub fn set_timer_count(count: u32) {
+    let mut lapic = unsafe { XApic::new(lapic_base()) };
+    lapic.set_timer_count(count);
+}
+
+/// Local APIC 定时器的当前计数
+pub fn timer_count() -> u32 {
+    let lapic = unsafe { XApic::new(lapic_base()) };
+    lapic.timer_count()
+}
+
+/// 触发 `KernelYield` 中断，`arg` 通过 `rdi` 传给 `process::handle_kernel_yield`
+///
+/// 中断返回时可能已经切换过其他进程，所有寄存器都从保存的上下文中恢复。
+///
+/// # Safety
+///
+/// `arg` 必须满足 `process::handle_kernel_yield` 的要求。
+pub unsafe fn kernel_yield(arg: u64) {
+    unsafe {
+        core::arch::asm!(
+            "int {}",
+            const consts::Interrupts::KernelYield as u8,
+            in("rdi") arg,
+        )
+    };
 }
 
 #[inline(always)]
 pub fn ack(_irq: u8) {
-    let mut lapic = unsafe { XApic::new(crate::memory::physical_to_virtual(LAPIC_ADDR)) };
+    let mut lapic = unsafe { XApic::new(lapic_base()) };
     lapic.eoi();
 }
@@ -1,25 +1,27 @@
 use super::handlers::Registers;
-use core::alloc::Layout;
+use crate::memory::{UserPtr, UserSlice};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use fatpart::Device;
-use spin::Mutex;
-use x86_64::{structures::idt::InterruptStackFrame, VirtAddr};
-
-static RETURN_POINT: Mutex<Option<(VirtAddr, VirtAddr, u64, Registers)>> = Mutex::new(None);
-
-#[repr(u64)]
-pub enum Syscall {
-    SpawnProcess = 1,
-    ExitProcess = 2,
-    PrintStr = 5,
-    ReadKey = 6,
-    PlotPixel = 7,
-    Sleep = 8,
-    DisplayResolution = 9,
-    Allocate = 10,
-    Deallocate = 11,
-    ReadDisk = 12,
+use syscall_abi::{
+    DiskRead, Errno, Pixel, ProcessInfo, Resolution, StrRef, SyscallResult, Timespec,
+};
+use x86_64::structures::idt::InterruptStackFrame;
+
+pub use syscall_abi::Syscall;
+
+/// `spawn` 和 `exec` 接受的最大参数个数
+const MAX_ARGS: u64 = 64;
+
+/// 系统调用的上下文，可能在处理中切换到其他进程
+struct Context<'a> {
+    sf: &'a mut InterruptStackFrame,
+    regs: &'a mut Registers,
+    /// 寄存器已被切换为其他进程的上下文，不能再写入返回值
+    switched: bool,
 }
 
+/// 分发系统调用，结果按照 `syscall_abi` 的约定写入 `rax`
 pub extern "C" fn syscall_handler(
     a0: u64,
     a1: u64,
@@ -28,48 +30,133 @@ pub extern "C" fn syscall_handler(
     sf: &mut InterruptStackFrame,
     regs: &mut Registers,
 ) {
-    use Syscall::*;
-
     debug!("syscall = {:x} {:x} {:x} {:x}", a0, a1, a2, a3);
-    match a0 {
-        v if v == SpawnProcess as u64 => spawn_process(a1, a2, sf, regs),
-        v if v == ExitProcess as u64 => exit_process(sf, regs),
-        v if v == PrintStr as u64 => print_str(unsafe {
-            core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-                a1 as *const u8,
-                a2 as usize,
-            ))
-        }),
-        v if v == ReadKey as u64 => read_str(unsafe {
-            (a1 as *mut Option<pc_keyboard::DecodedKey>)
-                .as_mut()
-                .unwrap()
-        }),
-        v if v == PlotPixel as u64 => {
-            plot_pixel(a1 as usize, a2 as usize, (a3 & 0xFFFFFFFF) as u32)
+    if Syscall::from_id(a0).is_none() {
+        warn!("unknown syscall {:#x}", a0);
+    }
+    let mut context = Context {
+        sf,
+        regs,
+        switched: false,
+    };
+    let ret = syscall_abi::dispatch(&mut context, a0, [a1, a2, a3]);
+    if !context.switched {
+        context.regs.set_rax(syscall_abi::encode(ret));
+    }
+}
+
+impl syscall_abi::Handler for Context<'_> {
+    fn spawn_process(&mut self) -> SyscallResult {
+        // 调用者在切换前保存的上下文中得到返回值
+        self.regs.set_rax(0);
+        self.switched = true;
+        spawn_process(self.sf, self.regs);
+        Ok(0)
+    }
+    fn exit_process(&mut self, code: u64) -> SyscallResult {
+        if crate::process::current_pid() == 0 {
+            return Err(Errno::EPERM);
         }
-        v if v == Sleep as u64 => sleep(a1),
-        v if v == DisplayResolution as u64 => {
-            display_resolution(unsafe { (a1 as *mut u64).as_mut().unwrap() }, unsafe {
-                (a2 as *mut u64).as_mut().unwrap()
-            })
+        self.switched = true;
+        exit_process(code as i32, self.sf, self.regs);
+        Ok(0)
+    }
+    fn print_str(&mut self, s: u64) -> SyscallResult {
+        let s = UserPtr::<StrRef>::new(s).read()?;
+        print_str(UserSlice::new(s.ptr, s.len as usize))
+    }
+    fn read_key(&mut self, ptr: u64) -> SyscallResult {
+        let ptr = UserPtr::new(ptr);
+        // 先检查指针，避免取出按键后无法写入
+        ptr.write(None)?;
+        loop {
+            match crate::drivers::get_key() {
+                Some(key) => {
+                    ptr.write(Some(key))?;
+                    return Ok(0);
+                }
+                // 内核伪进程运行着 shell，不阻塞
+                None if crate::process::current_pid() == 0 => return Ok(0),
+                // 在内核中等待按键，被唤醒后重新检查
+                None => crate::process::wait_on(&crate::drivers::keyboard::KEY_WAIT),
+            }
         }
-        v if v == Allocate as u64 => {
-            allocate(unsafe { (a1 as *const Layout).as_ref().unwrap() }, unsafe {
-                (a2 as *mut *mut u8).as_mut().unwrap()
-            })
+    }
+    fn plot_pixel(&mut self, pixel: u64) -> SyscallResult {
+        let pixel = UserPtr::<Pixel>::new(pixel).read()?;
+        plot_pixel(pixel.x as usize, pixel.y as usize, pixel.color)
+    }
+    fn sleep(&mut self, ns: u64) -> SyscallResult {
+        if crate::process::current_pid() == 0 {
+            return sleep(ns);
         }
-        v if v == Deallocate as u64 => deallocate(unsafe { a1 as *mut u8 }, unsafe {
-            (a2 as *const Layout).as_ref().unwrap()
-        }),
-        v if v == ReadDisk as u64 => read_disk(a1, unsafe {
-            core::slice::from_raw_parts_mut(a2 as *mut u8, a3 as usize)
-        }),
-        _ => (),
+        crate::process::sleep(ns);
+        Ok(0)
+    }
+    fn display_resolution(&mut self, ptr: u64) -> SyscallResult {
+        display_resolution(UserPtr::new(ptr))
+    }
+    fn read_disk(&mut self, req: u64) -> SyscallResult {
+        let req = UserPtr::<DiskRead>::new(req).read()?;
+        read_disk(req.block, UserSlice::new(req.ptr, req.len as usize))
+    }
+    fn brk(&mut self, addr: u64) -> SyscallResult {
+        brk(addr)
+    }
+    fn abi_version(&mut self) -> SyscallResult {
+        Ok(syscall_abi::ABI_VERSION)
+    }
+    fn spawn(&mut self, path: u64, argv: u64, argc: u64) -> SyscallResult {
+        let (file, args) = read_program(path, argv, argc)?;
+        let pid = crate::process::spawn(&file, args, crate::process::current_pid())?;
+        Ok(pid as u64)
+    }
+    fn exec(&mut self, path: u64, argv: u64, argc: u64) -> SyscallResult {
+        let (file, args) = read_program(path, argv, argc)?;
+        crate::process::exec(&file, args, self.sf, self.regs)?;
+        // 新程序从入口开始运行，不需要返回值
+        self.switched = true;
+        Ok(0)
+    }
+    fn waitpid(&mut self, pid: u64) -> SyscallResult {
+        let code = crate::process::wait(pid as usize)?;
+        Ok(code as u32 as u64)
+    }
+    fn getpid(&mut self) -> SyscallResult {
+        Ok(crate::process::current_pid() as u64)
+    }
+    fn clock_gettime(&mut self, clock: u64, ptr: u64) -> SyscallResult {
+        clock_gettime(clock, UserPtr::new(ptr))
+    }
+    fn fork(&mut self) -> SyscallResult {
+        let pid = crate::process::fork(self.sf, self.regs)?;
+        Ok(pid as u64)
+    }
+    fn list_processes(&mut self, ptr: u64, len: u64) -> SyscallResult {
+        // 写入用户内存时可能需要处理缺页，不能持有进程列表
+        let infos = crate::process::process_info();
+        let size = core::mem::size_of::<ProcessInfo>() as u64;
+        for (i, info) in infos.iter().take(len as usize).enumerate() {
+            UserPtr::new(ptr.wrapping_add(i as u64 * size)).write(*info)?;
+        }
+        Ok(infos.len() as u64)
+    }
+    fn kill(&mut self, pid: u64) -> SyscallResult {
+        let current = crate::process::current_pid();
+        crate::process::kill(pid as usize)?;
+        if pid as usize == current {
+            self.switched = true;
+            crate::process::switch_first_ready_process(self.sf, self.regs);
+        }
+        Ok(0)
+    }
+    fn set_priority(&mut self, pid: u64, nice: u64) -> SyscallResult {
+        crate::process::set_priority(pid as usize, nice as i64)?;
+        Ok(0)
     }
 }
 
-pub fn spawn_process(target: u64, stack: u64, s: &mut InterruptStackFrame, regs: &mut Registers) {
+pub fn spawn_process(s: &mut InterruptStackFrame, regs: &mut Registers) {
     crate::process::save_current_process(s, regs);
     crate::process::get_process_list_sure()
         .first_mut()
@@ -78,55 +165,92 @@ pub fn spawn_process(target: u64, stack: u64, s: &mut InterruptStackFrame, regs:
     crate::process::switch_first_ready_process(s, regs);
 }
 
-pub fn exit_process(s: &mut InterruptStackFrame, regs: &mut Registers) {
-    crate::process::kill_current_process();
+pub fn exit_process(code: i32, s: &mut InterruptStackFrame, regs: &mut Registers) {
+    crate::process::exit_current_process(code);
     crate::process::switch_first_ready_process(s, regs);
 }
 
-pub fn print_str(s: &str) {
-    print!("{}", s)
+/// 读取 `spawn` 和 `exec` 的程序路径与参数
+fn read_program(
+    path: u64,
+    argv: u64,
+    argc: u64,
+) -> Result<(crate::drivers::OsFile, Vec<String>), Errno> {
+    if argc > MAX_ARGS {
+        return Err(Errno::E2BIG);
+    }
+    let path = read_str(UserPtr::new(path))?;
+    let file = crate::drivers::find_file(&path).ok_or(Errno::ENOENT)?;
+    let args = (0..argc)
+        .map(|i| {
+            read_str(UserPtr::new(
+                argv.wrapping_add(i * core::mem::size_of::<StrRef>() as u64),
+            ))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((file, args))
 }
 
-pub fn read_str(s: &mut Option<pc_keyboard::DecodedKey>) {
-    *s = crate::drivers::get_key();
+/// 从用户内存复制字符串
+fn read_str(ptr: UserPtr<StrRef>) -> Result<String, Errno> {
+    let s = ptr.read()?;
+    Ok(UserSlice::new(s.ptr, s.len as usize).as_str()?.to_string())
 }
 
-pub fn plot_pixel(x: usize, y: usize, color: u32) {
-    let _ = crate::display::get_display_sure().set_pixel(x, y, color);
+pub fn print_str(s: UserSlice) -> SyscallResult {
+    print!("{}", s.as_str()?);
+    Ok(s.len() as u64)
 }
 
-pub fn sleep(ns: u64) {
-    crate::uefi_clock::get_clock_sure().spin_wait_for_ns(ns as usize);
+pub fn plot_pixel(x: usize, y: usize, color: u32) -> SyscallResult {
+    crate::display::get_display_sure()
+        .set_pixel(x, y, color)
+        .map_err(|_| Errno::EINVAL)?;
+    Ok(0)
 }
 
-pub fn display_resolution(px: &mut u64, py: &mut u64) {
-    let (x, y) = crate::display::get_display_sure().resolution();
-    *px = x as u64;
-    *py = y as u64;
+/// 内核不能阻塞，只能忙等待
+pub fn sleep(ns: u64) -> SyscallResult {
+    crate::time::busy_wait(core::time::Duration::from_nanos(ns));
+    Ok(0)
 }
 
-pub fn allocate(layout: &core::alloc::Layout, ptr: &mut *mut u8) {
-    *ptr = crate::allocator::ALLOCATOR
-        .lock()
-        .allocate_first_fit(layout.clone())
-        .unwrap()
-        .as_ptr();
-    debug!("allocated {:x} => {:x}", ptr as *mut _ as u64, *ptr as u64);
+pub fn clock_gettime(clock: u64, ptr: UserPtr<Timespec>) -> SyscallResult {
+    let time = match clock {
+        syscall_abi::CLOCK_REALTIME => crate::time::realtime(),
+        syscall_abi::CLOCK_MONOTONIC => {
+            core::time::Duration::from_nanos(crate::time::Instant::now().as_nanos())
+        }
+        _ => return Err(Errno::EINVAL),
+    };
+    ptr.write(Timespec {
+        sec: time.as_secs(),
+        nsec: time.subsec_nanos() as u64,
+    })?;
+    Ok(0)
 }
 
-pub fn deallocate(ptr: *mut u8, layout: &core::alloc::Layout) {
-    unsafe {
-        crate::allocator::ALLOCATOR
-            .lock()
-            .deallocate(core::ptr::NonNull::new_unchecked(ptr), layout.clone());
-    }
-    debug!("deallocated {:x}", ptr as u64);
+pub fn display_resolution(ptr: UserPtr<Resolution>) -> SyscallResult {
+    let (width, height) = crate::display::get_display_sure().resolution();
+    ptr.write(Resolution {
+        width: width as u64,
+        height: height as u64,
+    })?;
+    Ok(0)
 }
 
-pub fn read_disk(id: u64, dst: &mut [u8]) {
+pub fn read_disk(id: u64, mut dst: UserSlice) -> SyscallResult {
     use crate::drivers::device;
 
+    let dst = dst.as_mut_slice()?;
+    let block_size = device().block_size().map_err(|_| Errno::EIO)?;
     device()
-        .read_block(id as usize, dst.len() / device().block_size().unwrap(), dst)
-        .unwrap()
+        .read_block(id as usize, dst.len() / block_size, dst)
+        .map_err(|_| Errno::EIO)?;
+    Ok(0)
+}
+
+/// 返回新的堆结束地址，参见 `process::brk`
+pub fn brk(addr: u64) -> SyscallResult {
+    Ok(crate::process::brk(addr))
 }
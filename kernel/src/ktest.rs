@@ -0,0 +1,658 @@
+//! 内核集成测试
+//!
+//! 内核命令行包含 `test` 时，`kmain` 在初始化完成后运行 `TESTS` 中的测试用例代替 shell，
+//! 结果输出到控制台（配合 `console=serial` 可在无界面的 QEMU 中查看），
+//! 最后通过 QEMU 的 `isa-debug-exit` 设备以通过/失败的退出码结束虚拟机。
+
+use crate::drivers::{find_file, OsFile};
+use crate::process::Process;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB, Translate,
+};
+use x86_64::VirtAddr;
+
+type TestResult = Result<(), &'static str>;
+
+/// 检查条件，失败时返回包含位置和条件的错误
+macro_rules! check {
+    ($cond:expr) => {
+        if !$cond {
+            return Err(concat!(file!(), ":", line!(), ": ", stringify!($cond)));
+        }
+    };
+}
+
+/// 测试用例列表
+const TESTS: &[(&str, fn() -> TestResult)] = &[
+    ("allocator", test_allocator),
+    ("frame_allocator", test_frame_allocator),
+    ("page_table", test_page_table),
+    ("fat_read", test_fat_read),
+    ("elf_load", test_elf_load),
+    ("syscall", test_syscall),
+    ("scheduler", test_scheduler),
+    ("time", test_time),
+    ("fpu", test_fpu),
+    ("kernel_stack", test_kernel_stack),
+    ("address_space", test_address_space),
+    ("stack_growth", test_stack_growth),
+    ("initial_stack", test_initial_stack),
+    ("kill", test_kill),
+    ("user_load", test_user_load),
+];
+
+/// `isa-debug-exit` 设备的 I/O 端口，需要 QEMU 参数
+/// `-device isa-debug-exit,iobase=0xf4,iosize=0x04`
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// QEMU 的退出码为 `(code << 1) | 1`，即通过为 33，失败为 35
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// 命令行中是否要求运行测试
+pub fn enabled(cmdline: &str) -> bool {
+    cmdline.split_whitespace().any(|arg| arg == "test")
+}
+
+/// 运行所有测试用例并退出 QEMU
+pub fn run() -> ! {
+    println!("running {} kernel tests", TESTS.len());
+    let mut failed = 0;
+    for (name, test) in TESTS {
+        match test() {
+            Ok(()) => println!("test {} ... ok", name),
+            Err(err) => {
+                println!("test {} ... FAILED\n    {}", name, err);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "test result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        TESTS.len() - failed,
+        failed
+    );
+
+    exit_qemu(if failed == 0 {
+        QemuExitCode::Success
+    } else {
+        QemuExitCode::Failed
+    });
+    // 不在 QEMU 中运行时退回到关机
+    crate::acpi::power_off();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+pub fn exit_qemu(code: QemuExitCode) {
+    unsafe {
+        Port::new(ISA_DEBUG_EXIT_PORT).write(code as u32);
+    }
+}
+
+fn test_allocator() -> TestResult {
+    use crate::allocator::{ALLOCATOR, HEAP_INITIAL_SIZE};
+
+    fn round() -> TestResult {
+        let boxed = Box::new(0x1234_5678_u64);
+        check!(*boxed == 0x1234_5678);
+
+        let mut v = Vec::new();
+        for i in 0..0x4000_u32 {
+            v.push(i);
+        }
+        check!(v.iter().enumerate().all(|(i, &x)| i as u32 == x));
+        Ok(())
+    }
+
+    // 第一轮可能为 slab 缓存取得新的页，之后的分配不应再占用更多内存
+    round()?;
+    let used = ALLOCATOR.stats().used;
+    round()?;
+    check!(ALLOCATOR.stats().used == used);
+
+    // 释放的小对象会被立即重用
+    let a = Box::new([0_u8; 48]);
+    let addr = &*a as *const _ as usize;
+    drop(a);
+    let b = Box::new([0_u8; 48]);
+    check!(&*b as *const _ as usize == addr);
+
+    // 超过初始大小的分配会扩展堆
+    let big = vec![0xa5_u8; HEAP_INITIAL_SIZE * 2];
+    check!(ALLOCATOR.stats().size > HEAP_INITIAL_SIZE * 2);
+    check!(big.iter().all(|&b| b == 0xa5));
+    Ok(())
+}
+
+fn test_frame_allocator() -> TestResult {
+    let mut alloc = crate::memory::get_frame_alloc_sure();
+    let free = alloc.stats().free;
+
+    let frame = alloc.allocate_frame().ok_or("out of frames")?;
+    check!(alloc.stats().free == free - 1);
+    unsafe { alloc.deallocate_frame(frame) };
+    check!(alloc.stats().free == free);
+
+    let frames = alloc.allocate_frames(16).ok_or("out of frames")?;
+    check!(frames.end - frames.start == 16);
+    check!(alloc.stats().free == free - 16);
+    // 释放后可以再次分配到同一段内存
+    let start = frames.start;
+    unsafe { alloc.deallocate_frames(frames) };
+    check!(alloc.stats().free == free);
+    let frames = alloc.allocate_frames(16).ok_or("out of frames")?;
+    check!(frames.start == start);
+    unsafe { alloc.deallocate_frames(frames) };
+    check!(alloc.allocate_frames(free + 1).is_none());
+    Ok(())
+}
+
+fn test_page_table() -> TestResult {
+    // 内核地址空间中未被使用的一页
+    const TEST_PAGE: u64 = 0xFFFF_FE00_0000_0000;
+
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(TEST_PAGE));
+    let frame = crate::memory::get_frame_alloc_sure()
+        .allocate_frame()
+        .ok_or("out of frames")?;
+    let mut page_table = crate::memory::get_page_table_sure();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    unsafe {
+        page_table
+            .map_to(
+                page,
+                frame,
+                flags,
+                &mut *crate::memory::get_frame_alloc_sure(),
+            )
+            .map_err(|_| "failed to map page")?
+            .flush();
+    }
+    check!(page_table.translate_addr(VirtAddr::new(TEST_PAGE)) == Some(frame.start_address()));
+
+    // 通过新映射写入，通过物理内存映射读出
+    let virt = TEST_PAGE as *mut u64;
+    let phys =
+        crate::memory::physical_to_virtual(frame.start_address().as_u64() as usize) as *const u64;
+    unsafe {
+        virt.write_volatile(0xdead_beef);
+        check!(phys.read_volatile() == 0xdead_beef);
+    }
+
+    page_table
+        .unmap(page)
+        .map_err(|_| "failed to unmap page")?
+        .1
+        .flush();
+    check!(page_table
+        .translate_addr(VirtAddr::new(TEST_PAGE))
+        .is_none());
+    Ok(())
+}
+
+/// 将文件读入内存
+fn load_file(file: &OsFile) -> Result<Vec<u8>, &'static str> {
+    let mut buf = vec![0; file.sectors().len() * 512];
+    file.load_to(&mut buf).map_err(|_| "failed to read file")?;
+    buf.truncate(file.entry.size as usize);
+    Ok(buf)
+}
+
+fn test_fat_read() -> TestResult {
+    let file = find_file("sampleio").ok_or("sampleio not found")?;
+    check!(file.entry.size > 0);
+    let buf = load_file(&file)?;
+    check!(buf.len() == file.entry.size as usize);
+    check!(buf.starts_with(b"\x7fELF"));
+    Ok(())
+}
+
+/// 加载 sampleio，`args` 不包括程序名
+fn load_sampleio(id: usize, args: Vec<String>, env: Vec<String>) -> Result<Process, &'static str> {
+    let file = find_file("sampleio").ok_or("sampleio not found")?;
+    crate::process::load_program(&file, id, 0, args, env).map_err(|_| "failed to load sampleio")
+}
+
+/// 检查 `frames` 都已归还给帧分配器
+///
+/// 只检查给定的帧，测试中内核堆扩展等其他分配不影响结果。
+fn check_frames_freed(frames: &[PhysFrame]) -> TestResult {
+    let frame_alloc = crate::memory::get_frame_alloc_sure();
+    check!(frames.iter().all(|&frame| frame_alloc.is_frame_free(frame)));
+    Ok(())
+}
+
+/// 销毁进程，检查它的帧（包括页表）全部归还
+fn drop_and_check(proc: Process) -> TestResult {
+    let frames = proc.owned_frames();
+    check!(!frames.is_empty());
+    drop(proc);
+    check_frames_freed(&frames)
+}
+
+/// 对不带参数的 sampleio 进程运行 `f`，之后销毁进程并检查它的帧全部归还
+fn with_sampleio(f: impl FnOnce(&mut Process) -> TestResult) -> TestResult {
+    let mut proc = load_sampleio(0x1000, Vec::new(), Vec::new())?;
+    f(&mut proc)?;
+    drop_and_check(proc)
+}
+
+fn test_elf_load() -> TestResult {
+    // 映射到独立的页表中，不影响内核页表
+    with_sampleio(|proc| {
+        let entry = proc.state_isf_mut().instruction_pointer;
+        check!(!entry.is_null());
+        check!(proc.page_table_mut().translate_addr(entry).is_some());
+        check!(proc.resident_pages() > 0);
+        Ok(())
+    })
+}
+
+fn test_scheduler() -> TestResult {
+    use crate::process::{MlfqScheduler, PriorityScheduler, RoundRobinScheduler, Scheduler};
+
+    // 位置 0 为内核伪进程，不会被调度器选中
+    let mut list: Vec<Process> = (0..4)
+        .map(|id| Process::new(&mut *crate::memory::get_frame_alloc_sure(), id))
+        .collect();
+
+    let mut rr = RoundRobinScheduler;
+    check!(rr.pick_next(&mut list, 1) == Some(2));
+    check!(rr.pick_next(&mut list, 3) == Some(1));
+    // 只选择就绪的进程
+    list[2].resume();
+    check!(rr.pick_next(&mut list, 1) == Some(3));
+    list[2].pause();
+
+    let mut priority = PriorityScheduler;
+    list[3].set_nice(-5);
+    check!(priority.pick_next(&mut list, 3) == Some(3));
+    check!(priority.time_slice(&list[3]) > priority.time_slice(&list[1]));
+    // nice 范围的两端，超出范围的值被截断
+    list[3].set_nice(i8::MIN);
+    check!(priority.time_slice(&list[3]) == 8);
+    list[3].set_nice(i8::MAX);
+    check!(priority.time_slice(&list[3]) == 1);
+    list[3].set_nice(-5);
+
+    // 用完时间片的进程降级，时间片变长
+    let mut mlfq = MlfqScheduler::new();
+    check!(mlfq.pick_next(&mut list, 0) == Some(1));
+    mlfq.expired(&mut list[1]);
+    check!(mlfq.pick_next(&mut list, 0) == Some(2));
+    check!(mlfq.time_slice(&list[1]) > mlfq.time_slice(&list[2]));
+    Ok(())
+}
+
+fn test_time() -> TestResult {
+    use crate::time::{Instant, TimerWheel};
+    use core::time::Duration;
+
+    let start = Instant::now();
+    crate::time::busy_wait(Duration::from_millis(20));
+    // 没有 HPET 时单调时钟的精度为一个时钟中断周期
+    check!(start.elapsed() >= Duration::from_millis(10));
+    check!(Instant::now() >= start);
+    // 墙上时间来自 UEFI，晚于 2017 年
+    check!(crate::time::realtime().as_secs() > 1_500_000_000);
+
+    // 超过一圈的定时器在之后的圈中到期
+    let mut wheel = TimerWheel::new(0);
+    wheel.insert(3, 'a');
+    wheel.insert(3 + 64, 'b');
+    wheel.insert(1, 'c');
+    check!(wheel.expire(2) == vec!['c']);
+    check!(wheel.expire(3) == vec!['a']);
+    check!(wheel.expire(66).is_empty());
+    check!(wheel.expire(200) == vec!['b']);
+    check!(wheel.is_empty());
+    Ok(())
+}
+
+fn test_fpu() -> TestResult {
+    use crate::fpu::FpuState;
+    use core::arch::asm;
+
+    // 内核不使用 SSE，测试中直接修改 `xmm0`
+    fn set_xmm0(value: u64) {
+        unsafe { asm!("movq xmm0, {}", in(reg) value, options(nomem, nostack)) };
+    }
+    fn xmm0() -> u64 {
+        let value;
+        unsafe { asm!("movq {}, xmm0", out(reg) value, options(nomem, nostack)) };
+        value
+    }
+
+    let mut state = FpuState::new();
+    set_xmm0(0x1234_5678_9abc_def0);
+    state.save();
+    set_xmm0(0);
+    state.restore();
+    check!(xmm0() == 0x1234_5678_9abc_def0);
+    // 新进程的寄存器为 0
+    FpuState::new().restore();
+    check!(xmm0() == 0);
+    Ok(())
+}
+
+fn test_syscall() -> TestResult {
+    use syscall_abi::Errno;
+
+    fn syscall(id: u64, arg0: u64, arg1: u64, arg2: u64) -> syscall_abi::SyscallResult {
+        let ret: u64;
+        unsafe {
+            core::arch::asm!("
+                push rbp
+                int {id}
+                pop rbp",
+                id = const 0x80,
+                inlateout("rax") id => ret,
+                in("rdi") arg0,
+                in("rsi") arg1,
+                in("rdx") arg2,
+            );
+        }
+        syscall_abi::decode(ret)
+    }
+
+    check!(syscall(crate::interrupts::Syscall::Sleep as u64, 0, 0, 0) == Ok(0));
+    let ret = syscall(crate::interrupts::Syscall::AbiVersion as u64, 0, 0, 0);
+    check!(ret == Ok(syscall_abi::ABI_VERSION));
+
+    // 内核内存、空指针以及越过用户地址空间的指针都不能被系统调用访问
+    let mut resolution = syscall_abi::Resolution::default();
+    let ret = syscall(
+        crate::interrupts::Syscall::DisplayResolution as u64,
+        &mut resolution as *mut _ as u64,
+        0,
+        0,
+    );
+    check!(ret == Err(Errno::EFAULT));
+    check!(resolution == syscall_abi::Resolution::default());
+    let ret = syscall(
+        crate::interrupts::Syscall::DisplayResolution as u64,
+        0,
+        0,
+        0,
+    );
+    check!(ret == Err(Errno::EFAULT));
+    let ret = syscall(
+        crate::interrupts::Syscall::PrintStr as u64,
+        0x0000_7FFF_FFFF_FFF8,
+        0,
+        0,
+    );
+    check!(ret == Err(Errno::EFAULT));
+
+    // 内核伪进程没有父进程和子进程，也不能退出
+    check!(syscall(crate::interrupts::Syscall::GetPid as u64, 0, 0, 0) == Ok(0));
+    let ret = syscall(crate::interrupts::Syscall::WaitPid as u64, 0x1234, 0, 0);
+    check!(ret == Err(Errno::ECHILD));
+    let ret = syscall(crate::interrupts::Syscall::ExitProcess as u64, 0, 0, 0);
+    check!(ret == Err(Errno::EPERM));
+    // 参数过多时在访问内存之前失败
+    let ret = syscall(crate::interrupts::Syscall::Spawn as u64, 0, 0, 0x1000);
+    check!(ret == Err(Errno::E2BIG));
+
+    // 未知的时钟在访问内存之前失败
+    let ret = syscall(crate::interrupts::Syscall::ClockGetTime as u64, 7, 0, 0);
+    check!(ret == Err(Errno::EINVAL));
+    let ret = syscall(
+        crate::interrupts::Syscall::ClockGetTime as u64,
+        syscall_abi::CLOCK_MONOTONIC,
+        0,
+        0,
+    );
+    check!(ret == Err(Errno::EFAULT));
+
+    // 未知的系统调用号
+    check!(syscall(0xdead, 0, 0, 0) == Err(Errno::ENOSYS));
+    Ok(())
+}
+
+fn test_kernel_stack() -> TestResult {
+    use crate::memory::{is_stack_guard, KernelStack};
+    use crate::time::Instant;
+    use core::time::Duration;
+
+    let new_stack = || KernelStack::new(4, &mut *crate::memory::get_frame_alloc_sure());
+    let free = || crate::memory::get_frame_alloc_sure().stats().free;
+    // 先分配一次，使页表中已有栈区域的中间页表
+    drop(new_stack().ok_or("out of frames")?);
+    let before = free();
+    let stack = new_stack().ok_or("out of frames")?;
+    check!(free() == before - 4);
+    {
+        let page_table = crate::memory::get_page_table_sure();
+        check!(page_table.translate_addr(stack.top() - 8u64).is_some());
+        // 栈下方是不映射的保护页
+        let guard = stack.top() - 5 * 0x1000u64;
+        check!(page_table.translate_addr(guard).is_none());
+        check!(is_stack_guard(guard));
+        check!(!is_stack_guard(stack.top() - 8u64));
+    }
+    drop(stack);
+    check!(free() == before);
+
+    // 内核伪进程在内核中睡眠，期间处理器空闲，到期后由时钟中断切换回来
+    let start = Instant::now();
+    x86_64::instructions::interrupts::without_interrupts(|| crate::process::sleep(20_000_000));
+    check!(start.elapsed() >= Duration::from_millis(10));
+    check!(crate::process::current_pid() == 0);
+    Ok(())
+}
+
+fn test_address_space() -> TestResult {
+    use crate::process::{Access, Fault};
+
+    let fault = |proc: &mut Process, addr: VirtAddr, access| {
+        proc.handle_page_fault(addr, access, &mut *crate::memory::get_frame_alloc_sure())
+    };
+    with_sampleio(|parent| {
+        // 低半部分不包含内核的恒等映射
+        check!(parent
+            .page_table_mut()
+            .translate_addr(VirtAddr::new(0x1000))
+            .is_none());
+
+        // 栈在首次访问时才分配，栈顶存放参数的页已经映射
+        let stack = parent.state_isf_mut().stack_pointer - 0x2000u64;
+        check!(parent.page_table_mut().translate_addr(stack).is_none());
+        let resident = parent.resident_pages();
+        check!(fault(parent, stack, Access::Write).is_ok());
+        check!(parent.resident_pages() == resident + 1);
+        let frame = parent
+            .page_table_mut()
+            .translate_addr(stack)
+            .ok_or("stack not mapped")?;
+        let byte = crate::memory::physical_to_virtual(frame.as_u64() as usize) as *mut u8;
+        unsafe { byte.write(0x5A) };
+
+        // 不属于任何区域的地址和违反权限的访问不能处理
+        check!(fault(parent, VirtAddr::new(0x10), Access::Read) == Err(Fault::Unmapped));
+        let entry = parent.state_isf_mut().instruction_pointer;
+        check!(fault(parent, entry, Access::Write) == Err(Fault::Protection));
+
+        // 子进程与父进程共享帧，写入时复制出私有的帧
+        let mut child = parent.fork(0x1001).map_err(|_| "failed to fork")?;
+        check!(child.resident_pages() == parent.resident_pages());
+        check!(child.page_table_mut().translate_addr(stack) == Some(frame));
+        check!(fault(&mut child, stack, Access::Write).is_ok());
+        let copy = child
+            .page_table_mut()
+            .translate_addr(stack)
+            .ok_or("stack not mapped")?;
+        check!(copy != frame);
+        check!(
+            unsafe { *(crate::memory::physical_to_virtual(copy.as_u64() as usize) as *const u8) }
+                == 0x5A
+        );
+        // 帧不再共享，父进程写入时不需要复制
+        check!(fault(parent, stack, Access::Write).is_ok());
+        check!(parent.page_table_mut().translate_addr(stack) == Some(frame));
+
+        // 子进程结束时只归还它私有的帧，与父进程共享的帧仍被父进程使用
+        let parent_frames = parent.owned_frames();
+        let child_frames = child.owned_frames();
+        drop(child);
+        check!(parent_frames
+            .iter()
+            .all(|frame| !crate::memory::get_frame_alloc_sure().is_frame_free(*frame)));
+        let private: Vec<_> = child_frames
+            .into_iter()
+            .filter(|frame| !parent_frames.contains(frame))
+            .collect();
+        check_frames_freed(&private)
+    })
+}
+
+fn test_stack_growth() -> TestResult {
+    use crate::process::{Access, Fault};
+
+    let fault = |proc: &mut Process, addr: u64| {
+        proc.handle_page_fault(
+            VirtAddr::new(addr),
+            Access::Write,
+            &mut *crate::memory::get_frame_alloc_sure(),
+        )
+    };
+    with_sampleio(|proc| {
+        // 栈顶随机选取，两个进程几乎不会相同；没有参数时栈顶的数据不超过一页
+        let sp = proc.state_isf_mut().stack_pointer.as_u64();
+        let mut other = load_sampleio(0x1001, Vec::new(), Vec::new())?;
+        check!(sp & 0xF == 0);
+        check!(sp != other.state_isf_mut().stack_pointer.as_u64());
+        drop_and_check(other)?;
+        let top = (sp + 0xFFF) & !0xFFF;
+
+        // 限制以内的访问使栈增长，保护页中的访问是栈溢出
+        let floor = top - crate::process::stack_limit();
+        check!(fault(proc, top - 8).is_ok());
+        check!(fault(proc, top - 0x100000).is_ok());
+        check!(fault(proc, floor).is_ok());
+        check!(fault(proc, floor - 8) == Err(Fault::StackOverflow));
+        check!(fault(proc, floor - 0x2000) == Err(Fault::Unmapped));
+        check!(proc.resident_pages() >= 3);
+        Ok(())
+    })
+}
+
+fn test_initial_stack() -> TestResult {
+    use syscall_abi::{AT_ENTRY, AT_NULL, AT_PAGESZ, AT_RANDOM};
+
+    let args = vec![String::from("a"), String::from("bc")];
+    let env = vec![String::from("X=1")];
+    let mut proc = load_sampleio(0x1000, args, env)?;
+    let sp = proc.state_isf_mut().stack_pointer.as_u64();
+    let entry = proc.state_isf_mut().instruction_pointer.as_u64();
+    check!(sp & 0xF == 0);
+
+    // 通过物理内存偏移映射读取新进程的内存
+    let page_table = &*proc.page_table_mut();
+    let read = |addr: u64| -> Result<u64, &'static str> {
+        let phys = page_table
+            .translate_addr(VirtAddr::new(addr))
+            .ok_or("stack not mapped")?;
+        Ok(unsafe { *(crate::memory::physical_to_virtual(phys.as_u64() as usize) as *const u64) })
+    };
+    let read_str = |addr: u64| -> Result<String, &'static str> {
+        let mut s = String::new();
+        for i in addr.. {
+            match read(i & !7)?.to_le_bytes()[(i & 7) as usize] {
+                0 => break,
+                b => s.push(b as char),
+            }
+        }
+        Ok(s)
+    };
+
+    check!(read(sp)? == 3);
+    check!(read_str(read(sp + 8)?)? == "sampleio");
+    check!(read_str(read(sp + 16)?)? == "a");
+    check!(read_str(read(sp + 24)?)? == "bc");
+    check!(read(sp + 32)? == 0);
+    check!(read_str(read(sp + 40)?)? == "X=1");
+    check!(read(sp + 48)? == 0);
+    let mut auxv = sp + 56;
+    let (mut pagesz, mut at_entry, mut random) = (0, 0, 0);
+    loop {
+        let (key, value) = (read(auxv)?, read(auxv + 8)?);
+        match key {
+            AT_NULL => break,
+            AT_PAGESZ => pagesz = value,
+            AT_ENTRY => at_entry = value,
+            AT_RANDOM => random = value,
+            _ => (),
+        }
+        auxv += 16;
+    }
+    check!(pagesz == 0x1000);
+    check!(at_entry == entry);
+    check!(random > sp && read(random).is_ok());
+    drop_and_check(proc)
+}
+
+fn test_kill() -> TestResult {
+    use crate::process::{kill, process_info, set_priority, spawn};
+    use syscall_abi::{Errno, PROCESS_READY};
+
+    let file = find_file("sampleio").ok_or("sampleio not found")?;
+    // 时钟中断会访问进程列表
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let pid = spawn(&file, Vec::new(), 0).map_err(|_| "failed to spawn sampleio")?;
+        let frames = crate::process::get_process_list_sure()
+            .iter()
+            .find(|p| p.id() == pid)
+            .map(Process::owned_frames)
+            .ok_or("process not spawned")?;
+        check!(set_priority(pid, -100).is_ok());
+        let info = process_info()
+            .into_iter()
+            .find(|p| p.pid == pid as u64)
+            .ok_or("process not listed")?;
+        check!(info.name() == "sampleio");
+        check!(info.parent == 0);
+        check!(info.state == PROCESS_READY);
+        check!(info.nice == -20);
+        check!(info.memory > 0);
+
+        // 父进程为内核时直接回收，内存全部释放
+        check!(kill(pid).is_ok());
+        check!(kill(pid) == Err(Errno::ESRCH));
+        check!(kill(0) == Err(Errno::EPERM));
+        check!(set_priority(0, 0) == Err(Errno::EPERM));
+        check!(set_priority(pid, 0) == Err(Errno::ESRCH));
+        check!(!process_info().iter().any(|p| p.pid == pid as u64));
+        check_frames_freed(&frames)
+    })
+}
+
+fn test_user_load() -> TestResult {
+    use x86_64::registers::control::Cr3;
+
+    // 用户进程的 `spawn` 和 `exec` 在它自己的页表下加载程序，低半部分没有恒等映射
+    with_sampleio(|parent| {
+        let (kernel_table, flags) = Cr3::read();
+        let child = x86_64::instructions::interrupts::without_interrupts(|| {
+            unsafe { Cr3::write(parent.page_table_frame(), flags) };
+            let child = load_sampleio(0x1001, vec![String::from("child")], Vec::new());
+            unsafe { Cr3::write(kernel_table, flags) };
+            child
+        })?;
+        check!(child.args() == ["child"]);
+        check!(child.resident_pages() > 0);
+        drop_and_check(child)
+    })
+}
@@ -1,6 +1,5 @@
 #![no_std]
 #![no_main]
-#![feature(type_alias_impl_trait)]
 #![feature(naked_functions)]
 #![feature(core_intrinsics)]
 #![feature(alloc_error_handler)]
@@ -26,17 +25,24 @@ mod macros;
 #[macro_use]
 mod console;
 
+mod acpi;
 mod allocator;
 mod apps;
 mod display;
 mod driver_holder;
 mod drivers;
+mod fpu;
 mod gdt;
 mod interrupts;
+mod ktest;
 // mod libm;
 mod logging;
 mod memory;
 mod process;
+mod random;
+mod serial;
+mod smp;
+mod time;
 mod uefi_clock;
 mod utils;
 
@@ -63,17 +69,40 @@ boot::entry_point!(kmain);
 pub fn kmain(boot_info: &'static BootInfo) -> ! {
     gdt::init();
 
+    // 初始化串口终端
+    if boot_info.console.serial {
+        serial::initialize();
+        println!("serial initialized");
+    }
+
     // 初始化显示驱动
     display::initialize(&boot_info.graphic_info);
     display::get_display_sure().clear();
 
     // 初始化图形终端
-    console::initialize();
-    println!("console initialized");
+    if boot_info.console.framebuffer {
+        console::initialize();
+        println!("console initialized");
+    }
 
     // 初始化日志系统
     logging::initialize();
     info!("logging initialized");
+    info!("cmdline: {:?}", boot_info.cmdline.as_str());
+    if boot_info.initramfs_size != 0 {
+        info!(
+            "initramfs at {:#x}, size {:#x}",
+            boot_info.initramfs_addr, boot_info.initramfs_size
+        );
+    }
+
+    // 解析 ACPI 表
+    unsafe {
+        acpi::init(boot_info.acpi_rsdp_addr);
+    }
+    if boot_info.smbios_addr != 0 {
+        info!("smbios entry point at {:#x}", boot_info.smbios_addr);
+    }
 
     // 初始化中断（CPU 异常、时钟）
     unsafe {
@@ -90,11 +119,14 @@ pub fn kmain(boot_info: &'static BootInfo) -> ! {
         uefi_clock::get_clock_sure().now()
     );
 
+    // 校准时钟中断，初始化单调时钟
+    time::init();
+
     // 初始化帧分配器
     unsafe {
         memory::init(
             VirtAddr::new_truncate(memory::PHYSICAL_OFFSET as u64),
-            &boot_info.memory_map,
+            boot_info,
         );
     }
     // 初始化堆内存
@@ -106,13 +138,25 @@ pub fn kmain(boot_info: &'static BootInfo) -> ! {
 
     info!("memory allocator initialized");
 
+    // 为双重错误等异常换用带保护页的栈
+    gdt::init_ist_stacks();
+
+    // 启动其他处理器
+    smp::init(boot_info);
+
     // 初始化键盘驱动
     unsafe {
         drivers::keyboard::init();
     }
 
+    // 允许用户程序使用 SSE
+    fpu::init();
+
+    // 设置随机数种子，用于随机化用户栈的位置
+    random::init();
+
     // 初始化进程管理
-    process::init();
+    process::init(&boot_info.cmdline);
 
     // 开中断
     x86_64::instructions::interrupts::enable();
@@ -129,10 +173,16 @@ pub fn kmain(boot_info: &'static BootInfo) -> ! {
         drivers::filesystem::init();
     }
 
-    let exit_code = apps::shell_main(boot_info);
+    if ktest::enabled(&boot_info.cmdline) {
+        ktest::run();
+    }
+
+    let exit_code = apps::shell_main();
     info!("init process exit = {}, shutdown in 5s", exit_code);
     uefi_clock::get_clock_sure().spin_wait_for_ns(5_000_000_000);
 
+    acpi::power_off();
+
     unsafe {
         boot_info.system_table.runtime_services().reset(
             boot::ResetType::Shutdown,
@@ -0,0 +1,292 @@
+//! 基于位图的物理帧分配器
+//!
+//! 每个物理帧对应位图中的一位，置位表示空闲。位图本身存放在第一段足够大的可用内存中。
+//!
+//! 除 `CONVENTIONAL` 内存外，还会回收引导阶段使用过的 `BOOT_SERVICES_*` 与 `LOADER_DATA` 内存。
+//! 这些区域中仍有内核需要的内容：UEFI 与引导程序创建的页表、内核 ELF 段和内核栈、
+//! 位于 UEFI 栈上的 `BootInfo` 以及 initramfs。回收时会遍历当前页表，
+//! 保留所有页表帧以及内核地址空间中（物理内存映射以外）被映射的帧，并保留显式给出的区域。
+
+use boot::{MemoryMap, MemoryType};
+use core::ops::Range;
+use x86_64::structures::paging::page_table::PageTableLevel;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::PhysAddr;
+
+const FRAME_SIZE: u64 = 4096;
+const BITS: usize = u64::BITS as usize;
+
+/// 物理帧使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    /// 可管理的帧总数
+    pub total: usize,
+    /// 空闲帧数
+    pub free: usize,
+    /// 从引导阶段内存中回收的帧数
+    pub reclaimed: usize,
+}
+
+impl FrameStats {
+    pub fn used(&self) -> usize {
+        self.total - self.free
+    }
+}
+
+pub struct BitmapFrameAllocator {
+    /// 置位表示对应的帧空闲
+    bitmap: &'static mut [u64],
+    /// 下次查找的起始位置
+    next: usize,
+    /// 引导程序映射到 `PHYSICAL_OFFSET` 的物理内存大小
+    phys_map_size: u64,
+    stats: FrameStats,
+}
+
+/// 引导完成后可以回收的内存类型
+fn is_reclaimable(ty: MemoryType) -> bool {
+    matches!(
+        ty,
+        MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA | MemoryType::LOADER_DATA
+    )
+}
+
+fn frame_index(addr: u64) -> usize {
+    (addr / FRAME_SIZE) as usize
+}
+
+impl BitmapFrameAllocator {
+    /// 根据内存映射创建帧分配器，并回收引导阶段的内存
+    ///
+    /// # Safety
+    ///
+    /// 物理内存需要映射到 `PHYSICAL_OFFSET`，内存映射需要是有效的，
+    /// 且 `reserved` 包含了页表之外内核仍然需要的所有可回收内存。
+    pub unsafe fn init(memory_map: &'static MemoryMap, reserved: &[Range<u64>]) -> Self {
+        let usable = || {
+            memory_map
+                .iter
+                .iter()
+                .filter(|r| r.ty == MemoryType::CONVENTIONAL || is_reclaimable(r.ty))
+        };
+        let frames = usable()
+            .map(|r| frame_index(r.phys_start) + r.page_count as usize)
+            .max()
+            .unwrap_or(0);
+        let words = (frames + BITS - 1) / BITS;
+
+        // 1. 将位图放在第一段足够大的空闲内存中
+        let bitmap_frames = ((words * 8) as u64 + FRAME_SIZE - 1) / FRAME_SIZE;
+        let bitmap_region = memory_map
+            .iter
+            .iter()
+            .find(|r| r.ty == MemoryType::CONVENTIONAL && r.page_count >= bitmap_frames)
+            .expect("no memory for frame bitmap");
+        let bitmap = unsafe {
+            core::slice::from_raw_parts_mut(
+                super::physical_to_virtual(bitmap_region.phys_start as usize) as *mut u64,
+                words,
+            )
+        };
+        bitmap.fill(0);
+
+        let mut alloc = Self {
+            bitmap,
+            next: 0,
+            // 与引导程序相同，包括 4GiB 以下的 MMIO 区域
+            phys_map_size: memory_map
+                .iter
+                .iter()
+                .map(|r| r.phys_start + r.page_count * FRAME_SIZE)
+                .max()
+                .unwrap_or(0)
+                .max(0x1_0000_0000),
+            stats: FrameStats {
+                total: 0,
+                free: 0,
+                reclaimed: 0,
+            },
+        };
+
+        // 2. 标记空闲内存，暂时包括所有可回收内存
+        for region in usable() {
+            let start = frame_index(region.phys_start);
+            alloc.set_range(start..start + region.page_count as usize, true);
+        }
+
+        // 3. 保留位图、页表及仍在使用的内存
+        let bitmap_start = frame_index(bitmap_region.phys_start);
+        alloc.set_range(bitmap_start..bitmap_start + bitmap_frames as usize, false);
+        // 物理地址 0 容易与空指针混淆，不分配
+        alloc.set_range(0..1, false);
+        let (l4_frame, _) = x86_64::registers::control::Cr3::read();
+        unsafe { alloc.reserve_page_table(l4_frame, PageTableLevel::Four, 0) };
+        for range in reserved {
+            let start = frame_index(range.start);
+            let end = frame_index(range.end + FRAME_SIZE - 1);
+            alloc.set_range(start..end, false);
+        }
+
+        // 4. 统计
+        alloc.stats.total = usable().map(|r| r.page_count as usize).sum();
+        alloc.stats.free = alloc.count_free();
+        let conventional_free = memory_map
+            .iter
+            .iter()
+            .filter(|r| r.ty == MemoryType::CONVENTIONAL)
+            .flat_map(|r| {
+                let start = frame_index(r.phys_start);
+                start..start + r.page_count as usize
+            })
+            .filter(|&i| alloc.is_free(i))
+            .count();
+        alloc.stats.reclaimed = alloc.stats.free - conventional_free;
+        alloc
+    }
+
+    /// 保留页表帧，以及内核地址空间中物理内存映射以外被映射的帧
+    ///
+    /// 低半部分是 UEFI 的恒等映射，它和物理内存映射一样映射了全部物理内存，
+    /// 其中的帧不代表正在被使用。
+    unsafe fn reserve_page_table(&mut self, frame: PhysFrame, level: PageTableLevel, base: u64) {
+        let index = frame_index(frame.start_address().as_u64());
+        self.set_range(index..index + 1, false);
+        let table = unsafe {
+            &*(super::physical_to_virtual(frame.start_address().as_u64() as usize)
+                as *const PageTable)
+        };
+        let entry_size = level.entry_address_space_alignment();
+        for (i, entry) in table.iter().enumerate() {
+            if !entry.flags().contains(PageTableFlags::PRESENT) {
+                continue;
+            }
+            let mut addr = base + i as u64 * entry_size;
+            if level == PageTableLevel::Four && i >= 256 {
+                // 高半部分的规范地址需要符号扩展
+                addr |= 0xFFFF_0000_0000_0000;
+            }
+            let is_leaf =
+                level == PageTableLevel::One || entry.flags().contains(PageTableFlags::HUGE_PAGE);
+            if !is_leaf {
+                let next = level.next_lower_level().unwrap();
+                let frame = PhysFrame::containing_address(entry.addr());
+                unsafe { self.reserve_page_table(frame, next, addr) };
+            } else if addr >= 0xFFFF_8000_0000_0000
+                && !(super::PHYSICAL_OFFSET..super::PHYSICAL_OFFSET + self.phys_map_size)
+                    .contains(&addr)
+            {
+                let start = frame_index(entry.addr().as_u64());
+                self.set_range(start..start + (entry_size / FRAME_SIZE) as usize, false);
+            }
+        }
+    }
+
+    fn is_free(&self, index: usize) -> bool {
+        self.bitmap
+            .get(index / BITS)
+            .map_or(false, |word| word & (1 << (index % BITS)) != 0)
+    }
+
+    /// 设置一段帧的状态，超出位图的部分被忽略
+    fn set_range(&mut self, range: Range<usize>, free: bool) {
+        let end = range.end.min(self.bitmap.len() * BITS);
+        for index in range.start..end {
+            if free {
+                self.bitmap[index / BITS] |= 1 << (index % BITS);
+            } else {
+                self.bitmap[index / BITS] &= !(1 << (index % BITS));
+            }
+        }
+    }
+
+    fn count_free(&self) -> usize {
+        self.bitmap.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// 分配 `count` 个物理地址连续的帧
+    pub fn allocate_frames(&mut self, count: usize) -> Option<Range<PhysFrame>> {
+        if count == 0 || count > self.stats.free {
+            return None;
+        }
+        let frames = self.bitmap.len() * BITS;
+        let mut start = 0;
+        let mut len = 0;
+        let mut index = if count == 1 { self.next } else { 0 };
+        let mut scanned = 0;
+        while scanned < frames {
+            if index >= frames {
+                // 单帧分配从 `next` 开始查找，需要回绕
+                index = 0;
+                len = 0;
+            }
+            if len == 0 && index % BITS == 0 && self.bitmap[index / BITS] == 0 {
+                // 跳过全部已用的字
+                index += BITS;
+                scanned += BITS;
+                continue;
+            }
+            if self.is_free(index) {
+                if len == 0 {
+                    start = index;
+                }
+                len += 1;
+                if len == count {
+                    self.set_range(start..start + count, false);
+                    self.stats.free -= count;
+                    self.next = start + count;
+                    let frame = |i: usize| {
+                        PhysFrame::containing_address(PhysAddr::new(i as u64 * FRAME_SIZE))
+                    };
+                    return Some(frame(start)..frame(start + count));
+                }
+            } else {
+                len = 0;
+            }
+            index += 1;
+            scanned += 1;
+        }
+        None
+    }
+
+    /// 释放一段由 `allocate_frames` 分配的帧
+    ///
+    /// # Safety
+    ///
+    /// 帧不能再被使用
+    pub unsafe fn deallocate_frames(&mut self, frames: Range<PhysFrame>) {
+        let start = frame_index(frames.start.start_address().as_u64());
+        let end = frame_index(frames.end.start_address().as_u64());
+        for index in start..end {
+            assert!(
+                index < self.bitmap.len() * BITS && !self.is_free(index),
+                "double free or invalid frame {:#x}",
+                index as u64 * FRAME_SIZE
+            );
+        }
+        self.set_range(start..end, true);
+        self.stats.free += end - start;
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// 帧是否空闲
+    pub fn is_frame_free(&self, frame: PhysFrame) -> bool {
+        self.is_free(frame_index(frame.start_address().as_u64()))
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate_frames(1).map(|frames| frames.start)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        unsafe { self.deallocate_frames(frame..frame + 1) };
+    }
+}
@@ -0,0 +1,153 @@
+//! 带保护页的内核栈
+//!
+//! 内核栈分配在 `KERNEL_STACK_START` 开始的区域中，每个栈占用 `SLOT_PAGES` 页的虚拟地址，
+//! 最低的一页不映射作为保护页，栈溢出时产生缺页异常而不是覆盖相邻的内存。
+//! 该区域与内核代码位于同一个顶级页表项下，新建的映射在所有进程的页表中都可见。
+
+use super::BitmapFrameAllocator;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+/// 内核栈区域的起始地址
+pub const KERNEL_STACK_START: u64 = 0xFFFF_FF40_0000_0000;
+/// 内核栈区域的结束地址，之后为内核堆
+const KERNEL_STACK_END: u64 = 0xFFFF_FF80_0000_0000;
+/// 每个栈占用的虚拟页数，包括保护页
+const SLOT_PAGES: u64 = 16;
+/// 单个内核栈的最大页数
+pub const MAX_STACK_PAGES: u64 = SLOT_PAGES - 1;
+
+struct Slots {
+    /// 从未使用过的第一个槽
+    next: u64,
+    /// 已经释放的槽
+    free: Vec<u64>,
+    /// 释放时仍在使用的栈，之后分配新栈时再回收
+    deferred: Vec<(u64, u64)>,
+}
+
+static SLOTS: Mutex<Slots> = Mutex::new(Slots {
+    next: 0,
+    free: Vec::new(),
+    deferred: Vec::new(),
+});
+
+/// 映射在内核栈区域中的一个栈，释放时取消映射并归还帧
+#[derive(Debug)]
+pub struct KernelStack {
+    slot: u64,
+    pages: u64,
+}
+
+impl KernelStack {
+    /// 分配 `pages` 页的栈，帧不足时返回 `None`
+    pub fn new(pages: u64, frame_alloc: &mut BitmapFrameAllocator) -> Option<Self> {
+        assert!(
+            (1..=MAX_STACK_PAGES).contains(&pages),
+            "invalid kernel stack size"
+        );
+        let slot = {
+            let mut slots = SLOTS.lock();
+            // 回收已经不再使用的栈，当前正在使用的栈仍然保留
+            let sp = stack_pointer();
+            let mut i = 0;
+            while i < slots.deferred.len() {
+                let (slot, pages) = slots.deferred[i];
+                if stack_range(slot, pages).contains(&sp) {
+                    i += 1;
+                    continue;
+                }
+                slots.deferred.swap_remove(i);
+                unsafe { unmap(slot, frame_alloc) };
+                slots.free.push(slot);
+            }
+            match slots.free.pop() {
+                Some(slot) => slot,
+                None if stack_range(slots.next, 1).end <= KERNEL_STACK_END => {
+                    slots.next += 1;
+                    slots.next - 1
+                }
+                None => return None,
+            }
+        };
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let mut page_table = super::get_page_table_sure();
+        for page in stack_pages(slot, pages) {
+            let mapped = frame_alloc.allocate_frame().and_then(|frame| unsafe {
+                match page_table.map_to(page, frame, flags, frame_alloc) {
+                    Ok(flush) => Some(flush.flush()),
+                    Err(_) => {
+                        frame_alloc.deallocate_frame(frame);
+                        None
+                    }
+                }
+            });
+            if mapped.is_none() {
+                drop(page_table);
+                unsafe { unmap(slot, frame_alloc) };
+                SLOTS.lock().free.push(slot);
+                return None;
+            }
+        }
+        Some(Self { slot, pages })
+    }
+
+    /// 栈顶，即栈所占内存的结束地址
+    pub fn top(&self) -> VirtAddr {
+        VirtAddr::new(stack_range(self.slot, self.pages).end)
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        // 进程结束时仍在自己的内核栈上执行，此时只能推迟释放
+        if stack_range(self.slot, self.pages).contains(&stack_pointer()) {
+            SLOTS.lock().deferred.push((self.slot, self.pages));
+            return;
+        }
+        unsafe { unmap(self.slot, &mut *super::get_frame_alloc_sure()) };
+        SLOTS.lock().free.push(self.slot);
+    }
+}
+
+/// `addr` 是否位于某个内核栈的保护页中，用于在异常中识别内核栈溢出
+pub fn is_stack_guard(addr: VirtAddr) -> bool {
+    // 可能在持有锁时发生异常，因此不访问 `SLOTS`
+    (KERNEL_STACK_START..KERNEL_STACK_END).contains(&addr.as_u64())
+        && (addr.as_u64() - KERNEL_STACK_START) % (SLOT_PAGES * 0x1000) < 0x1000
+}
+
+/// 栈从槽的末尾向下占用 `pages` 页，其下的页均不映射
+fn stack_range(slot: u64, pages: u64) -> core::ops::Range<u64> {
+    let end = KERNEL_STACK_START + (slot + 1) * SLOT_PAGES * 0x1000;
+    end - pages * 0x1000..end
+}
+
+fn stack_pages(slot: u64, pages: u64) -> impl Iterator<Item = Page<Size4KiB>> {
+    let range = stack_range(slot, pages);
+    Page::range(
+        Page::containing_address(VirtAddr::new(range.start)),
+        Page::containing_address(VirtAddr::new(range.end)),
+    )
+}
+
+/// 取消槽中所有已映射的页并释放帧
+unsafe fn unmap(slot: u64, frame_alloc: &mut BitmapFrameAllocator) {
+    let mut page_table = super::get_page_table_sure();
+    for page in stack_pages(slot, MAX_STACK_PAGES) {
+        if let Ok((frame, flush)) = page_table.unmap(page) {
+            flush.flush();
+            unsafe { frame_alloc.deallocate_frame(frame) };
+        }
+    }
+}
+
+fn stack_pointer() -> u64 {
+    let sp: u64;
+    unsafe { core::arch::asm!("mov {}, rsp", out(reg) sp, options(nomem, nostack)) };
+    sp
+}
@@ -1,13 +1,22 @@
 // This is from https://github.com/phil-opp/blog_os/blob/post-09/src/memory.rs
 
-use boot::{MemoryMap, MemoryType};
-use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB};
-use x86_64::{PhysAddr, VirtAddr};
+mod frame_allocator;
+mod kernel_stack;
+mod user;
+
+pub use frame_allocator::{BitmapFrameAllocator, FrameStats};
+pub use kernel_stack::{is_stack_guard, KernelStack};
+pub use user::{UserPtr, UserSlice};
+
+use boot::BootInfo;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{OffsetPageTable, PageTable, PhysFrame};
+use x86_64::VirtAddr;
 
 pub const PHYSICAL_OFFSET: u64 = 0xFFFF800000000000;
 
 once_mutex!(pub OFFSET_PAGE_TABLE: OffsetPageTable<'static>);
-once_mutex!(pub FRAME_ALLOCATOR: BootInfoFrameAllocator);
+once_mutex!(pub FRAME_ALLOCATOR: BitmapFrameAllocator);
 
 guard_access_fn! {
     #[doc = "当前页表"]
@@ -16,12 +25,35 @@ guard_access_fn! {
 
 guard_access_fn! {
     #[doc = "物理内存帧分配器"]
-    pub get_frame_alloc(FRAME_ALLOCATOR: BootInfoFrameAllocator)
+    pub get_frame_alloc(FRAME_ALLOCATOR: BitmapFrameAllocator)
+}
+
+static KERNEL_PAGE_TABLE: spin::Once<PhysFrame> = spin::Once::new();
+
+/// 内核的顶级页表所在的帧，新进程的页表从中复制
+pub fn kernel_page_table() -> PhysFrame {
+    *KERNEL_PAGE_TABLE.get().unwrap()
 }
 
-pub unsafe fn init(physical_memory_offset: VirtAddr, memory_map: &'static MemoryMap) {
+pub unsafe fn init(physical_memory_offset: VirtAddr, boot_info: &'static BootInfo) {
     init_OFFSET_PAGE_TABLE(unsafe { inner_init(physical_memory_offset) });
-    init_FRAME_ALLOCATOR(unsafe { BootInfoFrameAllocator::init(memory_map) });
+    KERNEL_PAGE_TABLE.call_once(|| Cr3::read().0);
+
+    // 页表之外仍需保留的引导阶段内存：位于 UEFI 栈上的 BootInfo 和 initramfs
+    let boot_info_addr = boot_info as *const BootInfo as u64;
+    let reserved = [
+        boot_info_addr..boot_info_addr + core::mem::size_of::<BootInfo>() as u64,
+        boot_info.initramfs_addr..boot_info.initramfs_addr + boot_info.initramfs_size,
+    ];
+    let frame_alloc = unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, &reserved) };
+    let stats = frame_alloc.stats();
+    info!(
+        "frame allocator: {} MiB free of {} MiB, {} MiB reclaimed",
+        stats.free / 256,
+        stats.total / 256,
+        stats.reclaimed / 256
+    );
+    init_FRAME_ALLOCATOR(frame_alloc);
 }
 
 /// Initialize a new OffsetPageTable.
@@ -46,8 +78,6 @@ pub fn physical_to_virtual(addr: usize) -> usize {
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoid aliasing `&mut` references (which is undefined behavior).
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
-    use x86_64::registers::control::Cr3;
-
     let (level_4_table_frame, _) = Cr3::read();
 
     let phys = level_4_table_frame.start_address();
@@ -56,41 +86,3 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
 
     unsafe { &mut *page_table_ptr }
 }
-
-type BootInfoFrameIter = impl Iterator<Item = PhysFrame>;
-
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-pub struct BootInfoFrameAllocator {
-    frames: BootInfoFrameIter,
-}
-
-fn create_frame_iter(memory_map: &'static MemoryMap) -> BootInfoFrameIter {
-    memory_map
-        .iter
-        .iter()
-        // 获得可用内存列表
-        .filter(|r| r.ty == MemoryType::CONVENTIONAL)
-        // 转换为段内可用起始地址列表（4KiB对齐）
-        .flat_map(|r| (0..r.page_count).map(move |v| (v * 4096 + r.phys_start)))
-        // 创建 `PhysFrame` 类型
-        .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-}
-
-impl BootInfoFrameAllocator {
-    /// Create a FrameAllocator from the passed memory map.
-    ///
-    /// This function is unsafe because the caller must guarantee that the passed
-    /// memory map is valid. The main requirement is that all frames that are marked
-    /// as `USABLE` in it are really unused.
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> BootInfoFrameAllocator {
-        BootInfoFrameAllocator {
-            frames: create_frame_iter(memory_map),
-        }
-    }
-}
-
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        self.frames.next()
-    }
-}
@@ -0,0 +1,141 @@
+//! 系统调用访问用户内存的接口
+//!
+//! 用户程序传入的指针在使用前需要检查：地址范围位于用户地址空间，且每一页都在当前页表中
+//! 以 `USER_ACCESSIBLE`（写入时还需要 `WRITABLE`）映射。尚未分配的页和写时复制的页在检查时
+//! 由进程的地址空间处理，仍然无法访问时返回 `EFAULT`。
+//! 系统调用执行期间中断关闭，且只有 BSP 运行用户程序，检查之后页表不会改变，
+//! 因此通过检查的访问不会产生缺页。
+
+use crate::process::Access;
+use core::marker::PhantomData;
+use syscall_abi::Errno;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+/// 用户地址空间的上界（不含）
+const USER_END: u64 = 0x0000_8000_0000_0000;
+
+/// 指向用户内存中一个 `T` 的指针
+#[derive(Debug, Clone, Copy)]
+pub struct UserPtr<T> {
+    addr: u64,
+    _marker: PhantomData<*mut T>,
+}
+
+/// 用户内存中的一段字节
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    addr: u64,
+    len: usize,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(addr: u64) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 从用户内存复制出一个值
+    pub fn read(&self) -> Result<T, Errno> {
+        check_range(self.addr, core::mem::size_of::<T>(), false)?;
+        Ok(unsafe { (self.addr as *const T).read_unaligned() })
+    }
+
+    /// 将值复制到用户内存
+    pub fn write(&self, value: T) -> Result<(), Errno> {
+        check_range(self.addr, core::mem::size_of::<T>(), true)?;
+        unsafe { (self.addr as *mut T).write_unaligned(value) };
+        Ok(())
+    }
+}
+
+impl UserSlice {
+    pub fn new(addr: u64, len: usize) -> Self {
+        Self { addr, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 检查可读后作为切片访问
+    pub fn as_slice(&self) -> Result<&[u8], Errno> {
+        if self.len == 0 {
+            return Ok(&[]);
+        }
+        check_range(self.addr, self.len, false)?;
+        Ok(unsafe { core::slice::from_raw_parts(self.addr as *const u8, self.len) })
+    }
+
+    /// 检查可写后作为切片访问
+    pub fn as_mut_slice(&mut self) -> Result<&mut [u8], Errno> {
+        if self.len == 0 {
+            return Ok(&mut []);
+        }
+        check_range(self.addr, self.len, true)?;
+        Ok(unsafe { core::slice::from_raw_parts_mut(self.addr as *mut u8, self.len) })
+    }
+
+    /// 作为 UTF-8 字符串访问，内容无效时返回 `EINVAL`
+    pub fn as_str(&self) -> Result<&str, Errno> {
+        core::str::from_utf8(self.as_slice()?).map_err(|_| Errno::EINVAL)
+    }
+}
+
+/// 检查 `addr..addr + len` 是否可以被用户态访问
+fn check_range(addr: u64, len: usize, write: bool) -> Result<(), Errno> {
+    let end = addr.checked_add(len as u64).ok_or(Errno::EFAULT)?;
+    if addr == 0 || end > USER_END {
+        return Err(Errno::EFAULT);
+    }
+    let mut page = addr & !0xFFF;
+    while page < end {
+        let accessible = page_flags(VirtAddr::new(page)).map_or(false, |flags| {
+            flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                && (!write || flags.contains(PageTableFlags::WRITABLE))
+        });
+        if !accessible {
+            // 按需分配或写时复制的页由进程的地址空间处理，内核访问时不会再产生缺页
+            let access = if write { Access::Write } else { Access::Read };
+            crate::process::handle_page_fault(VirtAddr::new(page), access)
+                .map_err(|_| Errno::EFAULT)?;
+        }
+        page += 0x1000;
+    }
+    Ok(())
+}
+
+/// 在当前页表中查找 `addr` 所在的页，返回各级页表项中 `USER_ACCESSIBLE` 与 `WRITABLE` 的交集
+///
+/// 页未被映射时返回 `None`
+fn page_flags(addr: VirtAddr) -> Option<PageTableFlags> {
+    let table_at =
+        |phys: u64| unsafe { &*(super::physical_to_virtual(phys as usize) as *const PageTable) };
+    let mut table = table_at(Cr3::read().0.start_address().as_u64());
+    let mut flags = PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE;
+    let indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+    for (level, &index) in indexes.iter().enumerate() {
+        let entry = &table[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        flags &= entry.flags();
+        if level == indexes.len() - 1 || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Some(flags);
+        }
+        table = table_at(entry.addr().as_u64());
+    }
+    None
+}
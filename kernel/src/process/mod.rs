@@ -0,0 +1,1239 @@
+mod scheduler;
+mod vm;
+mod wait_queue;
+
+pub use scheduler::{
+    MlfqScheduler, PriorityScheduler, RoundRobinScheduler, SchedInfo, Scheduler, MAX_NICE, MIN_NICE,
+};
+pub use vm::{Access, Fault};
+pub use wait_queue::WaitQueue;
+
+use vm::{AddressSpace, VmFlags, Vma, VmaKind, COPY_ON_WRITE};
+
+use crate::{
+    drivers::OsFile,
+    fpu::FpuState,
+    interrupts::Registers,
+    memory::{physical_to_virtual, BitmapFrameAllocator, KernelStack},
+    time::{Instant, TimerWheel},
+};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use syscall_abi::{
+    Errno, ProcessInfo, AT_ENTRY, AT_NULL, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_RANDOM,
+};
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::{InterruptStackFrame, InterruptStackFrameValue};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+    PhysFrame, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+use xmas_elf::{program, ElfFile};
+
+/// 用户堆的起始地址
+pub const USER_HEAP_START: u64 = 0x0000_1000_0000_0000;
+/// 用户堆的最大大小
+pub const USER_HEAP_MAX_SIZE: u64 = 0x4000_0000; // 1 GiB
+/// 进程内核栈的页数，用户态发生中断或系统调用时使用
+const KERNEL_STACK_PAGES: u64 = 4;
+/// 用户栈所在的区域，每个进程的栈顶在其中随机选取，栈向下增长不会超出区域
+const STACK_REGION_START: u64 = 0x0000_2000_0000_0000;
+const STACK_REGION_END: u64 = 0x0000_3000_0000_0000;
+/// 用户栈初始的大小，访问下方的地址时自动增长
+const STACK_INITIAL_PAGES: u64 = 16;
+/// 用户栈的默认大小限制，可以通过内核命令行的 `stack_limit=<KiB>` 修改
+const DEFAULT_STACK_LIMIT: u64 = 8 * 1024 * 1024;
+/// 用户栈大小限制的范围
+const STACK_LIMIT_RANGE: core::ops::RangeInclusive<u64> =
+    STACK_INITIAL_PAGES * 0x1000..=0x4000_0000; // 1 GiB
+
+/// 内核伪进程的环境变量
+const DEFAULT_ENV: &[&str] = &["PATH=/", "HOME=/"];
+
+/// 新进程的用户栈大小限制
+static STACK_LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_STACK_LIMIT);
+
+/// 下一个进程号，0 为内核伪进程
+static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+/// 按下 Ctrl+C 后置位，下一次调度时回到内核伪进程中的 shell
+static SHELL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub struct Process {
+    id: usize,
+    /// 父进程号，父进程结束后变为内核伪进程 0
+    parent: usize,
+    name: String,
+    /// 程序名之后的参数
+    args: Vec<String>,
+    /// `NAME=value` 形式的环境变量，创建子进程时继承
+    env: Vec<String>,
+    state: ProcessState,
+    /// 阻塞时等待结束的子进程
+    waiting_for: Option<usize>,
+    sched: SchedInfo,
+    state_isf: InterruptStackFrameValue,
+    state_reg: Registers,
+    /// x87 FPU 与 SSE 寄存器，在切换进程时保存和恢复
+    fpu: Box<FpuState>,
+    /// 页表所处地址
+    page_table_addr: (PhysFrame, Cr3Flags),
+    /// 若非内核进程，则具备独立页表及其控制，否则没有
+    page_table: Option<OffsetPageTable<'static>>, // 实际生命周期和 Process 一致
+    /// 用户地址空间中的区域
+    space: AddressSpace,
+    /// 用户堆的结束地址，堆占用 `USER_HEAP_START..heap_end`
+    heap_end: u64,
+    /// 运行过的时钟中断数
+    cpu_ticks: u64,
+    /// 内核栈，切换到该进程时设置为 `TSS.privilege_stack_table[0]` 和 `SYSCALL` 使用的栈，
+    /// 进程在系统调用中阻塞时，内核态的调用栈保留在这里
+    kernel_stack: KernelStack,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProcessState {
+    Ready,
+    Running,
+    /// 等待子进程结束，或在等待队列中
+    Blocked,
+    /// 睡眠到给定的时间
+    Sleeping(Instant),
+    /// 已经结束，保留退出码直到父进程回收
+    Zombie(i32),
+}
+
+impl Process {
+    pub fn new(frame_alloc: &mut BitmapFrameAllocator, id: usize) -> Self {
+        // 1. 为进程创建新的页表
+        let new_frame = frame_alloc
+            .allocate_frame()
+            .expect("cannot alloc page table for new process");
+        // 1.1. 低半部分留空给用户地址空间
+        let page_table_raw = unsafe {
+            &mut *(physical_to_virtual(new_frame.start_address().as_u64() as usize)
+                as *mut PageTable)
+        };
+        page_table_raw.zero();
+        // 1.2. 只复制内核页表高半部分的顶级页表项，低半部分的恒等映射不复制，
+        // 新进程可能由其他用户进程创建，不能复制当前页表
+        let kernel_table = unsafe {
+            &*(physical_to_virtual(
+                crate::memory::kernel_page_table().start_address().as_u64() as usize
+            ) as *const PageTable)
+        };
+        for (entry, kernel_entry) in page_table_raw.iter_mut().zip(kernel_table.iter()).skip(256) {
+            // 内核映射对用户态不可见，用户映射由 `map_to` 设置 `USER_ACCESSIBLE`
+            entry.set_addr(
+                kernel_entry.addr(),
+                kernel_entry.flags() - PageTableFlags::USER_ACCESSIBLE,
+            );
+        }
+        Self::with_page_table(frame_alloc, id, new_frame)
+    }
+
+    /// 创建使用 `page_table_addr` 处的顶级页表的进程
+    fn with_page_table(
+        frame_alloc: &mut BitmapFrameAllocator,
+        id: usize,
+        page_table_addr: PhysFrame,
+    ) -> Self {
+        let page_table = unsafe {
+            OffsetPageTable::new(
+                &mut *(physical_to_virtual(page_table_addr.start_address().as_u64() as usize)
+                    as *mut PageTable),
+                VirtAddr::new_truncate(crate::memory::PHYSICAL_OFFSET),
+            )
+        };
+        // 2. 创建伪上下文信息
+        let state = ProcessState::Ready;
+        let state_isf = InterruptStackFrameValue {
+            instruction_pointer: VirtAddr::new_truncate(0),
+            code_segment: 0,
+            cpu_flags: 0,
+            stack_pointer: VirtAddr::new_truncate(0),
+            stack_segment: 0,
+        };
+        let state_reg = Registers::default();
+        // 3. 返回
+        Self {
+            id,
+            parent: 0,
+            name: String::new(),
+            args: Vec::new(),
+            env: Vec::new(),
+            state,
+            waiting_for: None,
+            sched: SchedInfo::default(),
+            state_isf,
+            state_reg,
+            fpu: Box::new(FpuState::new()),
+            page_table_addr: (page_table_addr, Cr3::read().1),
+            page_table: Some(page_table),
+            space: AddressSpace::new(),
+            heap_end: USER_HEAP_START,
+            cpu_ticks: 0,
+            kernel_stack: KernelStack::new(KERNEL_STACK_PAGES, frame_alloc)
+                .expect("cannot alloc kernel stack for new process"),
+        }
+    }
+}
+
+impl Process {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+    pub fn sched(&self) -> &SchedInfo {
+        &self.sched
+    }
+    /// 设置优先级，超出范围的值会被截断
+    pub fn set_nice(&mut self, nice: i8) {
+        self.sched.nice = nice.clamp(MIN_NICE, MAX_NICE);
+    }
+    pub fn state_isf_mut(&mut self) -> &mut InterruptStackFrameValue {
+        &mut self.state_isf
+    }
+    pub fn page_table_mut(&mut self) -> &mut OffsetPageTable<'static> {
+        self.page_table.as_mut().unwrap()
+    }
+    /// 顶层页表所在的帧，即进程运行时 `Cr3` 的值
+    pub fn page_table_frame(&self) -> PhysFrame {
+        self.page_table_addr.0
+    }
+    /// 内核栈的栈顶
+    pub fn kernel_stack_top(&self) -> VirtAddr {
+        self.kernel_stack.top()
+    }
+    pub fn pause(&mut self) {
+        self.state = ProcessState::Ready;
+    }
+    pub fn resume(&mut self) {
+        self.state = ProcessState::Running;
+    }
+
+    /// 将进程的上下文恢复到中断栈帧和寄存器中，并切换页表
+    fn restore(&self, sf: &mut InterruptStackFrame, regs: &mut Registers) {
+        unsafe {
+            sf.as_mut().update(|sf_mut| {
+                sf_mut.instruction_pointer = self.state_isf.instruction_pointer;
+                sf_mut.code_segment = self.state_isf.code_segment;
+                sf_mut.cpu_flags = self.state_isf.cpu_flags;
+                sf_mut.stack_pointer = self.state_isf.stack_pointer;
+                sf_mut.stack_segment = self.state_isf.stack_segment;
+            });
+            *regs = self.state_reg.clone();
+            self.fpu.restore();
+            // 更新 Cr3 后会自动刷新 TLB
+            Cr3::write(self.page_table_addr.0, self.page_table_addr.1);
+        }
+        crate::gdt::set_kernel_stack(self.kernel_stack_top());
+        crate::interrupts::set_syscall_stack(self.kernel_stack_top());
+    }
+
+    /// 将用户堆的结束地址调整为 `addr`，释放不再使用的页，新的页在首次访问时分配
+    ///
+    /// `addr` 为 0 时仅查询。返回新的结束地址，失败时返回原来的结束地址。
+    pub fn brk(&mut self, addr: u64, frame_alloc: &mut BitmapFrameAllocator) -> u64 {
+        if addr == 0 || !(USER_HEAP_START..=USER_HEAP_START + USER_HEAP_MAX_SIZE).contains(&addr) {
+            return self.heap_end;
+        }
+        let old_pages = heap_pages(self.heap_end);
+        let new_pages = heap_pages(addr);
+        if new_pages.end < old_pages.end {
+            vm::unmap_pages(
+                self.page_table.as_mut().unwrap(),
+                Page::range(new_pages.end, old_pages.end),
+                frame_alloc,
+            );
+        }
+        if let Some(heap) = self.space.find_kind_mut(VmaKind::Heap) {
+            heap.end = new_pages.end.start_address().as_u64();
+        }
+        self.heap_end = addr;
+        addr
+    }
+
+    /// 处理本进程页表中的缺页，参见 `AddressSpace::handle_fault`
+    pub fn handle_page_fault(
+        &mut self,
+        addr: VirtAddr,
+        access: Access,
+        frame_alloc: &mut BitmapFrameAllocator,
+    ) -> Result<(), Fault> {
+        let page_table = self.page_table.as_mut().ok_or(Fault::Unmapped)?;
+        self.space
+            .handle_fault(page_table, addr, access, frame_alloc)
+    }
+
+    /// 复制出进程号为 `id` 的子进程，上下文和寄存器需要由调用者设置
+    ///
+    /// 可写的页在双方都改为只读并标记为写时复制，其余的页直接共享。
+    pub fn fork(&mut self, id: usize) -> Result<Process, Errno> {
+        let mut child = Process::new(&mut *crate::memory::get_frame_alloc_sure(), id);
+        child.parent = self.id;
+        child.name = self.name.clone();
+        child.args = self.args.clone();
+        child.env = self.env.clone();
+        child.sched = self.sched;
+        child.space = self.space.clone();
+        child.heap_end = self.heap_end;
+
+        // 收集映射时会分配内存，不能持有帧分配器
+        let mut pages = Vec::new();
+        vm::walk(
+            self.page_table_addr.0,
+            &mut |page, entry| {
+                let mut flags = entry.flags();
+                if flags.contains(PageTableFlags::WRITABLE) {
+                    flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+                    entry.set_flags(flags);
+                }
+                pages.push((page, PhysFrame::containing_address(entry.addr()), flags));
+            },
+            &mut |_| {},
+        );
+        x86_64::instructions::tlb::flush_all();
+        for &(_, frame, _) in &pages {
+            vm::share(frame);
+        }
+
+        let table_flags =
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let mut frame_alloc = crate::memory::get_frame_alloc_sure();
+        for (i, &(page, frame, flags)) in pages.iter().enumerate() {
+            let mapped = unsafe {
+                child.page_table_mut().map_to_with_table_flags(
+                    page,
+                    frame,
+                    flags,
+                    table_flags,
+                    &mut *frame_alloc,
+                )
+            };
+            if mapped.is_err() {
+                // 尚未映射到子进程的帧不再共享，子进程中已映射的帧在释放子进程时处理
+                for &(_, frame, _) in &pages[i..] {
+                    vm::release(frame);
+                }
+                drop(frame_alloc);
+                return Err(Errno::ENOMEM);
+            }
+        }
+        Ok(child)
+    }
+}
+
+/// 结束地址为 `end` 的用户堆占用的页
+fn heap_pages(end: u64) -> Range<Page> {
+    let start = Page::containing_address(VirtAddr::new(USER_HEAP_START));
+    let end = Page::containing_address(VirtAddr::new(end + 0xFFF));
+    start..end
+}
+
+impl Process {
+    /// 进程映射的用户页数，共享的帧在每个进程中都计算
+    pub fn resident_pages(&self) -> usize {
+        if !self.owns_page_table() {
+            return 0;
+        }
+        let mut pages = 0;
+        vm::walk(self.page_table_addr.0, &mut |_, _| pages += 1, &mut |_| {});
+        pages
+    }
+
+    /// 用户地址空间映射的帧、页表和顶级页表，与其他进程共享的帧也包括在内
+    pub fn owned_frames(&self) -> Vec<PhysFrame> {
+        if !self.owns_page_table() {
+            return Vec::new();
+        }
+        let mut frames = Vec::new();
+        let mut tables = Vec::new();
+        vm::walk(
+            self.page_table_addr.0,
+            &mut |_, entry| frames.push(PhysFrame::containing_address(entry.addr())),
+            &mut |frame| tables.push(frame),
+        );
+        frames.extend(tables);
+        frames.push(self.page_table_addr.0);
+        frames
+    }
+
+    /// 进程在 `ListProcesses` 中的信息
+    pub fn info(&self) -> ProcessInfo {
+        let mut info = ProcessInfo {
+            pid: self.id as u64,
+            parent: self.parent as u64,
+            state: match self.state {
+                ProcessState::Ready => syscall_abi::PROCESS_READY,
+                ProcessState::Running => syscall_abi::PROCESS_RUNNING,
+                ProcessState::Blocked => syscall_abi::PROCESS_BLOCKED,
+                ProcessState::Sleeping(_) => syscall_abi::PROCESS_SLEEPING,
+                ProcessState::Zombie(_) => syscall_abi::PROCESS_ZOMBIE,
+            },
+            nice: self.sched.nice as i64,
+            cpu_time_ns: self.cpu_ticks * crate::time::TICK_NS,
+            memory: self.resident_pages() as u64 * 0x1000,
+            ..ProcessInfo::default()
+        };
+        info.set_name(if self.id == 0 { "kernel" } else { &self.name });
+        info
+    }
+
+    /// 映射 ELF 的段并建立地址空间中的区域，在栈上放置参数，返回入口处的栈指针
+    ///
+    /// `elf` 位于从 `phys_start` 开始的连续物理帧中。
+    fn map_program(&mut self, elf: &ElfFile, phys_start: PhysAddr) -> Result<u64, Errno> {
+        elf_loader::map_elf(
+            elf,
+            phys_start,
+            crate::memory::PHYSICAL_OFFSET,
+            self.page_table_mut(),
+            &mut *crate::memory::get_frame_alloc_sure(),
+            true,
+        )
+        .map_err(|_| Errno::ENOMEM)?;
+
+        // ELF 的段已经映射，栈和堆在首次访问时分配
+        let mut end = 0;
+        for segment in elf.program_iter() {
+            if segment.get_type() != Ok(program::Type::Load) || segment.mem_size() == 0 {
+                continue;
+            }
+            // 相邻的段可能共用一页，该页按前一个段的权限处理
+            let start = (segment.virtual_addr() & !0xFFF).max(end);
+            let seg_end = (segment.virtual_addr() + segment.mem_size() + 0xFFF) & !0xFFF;
+            if start >= seg_end {
+                continue;
+            }
+            let mut flags = VmFlags::READ;
+            if segment.flags().is_write() {
+                flags |= VmFlags::WRITE;
+            }
+            if segment.flags().is_execute() {
+                flags |= VmFlags::EXEC;
+            }
+            self.space.insert(Vma {
+                start,
+                end: seg_end,
+                flags,
+                kind: VmaKind::Elf,
+            })?;
+            end = seg_end;
+        }
+        self.space.insert(Vma {
+            start: USER_HEAP_START,
+            end: USER_HEAP_START,
+            flags: VmFlags::READ | VmFlags::WRITE,
+            kind: VmaKind::Heap,
+        })?;
+        let stack_top = random_stack_top();
+        self.space
+            .insert_stack(stack_top, STACK_INITIAL_PAGES * 0x1000, stack_limit())?;
+        self.init_stack(stack_top, elf)
+    }
+
+    /// 按照 System V 的约定在栈顶放置 `argc`、`argv`、`envp` 和辅助向量，返回指向 `argc` 的栈指针
+    ///
+    /// 字符串、随机数和必要时复制的程序头位于栈顶，其下是指针数组，栈指针按 16 字节对齐。
+    fn init_stack(&mut self, top: u64, elf: &ElfFile) -> Result<u64, Errno> {
+        let ph_offset = elf.header.pt2.ph_offset();
+        let ph_size = elf.header.pt2.ph_count() as u64 * elf.header.pt2.ph_entry_size() as u64;
+        // 程序头通常位于某个段中，否则复制到栈上
+        let mapped_phdr = elf.program_iter().find_map(|segment| {
+            let inside = segment.get_type() == Ok(program::Type::Load)
+                && segment.offset() <= ph_offset
+                && ph_offset + ph_size <= segment.offset() + segment.file_size();
+            inside.then(|| segment.virtual_addr() + ph_offset - segment.offset())
+        });
+
+        // 栈顶的数据：16 字节随机数，程序头，字符串
+        let mut info = Vec::new();
+        let mut random = [0; 16];
+        crate::random::fill(&mut random);
+        info.extend_from_slice(&random);
+        if mapped_phdr.is_none() {
+            let range = ph_offset as usize..(ph_offset + ph_size) as usize;
+            info.extend_from_slice(elf.input.get(range).ok_or(Errno::ENOEXEC)?);
+        }
+        let mut strings = Vec::new();
+        let argv = core::iter::once(&self.name).chain(&self.args);
+        for s in argv.clone().chain(&self.env) {
+            strings.push(info.len() as u64);
+            info.extend_from_slice(s.as_bytes());
+            info.push(0);
+        }
+
+        let info_start = top.checked_sub(info.len() as u64).ok_or(Errno::E2BIG)? & !0xF;
+        let argc = argv.count();
+        let mut words = Vec::new();
+        words.push(argc as u64);
+        words.extend(strings[..argc].iter().map(|offset| info_start + offset));
+        words.push(0);
+        words.extend(strings[argc..].iter().map(|offset| info_start + offset));
+        words.push(0);
+        let auxv = [
+            (AT_PHDR, mapped_phdr.unwrap_or(info_start + 16)),
+            (AT_PHENT, elf.header.pt2.ph_entry_size() as u64),
+            (AT_PHNUM, elf.header.pt2.ph_count() as u64),
+            (AT_PAGESZ, 0x1000),
+            (AT_ENTRY, elf.header.pt2.entry_point()),
+            (AT_RANDOM, info_start),
+            (AT_NULL, 0),
+        ];
+        for (key, value) in auxv.iter() {
+            words.push(*key);
+            words.push(*value);
+        }
+
+        let sp = (info_start - words.len() as u64 * 8) & !0xF;
+        // 与 Linux 相同，参数和环境变量最多占用栈大小限制的四分之一
+        if top - sp > stack_limit() / 4 {
+            return Err(Errno::E2BIG);
+        }
+        let words: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.write_user(sp, &words)?;
+        self.write_user(info_start, &info)?;
+        Ok(sp)
+    }
+
+    /// 将 `data` 写入本进程地址空间中的 `addr`，按需分配页
+    fn write_user(&mut self, addr: u64, data: &[u8]) -> Result<(), Errno> {
+        let mut written = 0;
+        while written < data.len() {
+            let addr = VirtAddr::new(addr + written as u64);
+            self.handle_page_fault(
+                addr,
+                Access::Write,
+                &mut *crate::memory::get_frame_alloc_sure(),
+            )
+            .map_err(|_| Errno::EFAULT)?;
+            let phys = self
+                .page_table_mut()
+                .translate_addr(addr)
+                .ok_or(Errno::EFAULT)?;
+            let len = (0x1000 - (addr.as_u64() & 0xFFF) as usize).min(data.len() - written);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data[written..].as_ptr(),
+                    physical_to_virtual(phys.as_u64() as usize) as *mut u8,
+                    len,
+                );
+            }
+            written += len;
+        }
+        Ok(())
+    }
+
+    /// 接管加载 ELF 的缓冲区 `frames`，释放没有被映射的帧，记录被多次映射的帧
+    fn adopt_frames(&mut self, frames: Range<PhysFrame>) {
+        let mut counts = alloc::vec![0usize; (frames.end - frames.start) as usize];
+        vm::walk(
+            self.page_table_addr.0,
+            &mut |_, entry| {
+                let frame = PhysFrame::containing_address(entry.addr());
+                if frames.start <= frame && frame < frames.end {
+                    counts[(frame - frames.start) as usize] += 1;
+                }
+            },
+            &mut |_| {},
+        );
+        let mut unused = Vec::new();
+        for (frame, &count) in PhysFrame::range(frames.start, frames.end).zip(&counts) {
+            if count == 0 {
+                unused.push(frame);
+            }
+            for _ in 1..count {
+                vm::share(frame);
+            }
+        }
+        let mut frame_alloc = crate::memory::get_frame_alloc_sure();
+        for frame in unused {
+            unsafe { frame_alloc.deallocate_frame(frame) };
+        }
+    }
+
+    /// 是否有独立的页表，内核伪进程使用内核页表，释放后的进程没有页表
+    fn owns_page_table(&self) -> bool {
+        self.page_table.is_some() && self.page_table_addr.0 != crate::memory::kernel_page_table()
+    }
+
+    /// 释放用户地址空间中不再共享的帧、页表和顶级页表，之后进程不能再运行
+    ///
+    /// 调用前需要确保当前使用的不是该进程的页表。
+    fn free_address_space(&mut self) {
+        if !self.owns_page_table() {
+            return;
+        }
+        let mut frames = Vec::new();
+        let mut tables = Vec::new();
+        vm::walk(
+            self.page_table_addr.0,
+            &mut |_, entry| {
+                let frame = PhysFrame::containing_address(entry.addr());
+                if vm::release(frame) {
+                    frames.push(frame);
+                }
+            },
+            &mut |frame| tables.push(frame),
+        );
+
+        let mut frame_alloc = crate::memory::get_frame_alloc_sure();
+        unsafe {
+            for frame in frames.into_iter().chain(tables) {
+                frame_alloc.deallocate_frame(frame);
+            }
+            frame_alloc.deallocate_frame(self.page_table_addr.0);
+        }
+        self.page_table = None;
+        self.heap_end = USER_HEAP_START;
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.free_address_space();
+    }
+}
+
+once_mutex!(pub PROCESS_LIST: Vec<Process>);
+guard_access_fn! {
+    pub get_process_list(PROCESS_LIST: Vec<Process>)
+}
+
+once_mutex!(SCHEDULER: Box<dyn Scheduler>);
+guard_access_fn! {
+    get_scheduler(SCHEDULER: Box<dyn Scheduler>)
+}
+
+/// 睡眠进程的到期时间
+once_mutex!(SLEEPERS: TimerWheel<usize>);
+guard_access_fn! {
+    get_sleepers(SLEEPERS: TimerWheel<usize>)
+}
+
+/// 初始化进程系统，需要保证内存已经正确初始化
+pub fn init(cmdline: &str) {
+    init_PROCESS_LIST(Vec::new());
+    wait_queue::init();
+    let scheduler = scheduler::from_cmdline(cmdline);
+    info!("using {} scheduler", scheduler.name());
+    init_SCHEDULER(scheduler);
+    init_SLEEPERS(TimerWheel::new(crate::time::ticks()));
+    set_stack_limit(cmdline);
+    let mut alloc = crate::memory::get_frame_alloc_sure();
+    let mut list = get_process_list_sure();
+    // 内核伪进程
+    // 内核伪进程，直接使用 `memory` 模块的页表，不另外分配
+    let mut kproc = Process::with_page_table(&mut *alloc, list.len(), Cr3::read().0);
+    kproc.state = ProcessState::Running;
+    // 由 shell 启动的进程继承的环境变量
+    kproc.env = DEFAULT_ENV.iter().map(|var| String::from(*var)).collect();
+    list.push(kproc);
+    info!("process manager initialized");
+}
+
+/// 加载 ELF 文件，创建运行在 3 环的进程，`args` 不包括程序名
+pub fn load_program(
+    file: &OsFile,
+    id: usize,
+    parent: usize,
+    args: Vec<String>,
+    env: Vec<String>,
+) -> Result<Process, Errno> {
+    info!("loading file {} to memory", file.entry.stem());
+    let pages = (file.sectors().len() + 7) / 8;
+    // 分配连续的内存帧，`elf_loader` 将其直接映射为程序的段
+    let frames = crate::memory::get_frame_alloc_sure()
+        .allocate_frames(pages)
+        .ok_or(Errno::ENOMEM)?;
+    trace!("alloc = {:?}, {} pages", frames.start, pages);
+    // 进程的页表中没有低地址的恒等映射，通过物理内存映射访问缓冲区
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(
+            physical_to_virtual(frames.start.start_address().as_u64() as usize) as *mut u8,
+            pages * 0x1000,
+        )
+    };
+    // 解析 ELF 文件，失败时释放缓冲区
+    let elf = match file.load_to(buf) {
+        Ok(_) => xmas_elf::ElfFile::new(buf).map_err(|_| Errno::ENOEXEC),
+        Err(_) => Err(Errno::EIO),
+    };
+    let elf = match elf {
+        Ok(elf) => elf,
+        Err(err) => {
+            unsafe { crate::memory::get_frame_alloc_sure().deallocate_frames(frames) };
+            return Err(err);
+        }
+    };
+
+    let mut proc = Process::new(&mut *crate::memory::get_frame_alloc_sure(), id);
+    proc.parent = parent;
+    proc.name = file.entry.stem().trim().to_ascii_lowercase();
+    proc.args = args;
+    proc.env = env;
+    let entry = elf.header.pt2.entry_point();
+    let stack_pointer = proc.map_program(&elf, frames.start.start_address());
+    // 无论是否成功，缓冲区中的帧此后都由进程的页表管理，不能再访问 `elf`
+    proc.adopt_frames(frames);
+    let stack_pointer = stack_pointer?;
+
+    proc.state_isf.instruction_pointer = VirtAddr::new_truncate(entry);
+    proc.state_isf.stack_pointer = VirtAddr::new_truncate(stack_pointer);
+    // 进程运行在 3 环，IOPL 为 0，不能执行 `cli`、`in`/`out` 等特权指令
+    let (code_selector, data_selector) = crate::gdt::user_selectors();
+    proc.state_isf.code_segment = code_selector.0 as u64;
+    proc.state_isf.stack_segment = data_selector.0 as u64;
+    proc.state_isf.cpu_flags = RFlags::INTERRUPT_FLAG.bits();
+    Ok(proc)
+}
+
+/// 在栈区域中随机选取一个页对齐的栈顶，其下留有栈增长的最大范围和保护页
+fn random_stack_top() -> u64 {
+    let lowest = STACK_REGION_START + *STACK_LIMIT_RANGE.end() + 0x1000;
+    let pages = (STACK_REGION_END - lowest) / 0x1000;
+    lowest + crate::random::next_u64() % (pages + 1) * 0x1000
+}
+
+/// 新进程的用户栈大小限制
+pub fn stack_limit() -> u64 {
+    STACK_LIMIT.load(Ordering::Relaxed)
+}
+
+/// 根据内核命令行设置新进程的用户栈大小限制
+fn set_stack_limit(cmdline: &str) {
+    let arg = match cmdline
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("stack_limit="))
+    {
+        Some(arg) => arg,
+        None => return,
+    };
+    match arg.parse::<u64>() {
+        Ok(kib) => {
+            let limit = kib
+                .saturating_mul(1024)
+                .clamp(*STACK_LIMIT_RANGE.start(), *STACK_LIMIT_RANGE.end());
+            let limit = limit & !0xFFF;
+            STACK_LIMIT.store(limit, Ordering::Relaxed);
+            info!("user stack limit set to {} KiB", limit / 1024);
+        }
+        Err(_) => warn!("invalid stack limit {}, using default", arg),
+    }
+}
+
+/// 创建 `parent` 的子进程，不会自动切换过去，返回进程号
+pub fn spawn(file: &OsFile, args: Vec<String>, parent: usize) -> Result<usize, Errno> {
+    let id = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+    // 子进程继承父进程的环境变量和优先级
+    let (env, nice) = get_process_list_sure()
+        .iter()
+        .find(|p| p.id == parent)
+        .map(|p| (p.env.clone(), p.sched.nice))
+        .unwrap_or_default();
+    let mut proc = load_program(file, id, parent, args, env)?;
+    proc.sched.nice = nice;
+    get_process_list_sure().push(proc);
+    Ok(id)
+}
+
+/// 以新的程序替换当前进程，并将上下文切换到新程序的入口
+pub fn exec(
+    file: &OsFile,
+    args: Vec<String>,
+    sf: &mut InterruptStackFrame,
+    regs: &mut Registers,
+) -> Result<(), Errno> {
+    let (id, parent, env) = {
+        let list = get_process_list_sure();
+        let proc = list
+            .iter()
+            .find(|p| p.state == ProcessState::Running)
+            .ok_or(Errno::ESRCH)?;
+        (proc.id, proc.parent, proc.env.clone())
+    };
+    if id == 0 {
+        // 内核伪进程不能被替换
+        return Err(Errno::EPERM);
+    }
+    // 新程序保留原来的环境变量
+    let mut proc = load_program(file, id, parent, args, env)?;
+    proc.state = ProcessState::Running;
+
+    let mut list = get_process_list_sure();
+    let pos = list.iter().position(|p| p.id == id).unwrap();
+    proc.sched = list[pos].sched;
+    // 当前仍在旧进程的内核栈上执行，新程序继续使用它
+    core::mem::swap(&mut proc.kernel_stack, &mut list[pos].kernel_stack);
+    proc.restore(sf, regs);
+    let old = core::mem::replace(&mut list[pos], proc);
+    // 释放旧进程需要获取帧分配器
+    drop(list);
+    drop(old);
+    Ok(())
+}
+
+/// 复制当前进程，子进程从同一位置返回 0，返回子进程号
+pub fn fork(sf: &InterruptStackFrame, regs: &Registers) -> Result<usize, Errno> {
+    let id = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+    let mut list = get_process_list_sure();
+    let parent = list
+        .iter_mut()
+        .find(|p| p.state == ProcessState::Running)
+        .ok_or(Errno::ESRCH)?;
+    if parent.id == 0 {
+        // 内核伪进程的地址空间不属于用户
+        return Err(Errno::EPERM);
+    }
+    let mut child = parent.fork(id)?;
+    child.state_isf = **sf;
+    child.state_reg = regs.clone();
+    child.state_reg.set_rax(0);
+    // 当前的 FPU 状态仍是父进程的
+    child.fpu.save();
+    list.push(child);
+    Ok(id)
+}
+
+/// 处理当前用户进程对 `addr` 的 `access` 产生的缺页，需要在关中断时调用
+pub fn handle_page_fault(addr: VirtAddr, access: Access) -> Result<(), Fault> {
+    let mut list = get_process_list_sure();
+    match list.iter_mut().find(|p| p.state == ProcessState::Running) {
+        Some(proc) if proc.id != 0 => {
+            proc.handle_page_fault(addr, access, &mut *crate::memory::get_frame_alloc_sure())
+        }
+        _ => Err(Fault::Unmapped),
+    }
+}
+
+/// 结束访问了无效地址的当前进程，并切换到下一个进程
+pub fn kill_current_process(
+    addr: VirtAddr,
+    fault: Fault,
+    sf: &mut InterruptStackFrame,
+    regs: &mut Registers,
+) {
+    {
+        let list = get_process_list_sure();
+        if let Some(proc) = list.iter().find(|p| p.state == ProcessState::Running) {
+            match fault {
+                Fault::StackOverflow => println!(
+                    "process {} ({}) killed: stack overflow at {:#x}",
+                    proc.id,
+                    proc.name,
+                    addr.as_u64()
+                ),
+                _ => println!(
+                    "process {} ({}) killed: segmentation fault at {:#x} ({})",
+                    proc.id,
+                    proc.name,
+                    addr.as_u64(),
+                    fault
+                ),
+            }
+        }
+    }
+    exit_current_process(-syscall_abi::SIGSEGV);
+    switch_first_ready_process(sf, regs);
+}
+
+/// 当前运行的进程号
+pub fn current_pid() -> usize {
+    get_process_list_sure()
+        .iter()
+        .find(|p| p.state == ProcessState::Running)
+        .map_or(0, |p| p.id)
+}
+
+/// 等待当前进程的子进程 `pid` 结束，回收它并返回退出码
+///
+/// 子进程尚未结束时在内核中阻塞，需要在关中断时调用。
+pub fn wait(pid: usize) -> Result<i32, Errno> {
+    loop {
+        {
+            let mut list = get_process_list_sure();
+            let current = list
+                .iter()
+                .find(|p| p.state == ProcessState::Running)
+                .map_or(0, |p| p.id);
+            let pos = list
+                .iter()
+                .position(|p| p.id == pid && p.parent == current && p.id != current)
+                .ok_or(Errno::ECHILD)?;
+            if let ProcessState::Zombie(code) = list[pos].state {
+                list.remove(pos);
+                return Ok(code);
+            }
+        }
+        block_in_kernel(ProcessState::Blocked, &mut |proc| {
+            proc.waiting_for = Some(pid)
+        });
+    }
+}
+
+/// 所有进程的信息，包括尚未回收的僵尸进程
+pub fn process_info() -> Vec<ProcessInfo> {
+    get_process_list_sure().iter().map(Process::info).collect()
+}
+
+/// 正在运行的进程号，空闲时视为内核伪进程
+fn running_id(list: &[Process]) -> usize {
+    list.iter()
+        .find(|p| p.state == ProcessState::Running)
+        .map_or(0, |p| p.id)
+}
+
+/// 当前进程可以控制的进程 `pid` 在列表中的位置
+///
+/// 内核伪进程（运行 shell）可以控制所有用户进程，用户进程只能控制自己和子进程，
+/// 内核伪进程本身不能被控制。进程不存在或已经结束时返回 `ESRCH`，无权控制时返回 `EPERM`。
+fn controlled_process(list: &[Process], pid: usize) -> Result<usize, Errno> {
+    if pid == 0 {
+        return Err(Errno::EPERM);
+    }
+    let caller = running_id(list);
+    let pos = list
+        .iter()
+        .position(|p| p.id == pid && !matches!(p.state, ProcessState::Zombie(_)))
+        .ok_or(Errno::ESRCH)?;
+    if caller == 0 || pid == caller || list[pos].parent == caller {
+        Ok(pos)
+    } else {
+        Err(Errno::EPERM)
+    }
+}
+
+/// 以退出码 `-SIGKILL` 结束当前进程可以控制的进程 `pid`
+///
+/// 结束的是当前进程时，调用者需要随后切换到其他进程。
+pub fn kill(pid: usize) -> Result<(), Errno> {
+    let mut list = get_process_list_sure();
+    let pos = controlled_process(&list, pid)?;
+    info!("process {} ({}) killed", pid, list[pos].name);
+    exit_process(&mut list, pid, -syscall_abi::SIGKILL);
+    Ok(())
+}
+
+/// 设置当前进程可以控制的进程 `pid` 的优先级，超出范围的值会被截断
+///
+/// 只有内核伪进程可以提高优先级（降低 nice 值）。
+pub fn set_priority(pid: usize, nice: i64) -> Result<(), Errno> {
+    let mut list = get_process_list_sure();
+    let pos = controlled_process(&list, pid)?;
+    let nice = nice.clamp(MIN_NICE as i64, MAX_NICE as i64) as i8;
+    if running_id(&list) != 0 && nice < list[pos].sched.nice {
+        return Err(Errno::EPERM);
+    }
+    list[pos].set_nice(nice);
+    Ok(())
+}
+
+/// 请求在下一次调度时暂停用户进程，回到内核伪进程中的 shell
+///
+/// 在键盘中断中按下 Ctrl+C 时调用，shell 再次启动程序时暂停的进程继续运行。
+pub fn request_shell() {
+    SHELL_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// 在内核中阻塞当前进程直到 `queue` 被唤醒，需要在关中断时调用
+pub fn wait_on(queue: &WaitQueue) {
+    block_in_kernel(ProcessState::Blocked, &mut |proc| queue.push(proc.id));
+}
+
+/// 当前进程在内核中睡眠 `ns` 纳秒，需要在关中断时调用
+pub fn sleep(ns: u64) {
+    let deadline = Instant::now() + Duration::from_nanos(ns);
+    while Instant::now() < deadline {
+        block_in_kernel(ProcessState::Sleeping(deadline), &mut |proc| {
+            get_sleepers_sure().insert(crate::time::deadline_tick(deadline), proc.id)
+        });
+    }
+}
+
+/// 在内核中阻塞的请求，由 `block_in_kernel` 通过 `rdi` 传给中断处理
+struct BlockRequest<'a> {
+    state: ProcessState,
+    /// 在挂起前对当前进程调用，用于登记唤醒条件
+    f: &'a mut dyn FnMut(&mut Process),
+}
+
+/// 以 `state` 挂起当前进程并切换到其他进程，被唤醒后返回
+///
+/// 通过 `interrupts::kernel_yield` 进入中断，中断栈帧和寄存器即为调用者在内核态的上下文，
+/// 与用户态的上下文一样被保存和恢复，调用栈则保留在进程自己的内核栈上。
+/// 必须在关中断时调用，否则检查条件与挂起之间发生的唤醒会丢失。
+fn block_in_kernel(state: ProcessState, f: &mut dyn FnMut(&mut Process)) {
+    debug_assert!(!x86_64::instructions::interrupts::are_enabled());
+    let mut request = BlockRequest { state, f };
+    unsafe { crate::interrupts::kernel_yield(&mut request as *mut BlockRequest as u64) };
+}
+
+/// 处理 `block_in_kernel` 产生的中断
+///
+/// # Safety
+///
+/// `request` 必须指向调用者栈上的 `BlockRequest`。
+pub unsafe fn handle_kernel_yield(
+    request: u64,
+    sf: &mut InterruptStackFrame,
+    regs: &mut Registers,
+) {
+    let request = unsafe { &mut *(request as *mut BlockRequest) };
+    block_current(request.state, sf, regs, |proc| (request.f)(proc));
+}
+
+/// 保存当前进程并以 `state` 挂起，然后切换到其他进程
+fn block_current(
+    state: ProcessState,
+    sf: &mut InterruptStackFrame,
+    regs: &mut Registers,
+    f: impl FnOnce(&mut Process),
+) -> Option<usize> {
+    let id = save_current_process(sf, regs)?;
+    {
+        let mut list = get_process_list_sure();
+        let proc = list.iter_mut().find(|p| p.id == id).unwrap();
+        proc.state = state;
+        f(proc);
+    }
+    switch_first_ready_process(sf, regs);
+    Some(id)
+}
+
+/// 将等待队列唤醒的进程和睡眠到期的进程设置为就绪，返回是否有进程被唤醒
+fn wake_up(list: &mut [Process]) -> bool {
+    let mut woken = false;
+    for pid in wait_queue::take_wakeups() {
+        if let Some(proc) = list
+            .iter_mut()
+            .find(|p| p.id == pid && p.state == ProcessState::Blocked)
+        {
+            proc.state = ProcessState::Ready;
+            woken = true;
+        }
+    }
+    let mut sleepers = get_sleepers_sure();
+    let now = Instant::now();
+    for pid in sleepers.expire(crate::time::ticks()) {
+        let proc = match list.iter_mut().find(|p| p.id == pid) {
+            Some(proc) => proc,
+            None => continue,
+        };
+        match proc.state {
+            ProcessState::Sleeping(deadline) if deadline <= now => {
+                proc.state = ProcessState::Ready;
+                woken = true;
+            }
+            // 时钟中断计数与单调时钟存在误差，尚未到期时推迟到下一次时钟中断
+            ProcessState::Sleeping(deadline) => {
+                sleepers.insert(crate::time::deadline_tick(deadline), pid)
+            }
+            _ => (),
+        }
+    }
+    woken
+}
+
+/// 处理时钟中断：唤醒进程，并在当前进程用完时间片或有进程被唤醒时重新调度
+///
+/// 内核伪进程中的 shell 运行时不被抢占，由它启动程序时让出处理器。
+pub fn tick(sf: &mut InterruptStackFrame, regs: &mut Registers) {
+    let reschedule = {
+        let mut list = get_process_list_sure();
+        let woken = wake_up(&mut list);
+        match list.iter_mut().find(|p| p.state == ProcessState::Running) {
+            Some(proc) if proc.id == 0 => {
+                proc.cpu_ticks += 1;
+                // 已经回到 shell，忽略之后的 Ctrl+C
+                SHELL_REQUESTED.store(false, Ordering::Relaxed);
+                false
+            }
+            Some(proc) => {
+                proc.cpu_ticks += 1;
+                proc.sched.slice = proc.sched.slice.saturating_sub(1);
+                if proc.sched.slice == 0 {
+                    get_scheduler_sure().expired(proc);
+                }
+                proc.sched.slice == 0 || woken || SHELL_REQUESTED.load(Ordering::Relaxed)
+            }
+            // 处于空闲状态
+            None => true,
+        }
+    };
+    if reschedule {
+        switch_first_ready_process(sf, regs);
+    }
+}
+
+/// 以退出码 `code` 结束当前进程，执行前确保已经切换到有效进程上下文中
+pub fn exit_current_process(code: i32) {
+    let mut list = get_process_list_sure();
+    let id = match list.iter().find(|p| p.state == ProcessState::Running) {
+        Some(proc) => proc.id,
+        None => return,
+    };
+    exit_process(&mut list, id, code);
+}
+
+/// 以退出码 `code` 结束进程 `id`
+///
+/// 进程保留为僵尸进程，若父进程正在等待则唤醒它回收；父进程为内核且没有等待时直接回收。
+fn exit_process(list: &mut Vec<Process>, id: usize, code: i32) {
+    let proc = list.iter().find(|p| p.id == id).unwrap();
+    let parent = proc.parent;
+    if proc.state == ProcessState::Running {
+        // 之后会释放进程的页表，先切换到内核页表
+        unsafe { Cr3::write(crate::memory::kernel_page_table(), Cr3::read().1) };
+    }
+    info!(
+        "process {} exited with code {}, {} KiB resident",
+        id,
+        code,
+        proc.resident_pages() * 4
+    );
+    // 回收已经结束的子进程，其余子进程交给内核
+    list.retain(|p| !(p.parent == id && matches!(p.state, ProcessState::Zombie(_))));
+    for child in list.iter_mut().filter(|p| p.parent == id) {
+        child.parent = 0;
+    }
+    // 唤醒正在等待的父进程，由它回收僵尸进程
+    let waiting = list
+        .iter_mut()
+        .find(|p| p.id == parent && p.state == ProcessState::Blocked && p.waiting_for == Some(id));
+    let woken = waiting.is_some();
+    if let Some(waiting) = waiting {
+        waiting.waiting_for = None;
+        waiting.state = ProcessState::Ready;
+    }
+    if parent == 0 && !woken {
+        list.retain(|p| p.id != id);
+    } else {
+        // 僵尸进程只保留退出码，内存立即释放
+        let proc = list.iter_mut().find(|p| p.id == id).unwrap();
+        proc.state = ProcessState::Zombie(code);
+        proc.free_address_space();
+    }
+}
+
+/// 调整当前进程的用户堆，参见 `Process::brk`
+pub fn brk(addr: u64) -> u64 {
+    let mut list = get_process_list_sure();
+    match list.iter_mut().find(|p| p.state == ProcessState::Running) {
+        // 内核伪进程使用内核页表，没有用户堆
+        Some(proc) if proc.id != 0 => proc.brk(addr, &mut *crate::memory::get_frame_alloc_sure()),
+        _ => 0,
+    }
+}
+
+/// 将给定的中断栈帧和寄存器切换到调度器选择的进程，没有可运行的进程时进入空闲状态
+pub fn switch_first_ready_process(sf: &mut InterruptStackFrame, regs: &mut Registers) {
+    // 1. 暂停当前正在运行的进程，并保存其状态
+    let prev_id = save_current_process(sf, regs);
+    // 2. 寻找可以运行的进程
+    let mut list = get_process_list_sure();
+    wake_up(&mut list);
+    let prev = prev_id
+        .and_then(|id| list.iter().position(|p| p.id == id))
+        .unwrap_or(0);
+    if let Some(next) = find_next_process(&mut list, prev) {
+        let proc = &mut list[next];
+        proc.state = ProcessState::Running;
+        proc.sched.slice = get_scheduler_sure().time_slice(proc);
+        // 2b. 若非当前进程，则需要进行切换
+        if prev_id != Some(proc.id) {
+            proc.restore(sf, regs);
+        }
+        trace!(
+            "switched to process {} {:?} {:?} {:?}",
+            proc.id,
+            sf,
+            regs,
+            Cr3::read()
+        );
+    } else {
+        // 2c. 所有进程都在等待，进入空闲状态
+        idle(sf, regs);
+        trace!("no process to run, idle");
+    }
+}
+
+pub fn save_current_process(sf: &mut InterruptStackFrame, regs: &mut Registers) -> Option<usize> {
+    let mut list = get_process_list_sure();
+    // a. 若存在正在运行的进程，则保存状态
+    if let Some(running_proc) = list
+        .iter_mut()
+        .filter(|p| p.state == ProcessState::Running)
+        .next()
+    {
+        running_proc.state = ProcessState::Ready;
+        running_proc.state_isf = sf.clone();
+        running_proc.state_reg = regs.clone();
+        running_proc.fpu.save();
+        Some(running_proc.id)
+    } else {
+        // b. 否则不保存状态
+        None
+    }
+}
+
+fn find_next_process(list: &mut [Process], prev: usize) -> Option<usize> {
+    // 按下 Ctrl+C 后回到 shell，用户进程保持原来的状态
+    if SHELL_REQUESTED.swap(false, Ordering::Relaxed) && list[0].state == ProcessState::Ready {
+        return Some(0);
+    }
+    if let Some(next) = get_scheduler_sure().pick_next(list, prev) {
+        return Some(next);
+    }
+    // 没有就绪的用户程序时，若用户程序都已结束则回到内核，否则等待被唤醒
+    let finished = list[1..]
+        .iter()
+        .all(|p| matches!(p.state, ProcessState::Zombie(_)));
+    if list[0].state == ProcessState::Ready && finished {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// 切换到空闲上下文：在内核态开中断执行 `hlt`，直到时钟中断重新调度
+///
+/// 空闲上下文没有状态，每次都从头开始执行，因此不需要保存。
+fn idle(sf: &mut InterruptStackFrame, regs: &mut Registers) {
+    static mut IDLE_STACK: [u8; KERNEL_STACK_PAGES as usize * 0x1000] =
+        [0; KERNEL_STACK_PAGES as usize * 0x1000];
+
+    extern "C" fn idle_loop() -> ! {
+        loop {
+            x86_64::instructions::interrupts::enable_and_hlt();
+        }
+    }
+
+    // 与 `call` 进入函数时相同，栈顶减去返回地址后保持 16 字节对齐
+    let stack_top =
+        VirtAddr::from_ptr(unsafe { IDLE_STACK.as_ptr_range().end }).align_down(16u64) - 8u64;
+    let (code_selector, data_selector) = crate::gdt::kernel_selectors();
+    unsafe {
+        sf.as_mut().update(|sf_mut| {
+            sf_mut.instruction_pointer = VirtAddr::new(idle_loop as usize as u64);
+            sf_mut.code_segment = code_selector.0 as u64;
+            sf_mut.cpu_flags = RFlags::INTERRUPT_FLAG.bits();
+            sf_mut.stack_pointer = stack_top;
+            sf_mut.stack_segment = data_selector.0 as u64;
+        });
+        *regs = Registers::default();
+        Cr3::write(crate::memory::kernel_page_table(), Cr3::read().1);
+    }
+}
@@ -0,0 +1,156 @@
+//! 可替换的调度策略
+//!
+//! 调度器只决定下一个运行的进程和时间片长度，进程状态的维护由 `process` 模块完成。
+//! 列表中位置 0 是内核伪进程，调度器只在用户进程中选择。
+//! 通过内核命令行的 `sched=rr|priority|mlfq` 选择，默认为轮转调度。
+
+use super::{Process, ProcessState};
+use alloc::boxed::Box;
+
+/// 默认的 nice 值，越小优先级越高
+pub const DEFAULT_NICE: i8 = 0;
+pub const MIN_NICE: i8 = -20;
+pub const MAX_NICE: i8 = 19;
+
+/// 进程的调度信息
+#[derive(Debug, Clone, Copy)]
+pub struct SchedInfo {
+    /// 优先级，范围为 `MIN_NICE..=MAX_NICE`
+    pub nice: i8,
+    /// 多级反馈队列中所处的级别
+    pub level: u8,
+    /// 剩余的时间片，单位为时钟中断次数
+    pub slice: u32,
+}
+
+impl Default for SchedInfo {
+    fn default() -> Self {
+        Self {
+            nice: DEFAULT_NICE,
+            level: 0,
+            slice: 0,
+        }
+    }
+}
+
+pub trait Scheduler: Send {
+    fn name(&self) -> &'static str;
+    /// 从就绪的用户进程中选择下一个运行的进程，返回其在列表中的位置
+    ///
+    /// `prev` 为上一个运行的进程在列表中的位置。
+    fn pick_next(&mut self, list: &mut [Process], prev: usize) -> Option<usize>;
+    /// 进程被选中时分配的时间片
+    fn time_slice(&self, proc: &Process) -> u32;
+    /// 进程用完时间片时调用，主动阻塞或睡眠的进程不会调用
+    fn expired(&mut self, _proc: &mut Process) {}
+}
+
+/// 根据内核命令行创建调度器
+pub fn from_cmdline(cmdline: &str) -> Box<dyn Scheduler> {
+    let name = cmdline
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("sched="))
+        .unwrap_or("rr");
+    match name {
+        "priority" => Box::new(PriorityScheduler),
+        "mlfq" => Box::new(MlfqScheduler::new()),
+        "rr" => Box::new(RoundRobinScheduler),
+        _ => {
+            warn!("unknown scheduler {}, using round-robin", name);
+            Box::new(RoundRobinScheduler)
+        }
+    }
+}
+
+/// 从 `prev` 之后循环遍历用户进程的位置，最后是 `prev` 本身
+fn cyclic_after(len: usize, prev: usize) -> impl Iterator<Item = usize> {
+    (1..=len)
+        .map(move |offset| (prev + offset) % len)
+        .filter(|&i| i != 0)
+}
+
+fn is_ready(proc: &Process) -> bool {
+    proc.state == ProcessState::Ready
+}
+
+/// 轮转调度，所有进程使用相同的时间片
+pub struct RoundRobinScheduler;
+
+impl Scheduler for RoundRobinScheduler {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+
+    fn pick_next(&mut self, list: &mut [Process], prev: usize) -> Option<usize> {
+        cyclic_after(list.len(), prev).find(|&i| is_ready(&list[i]))
+    }
+
+    fn time_slice(&self, _proc: &Process) -> u32 {
+        2
+    }
+}
+
+/// 静态优先级调度，总是选择 nice 值最小的就绪进程，相同优先级之间轮转
+///
+/// 优先级越高，时间片越长；低优先级的进程在有高优先级进程就绪时会饿死。
+pub struct PriorityScheduler;
+
+impl Scheduler for PriorityScheduler {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn pick_next(&mut self, list: &mut [Process], prev: usize) -> Option<usize> {
+        cyclic_after(list.len(), prev)
+            .filter(|&i| is_ready(&list[i]))
+            .min_by_key(|&i| list[i].sched.nice)
+    }
+
+    fn time_slice(&self, proc: &Process) -> u32 {
+        // nice 为 -20 时 8 个时钟中断，为 19 时 1 个
+        ((MAX_NICE - proc.sched.nice) / 5 + 1) as u32
+    }
+}
+
+/// 多级反馈队列
+///
+/// 新进程位于最高级别，用完时间片后降低一级，级别越低时间片越长；
+/// 主动阻塞的进程保持级别，因此交互式进程会留在高级别。
+/// 每选择 `BOOST_INTERVAL` 次将所有进程提升到最高级别，避免饥饿。
+#[derive(Default)]
+pub struct MlfqScheduler {
+    picks: usize,
+}
+
+impl MlfqScheduler {
+    const LEVELS: u8 = 4;
+    const BOOST_INTERVAL: usize = 100;
+
+    pub fn new() -> Self {
+        Self { picks: 0 }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+
+    fn pick_next(&mut self, list: &mut [Process], prev: usize) -> Option<usize> {
+        self.picks += 1;
+        if self.picks % Self::BOOST_INTERVAL == 0 {
+            list.iter_mut().for_each(|p| p.sched.level = 0);
+        }
+        cyclic_after(list.len(), prev)
+            .filter(|&i| is_ready(&list[i]))
+            .min_by_key(|&i| list[i].sched.level)
+    }
+
+    fn time_slice(&self, proc: &Process) -> u32 {
+        1 << proc.sched.level
+    }
+
+    fn expired(&mut self, proc: &mut Process) {
+        proc.sched.level = (proc.sched.level + 1).min(Self::LEVELS - 1);
+    }
+}
@@ -0,0 +1,390 @@
+//! 进程的用户地址空间
+//!
+//! 用户地址空间由互不重叠的区域（VMA）描述，每个区域记录地址范围、访问权限和内容的来源。
+//! ELF 的段在加载时直接映射；栈和堆是匿名区域，首次访问时由缺页异常分配清零的帧。
+//! 栈区域在访问其下方的地址时向下增长，直到栈的大小限制，限制以下的一页作为保护页，
+//! 访问保护页视为栈溢出。
+//!
+//! `fork` 时父子进程共享所有的帧，可写的页改为只读并标记为写时复制，任何一方写入时
+//! 再复制出私有的帧。被多个页表项映射的帧记录在 `SHARED` 中，未记录的帧只被映射一次。
+
+use crate::memory::{physical_to_virtual, BitmapFrameAllocator};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use syscall_abi::Errno;
+use x86_64::structures::paging::mapper::TranslateResult;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableEntry,
+    PageTableFlags, PhysFrame, Translate,
+};
+use x86_64::VirtAddr;
+
+/// 写时复制的页，在页表项中使用一个可由系统软件自由使用的位
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/// 被多个页表项映射的帧及其映射次数
+static SHARED: Mutex<BTreeMap<PhysFrame, usize>> = Mutex::new(BTreeMap::new());
+
+bitflags! {
+    /// 区域的访问权限
+    pub struct VmFlags: u8 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+/// 区域的用途和内容来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaKind {
+    /// ELF 的段，加载时已经映射
+    Elf,
+    /// 用户栈，按需分配，访问下方的地址时向下增长
+    Stack,
+    /// 用户堆，按需分配，大小由 `brk` 调整
+    Heap,
+}
+
+#[derive(Debug, Clone)]
+pub struct Vma {
+    pub start: u64,
+    pub end: u64,
+    pub flags: VmFlags,
+    pub kind: VmaKind,
+}
+
+impl Vma {
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+
+    /// 映射区域中的页使用的页表项标志
+    fn page_flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        if self.flags.contains(VmFlags::WRITE) {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if !self.flags.contains(VmFlags::EXEC) {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        flags
+    }
+}
+
+/// 产生缺页的访问类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// 无法处理的缺页，进程会被结束
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// 地址不属于任何区域
+    Unmapped,
+    /// 区域不允许这种访问
+    Protection,
+    /// 栈增长超过了大小限制
+    StackOverflow,
+    /// 没有空闲的帧
+    OutOfMemory,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Fault::Unmapped => "address not mapped",
+            Fault::Protection => "access not permitted",
+            Fault::StackOverflow => "stack overflow",
+            Fault::OutOfMemory => "out of memory",
+        })
+    }
+}
+
+/// 按地址排序的区域列表
+#[derive(Debug, Clone, Default)]
+pub struct AddressSpace {
+    vmas: Vec<Vma>,
+    /// 栈可以增长到的最低地址，其下一页为保护页，没有栈时为 0
+    stack_floor: u64,
+}
+
+impl AddressSpace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 加入区域，与已有的区域或栈保留的范围重叠时失败
+    pub fn insert(&mut self, vma: Vma) -> Result<(), Errno> {
+        if self.overlaps(vma.start, vma.end) {
+            return Err(Errno::EINVAL);
+        }
+        let pos = self.vmas.partition_point(|v| v.start < vma.start);
+        self.vmas.insert(pos, vma);
+        Ok(())
+    }
+
+    /// 加入初始大小为 `size` 的栈区域，栈顶为 `top`，最多增长到 `limit` 字节
+    ///
+    /// 栈下方直到限制的范围和其下的保护页保留给栈，不能再加入其他区域。
+    pub fn insert_stack(&mut self, top: u64, size: u64, limit: u64) -> Result<(), Errno> {
+        let floor = top.checked_sub(limit.max(size)).ok_or(Errno::EINVAL)?;
+        if floor < 0x1000 || floor & 0xFFF != 0 || self.stack_floor != 0 {
+            return Err(Errno::EINVAL);
+        }
+        if self.overlaps(floor - 0x1000, top) {
+            return Err(Errno::EINVAL);
+        }
+        self.stack_floor = floor;
+        self.insert(Vma {
+            start: top - size,
+            end: top,
+            flags: VmFlags::READ | VmFlags::WRITE,
+            kind: VmaKind::Stack,
+        })
+    }
+
+    /// `start..end` 是否与已有的区域或栈保留的范围重叠
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.vmas.iter().any(|v| {
+            let reserved = match v.kind {
+                VmaKind::Stack => self.stack_floor - 0x1000,
+                _ => v.start,
+            };
+            reserved < end && start < v.end
+        })
+    }
+
+    /// 访问 `addr` 时栈向下增长以包含它，超过限制时返回 `StackOverflow`
+    fn grow_stack(&mut self, addr: u64) -> Result<(), Fault> {
+        let floor = self.stack_floor;
+        let stack = self.find_kind_mut(VmaKind::Stack).ok_or(Fault::Unmapped)?;
+        if addr >= stack.start {
+            return Err(Fault::Unmapped);
+        }
+        if addr >= floor {
+            stack.start = addr & !0xFFF;
+            Ok(())
+        } else if addr >= floor - 0x1000 {
+            Err(Fault::StackOverflow)
+        } else {
+            Err(Fault::Unmapped)
+        }
+    }
+
+    pub fn find(&self, addr: u64) -> Option<&Vma> {
+        self.vmas.iter().find(|v| v.contains(addr))
+    }
+
+    pub fn find_kind_mut(&mut self, kind: VmaKind) -> Option<&mut Vma> {
+        self.vmas.iter_mut().find(|v| v.kind == kind)
+    }
+
+    /// 处理 `page_table` 中对 `addr` 的 `access` 产生的缺页
+    ///
+    /// 匿名区域中未映射的页分配清零的帧，写时复制的页复制出私有的帧，
+    /// 栈下方的访问使栈增长。
+    pub fn handle_fault(
+        &mut self,
+        page_table: &mut OffsetPageTable<'static>,
+        addr: VirtAddr,
+        access: Access,
+        frame_alloc: &mut BitmapFrameAllocator,
+    ) -> Result<(), Fault> {
+        if self.find(addr.as_u64()).is_none() {
+            self.grow_stack(addr.as_u64())?;
+        }
+        let vma = self.find(addr.as_u64()).ok_or(Fault::Unmapped)?;
+        let allowed = match access {
+            Access::Read => vma.flags.contains(VmFlags::READ),
+            Access::Write => vma.flags.contains(VmFlags::WRITE),
+            Access::Execute => vma.flags.contains(VmFlags::EXEC),
+        };
+        if !allowed {
+            return Err(Fault::Protection);
+        }
+        let page = Page::containing_address(addr);
+        match page_table.translate(addr) {
+            TranslateResult::Mapped { frame, flags, .. } => {
+                let frame = PhysFrame::containing_address(frame.start_address());
+                let permitted = match access {
+                    Access::Read => true,
+                    Access::Write => flags.contains(PageTableFlags::WRITABLE),
+                    Access::Execute => !flags.contains(PageTableFlags::NO_EXECUTE),
+                };
+                if access == Access::Write && flags.contains(COPY_ON_WRITE) {
+                    copy_on_write(page_table, page, frame, flags, frame_alloc)
+                } else if permitted {
+                    // 检查用户指针时访问了已经映射的页
+                    Ok(())
+                } else {
+                    Err(Fault::Protection)
+                }
+            }
+            TranslateResult::NotMapped if vma.kind != VmaKind::Elf => {
+                let frame = frame_alloc.allocate_frame().ok_or(Fault::OutOfMemory)?;
+                unsafe {
+                    zero_frame(frame);
+                    match page_table.map_to(page, frame, vma.page_flags(), frame_alloc) {
+                        Ok(flush) => flush.flush(),
+                        Err(_) => {
+                            frame_alloc.deallocate_frame(frame);
+                            return Err(Fault::OutOfMemory);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(Fault::Unmapped),
+        }
+    }
+}
+
+/// 为写时复制的页换上可写的帧，帧不再共享时直接改为可写
+fn copy_on_write(
+    page_table: &mut OffsetPageTable<'static>,
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    frame_alloc: &mut BitmapFrameAllocator,
+) -> Result<(), Fault> {
+    let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+    if !is_shared(frame) {
+        unsafe {
+            page_table
+                .update_flags(page, flags)
+                .map_err(|_| Fault::Unmapped)?
+                .flush()
+        };
+        return Ok(());
+    }
+    let copy = frame_alloc.allocate_frame().ok_or(Fault::OutOfMemory)?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            physical_to_virtual(frame.start_address().as_u64() as usize) as *const u8,
+            physical_to_virtual(copy.start_address().as_u64() as usize) as *mut u8,
+            0x1000,
+        );
+        // 页已经映射，中间页表不会改变
+        page_table
+            .unmap(page)
+            .map_err(|_| Fault::Unmapped)?
+            .1
+            .flush();
+        page_table
+            .map_to(page, copy, flags, frame_alloc)
+            .map_err(|_| Fault::OutOfMemory)?
+            .flush();
+    }
+    // 原来的帧仍被其他页表项映射，不会在这里释放
+    release(frame);
+    Ok(())
+}
+
+unsafe fn zero_frame(frame: PhysFrame) {
+    unsafe {
+        core::ptr::write_bytes(
+            physical_to_virtual(frame.start_address().as_u64() as usize) as *mut u8,
+            0,
+            0x1000,
+        )
+    };
+}
+
+/// 帧是否被多个页表项映射
+pub fn is_shared(frame: PhysFrame) -> bool {
+    SHARED.lock().contains_key(&frame)
+}
+
+/// 记录帧被另一个页表项映射
+pub fn share(frame: PhysFrame) {
+    *SHARED.lock().entry(frame).or_insert(1) += 1;
+}
+
+/// 取消帧的一次映射，返回帧是否已经不再被使用，此时需要由调用者释放
+pub fn release(frame: PhysFrame) -> bool {
+    let mut shared = SHARED.lock();
+    match shared.get_mut(&frame) {
+        Some(count) if *count > 2 => {
+            *count -= 1;
+            false
+        }
+        Some(_) => {
+            shared.remove(&frame);
+            false
+        }
+        None => true,
+    }
+}
+
+/// 取消映射 `pages` 并释放不再使用的帧
+pub fn unmap_pages(
+    page_table: &mut OffsetPageTable<'static>,
+    pages: impl Iterator<Item = Page>,
+    frame_alloc: &mut BitmapFrameAllocator,
+) {
+    for page in pages {
+        if let Ok((frame, flush)) = page_table.unmap(page) {
+            flush.flush();
+            if release(frame) {
+                unsafe { frame_alloc.deallocate_frame(frame) };
+            }
+        }
+    }
+}
+
+/// 遍历用户地址空间中 `pml4` 自己的页表，对每个页表项调用 `leaf`，对页表所在的帧调用 `table`
+///
+/// 只访问低半部分的顶级页表项，它们都属于进程自己，高半部分与内核共享。
+/// 子页表先于父页表访问。
+pub fn walk(
+    pml4: PhysFrame,
+    leaf: &mut dyn FnMut(Page, &mut PageTableEntry),
+    table: &mut dyn FnMut(PhysFrame),
+) {
+    let pml4 = unsafe { page_table_at(pml4) };
+    for (index, entry) in pml4.iter_mut().enumerate().take(256) {
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let frame = PhysFrame::containing_address(entry.addr());
+        walk_table(frame, 3, (index as u64) << 39, leaf, table);
+        table(frame);
+    }
+}
+
+/// 遍历第 `level` 级页表 `frame`，其映射的地址从 `base` 开始
+fn walk_table(
+    frame: PhysFrame,
+    level: u8,
+    base: u64,
+    leaf: &mut dyn FnMut(Page, &mut PageTableEntry),
+    table: &mut dyn FnMut(PhysFrame),
+) {
+    for (index, entry) in unsafe { page_table_at(frame) }.iter_mut().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let addr = base + ((index as u64) << (12 + 9 * (level as u64 - 1)));
+        if level == 1 {
+            leaf(Page::containing_address(VirtAddr::new(addr)), entry);
+        } else if !flags.contains(PageTableFlags::HUGE_PAGE) {
+            // 用户空间不使用大页
+            let next = PhysFrame::containing_address(entry.addr());
+            walk_table(next, level - 1, addr, leaf, table);
+            table(next);
+        }
+    }
+}
+
+/// 通过物理内存偏移映射访问 `frame` 中的页表
+unsafe fn page_table_at(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe {
+        &mut *(physical_to_virtual(frame.start_address().as_u64() as usize) as *mut PageTable)
+    }
+}
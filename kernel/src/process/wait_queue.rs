@@ -0,0 +1,59 @@
+//! 等待队列
+//!
+//! 进程在系统调用中通过 `process::wait_on` 加入等待队列并进入 `Blocked` 状态。
+//! `notify_all` 可能在中断处理中调用，此时进程列表可能正被持有，
+//! 因此只将进程号放入无锁的唤醒队列，由下一次调度统一设置为就绪。
+
+use alloc::vec::Vec;
+use crossbeam_queue::ArrayQueue;
+use spin::{Mutex, Once};
+
+/// 等待唤醒处理的进程数上限
+const WAKEUP_CAPACITY: usize = 64;
+
+static WAKEUPS: Once<ArrayQueue<usize>> = Once::new();
+
+pub fn init() {
+    WAKEUPS.call_once(|| ArrayQueue::new(WAKEUP_CAPACITY));
+}
+
+pub struct WaitQueue {
+    waiters: Mutex<Vec<usize>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 加入等待者，调用时需要关中断
+    pub(super) fn push(&self, pid: usize) {
+        self.waiters.lock().push(pid);
+    }
+
+    /// 唤醒所有等待者
+    ///
+    /// 唤醒队列已满时，放不下的进程留在等待队列中，由下一次唤醒处理。
+    pub fn notify_all(&self) {
+        let wakeups = match WAKEUPS.get() {
+            Some(wakeups) => wakeups,
+            None => return,
+        };
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|&pid| wakeups.push(pid).is_err());
+        if !waiters.is_empty() {
+            warn!(
+                "too many pending wakeups, {} processes deferred",
+                waiters.len()
+            );
+        }
+    }
+}
+
+/// 取出所有待唤醒的进程号
+pub(super) fn take_wakeups() -> impl Iterator<Item = usize> {
+    let wakeups = WAKEUPS.get();
+    core::iter::from_fn(move || wakeups.and_then(ArrayQueue::pop))
+}
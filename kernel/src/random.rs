@@ -0,0 +1,47 @@
+//! 内核随机数
+//!
+//! 用于用户栈基址的随机化和 `AT_RANDOM` 等不要求密码学强度的场合。
+//! 处理器支持 `RDRAND` 时直接使用硬件随机数，否则使用以时间戳计数器为种子的 SplitMix64 序列。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::random::RdRand;
+
+/// SplitMix64 的状态，每次取数增加一个固定的奇数
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// 以时间戳计数器设置种子，需要在第一次取随机数之前调用
+pub fn init() {
+    let seed = unsafe { x86::time::rdtsc() };
+    STATE.store(seed, Ordering::Relaxed);
+    info!(
+        "random seeded, rdrand {}",
+        if RdRand::new().is_some() {
+            "available"
+        } else {
+            "unavailable"
+        }
+    );
+}
+
+/// 64 位随机数
+pub fn next_u64() -> u64 {
+    if let Some(value) = RdRand::new().and_then(|rdrand| rdrand.get_u64()) {
+        return value;
+    }
+    let mut z = STATE
+        .fetch_add(GAMMA, Ordering::Relaxed)
+        .wrapping_add(GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// 以随机数填充 `buf`
+pub fn fill(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
@@ -0,0 +1,24 @@
+//! 串口控制台
+//!
+//! 不依赖帧缓冲，在 `display::initialize` 之前即可使用，
+//! 配合 QEMU `-serial stdio` 可以在终端中查看内核输出。
+
+use boot::serial::{SerialPort, COM1};
+use core::fmt::{Arguments, Write};
+
+once_mutex!(pub SERIAL: SerialPort);
+
+guard_access_fn!(pub get_serial (SERIAL: SerialPort));
+
+pub fn initialize() {
+    let mut port = unsafe { SerialPort::new(COM1) };
+    port.init();
+    init_SERIAL(port);
+}
+
+/// 绕过锁直接输出，仅用于 panic 时锁可能已被持有的情况
+pub fn force_print(args: Arguments) {
+    if SERIAL.get().is_some() {
+        let _ = unsafe { SerialPort::new(COM1) }.write_fmt(args);
+    }
+}
@@ -0,0 +1,127 @@
+//! 应用处理器（AP）启动
+//!
+//! 引导程序退出启动服务后，AP 会被固件关闭，因此由内核通过 INIT-SIPI-SIPI 重新唤醒。
+//! AP 经由 `trampoline.s` 从实模式进入长模式，使用引导程序为其映射的栈进入 `ap_main`。
+//!
+//! 目前内核尚不支持多核调度，AP 启动后只加载 GDT/IDT 并停机等待。
+
+use boot::BootInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+use x86_64::registers::control::Cr3;
+
+core::arch::global_asm!(include_str!("trampoline.s"), options(att_syntax));
+
+extern "C" {
+    static AP_TRAMPOLINE_START: u8;
+    static AP_TRAMPOLINE_PROTECTED: u8;
+    static AP_TRAMPOLINE_LONG: u8;
+    static AP_TRAMPOLINE_GDT: u8;
+    static AP_TRAMPOLINE_GDTR: u8;
+    static AP_TRAMPOLINE_FAR32: u8;
+    static AP_TRAMPOLINE_FAR64: u8;
+    static AP_TRAMPOLINE_CR3: u8;
+    static AP_TRAMPOLINE_STACK: u8;
+    static AP_TRAMPOLINE_ENTRY: u8;
+    static AP_TRAMPOLINE_CPU: u8;
+    static AP_TRAMPOLINE_END: u8;
+}
+
+/// AP 等待启动的超时时间
+const STARTUP_TIMEOUT: Duration = Duration::from_millis(100);
+/// 等待 AP 启动时检查的间隔
+const STARTUP_POLL: Duration = Duration::from_micros(10);
+
+/// 已经上线的处理器数量，包括 BSP
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(1);
+/// 当前正在启动的 AP 是否已经进入内核
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 已经上线的处理器数量
+pub fn online_cpus() -> usize {
+    ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+/// 符号在跳板代码中的偏移
+fn offset_of(sym: &u8) -> usize {
+    sym as *const u8 as usize - unsafe { &AP_TRAMPOLINE_START } as *const u8 as usize
+}
+
+/// 跳板代码中某个字段的虚拟地址
+fn field<T>(base: usize, sym: &u8) -> *mut T {
+    crate::memory::physical_to_virtual(base + offset_of(sym)) as *mut T
+}
+
+/// 启动所有 AP，需要保证内存和中断已经初始化
+pub fn init(boot_info: &'static BootInfo) {
+    let base = boot_info.ap_trampoline_addr as usize;
+    if boot_info.cpus.len() <= 1 || base == 0 {
+        info!("single processor, skip AP startup");
+        return;
+    }
+
+    let cr3 = Cr3::read().0.start_address().as_u64();
+    assert!(cr3 < 0x1_0000_0000, "page table must be below 4GiB for APs");
+
+    // 1. 复制跳板代码，并填写依赖于其位置的绝对地址
+    unsafe {
+        let len = offset_of(&AP_TRAMPOLINE_END);
+        core::ptr::copy_nonoverlapping(
+            &AP_TRAMPOLINE_START as *const u8,
+            crate::memory::physical_to_virtual(base) as *mut u8,
+            len,
+        );
+        // GDTR 的基址位于 limit 之后
+        let gdtr = field::<u8>(base, &AP_TRAMPOLINE_GDTR);
+        (gdtr.add(2) as *mut u32).write_unaligned((base + offset_of(&AP_TRAMPOLINE_GDT)) as u32);
+        field::<u32>(base, &AP_TRAMPOLINE_FAR32)
+            .write_unaligned((base + offset_of(&AP_TRAMPOLINE_PROTECTED)) as u32);
+        field::<u32>(base, &AP_TRAMPOLINE_FAR64)
+            .write_unaligned((base + offset_of(&AP_TRAMPOLINE_LONG)) as u32);
+        field::<u64>(base, &AP_TRAMPOLINE_CR3).write_volatile(cr3);
+        field::<u64>(base, &AP_TRAMPOLINE_ENTRY).write_volatile(ap_main as usize as u64);
+    }
+
+    // 2. 逐个启动 AP，上一个进入内核后才修改参数启动下一个
+    for (id, cpu) in boot_info.cpus.iter().enumerate().filter(|(_, c)| !c.is_bsp) {
+        AP_STARTED.store(false, Ordering::Release);
+        unsafe {
+            field::<u64>(base, &AP_TRAMPOLINE_STACK).write_volatile(cpu.stack_top);
+            field::<u64>(base, &AP_TRAMPOLINE_CPU).write_volatile(id as u64);
+        }
+        crate::interrupts::start_ap(cpu.apic_id as u8, base as u32);
+
+        // 没有 HPET 时 `Instant` 依赖时钟中断，因此按间隔忙等待并累计时间
+        let mut waited = Duration::ZERO;
+        while !AP_STARTED.load(Ordering::Acquire) && waited < STARTUP_TIMEOUT {
+            crate::time::busy_wait(STARTUP_POLL);
+            waited += STARTUP_POLL;
+        }
+        if AP_STARTED.load(Ordering::Acquire) {
+            debug!("cpu {} (apic {}) started", id, cpu.apic_id);
+        } else {
+            warn!("cpu {} (apic {}) failed to start", id, cpu.apic_id);
+        }
+    }
+
+    info!(
+        "{} of {} processors online",
+        online_cpus(),
+        boot_info.cpus.len()
+    );
+}
+
+/// AP 进入内核的入口
+extern "C" fn ap_main(_cpu: u64) -> ! {
+    crate::gdt::init_ap();
+    crate::interrupts::init_ap();
+
+    ONLINE_CPUS.fetch_add(1, Ordering::AcqRel);
+    AP_STARTED.store(true, Ordering::Release);
+
+    // 尚未支持多核调度，关中断停机
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
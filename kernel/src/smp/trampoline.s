@@ -0,0 +1,103 @@
+# AP startup trampoline
+#
+# The BSP copies [AP_TRAMPOLINE_START, AP_TRAMPOLINE_END) to a 4KiB aligned
+# page below 1MiB, fills the parameters at the end and sends INIT-SIPI-SIPI.
+# The AP starts in real mode at CS:IP = (page >> 4):0, enters protected mode,
+# then long mode with the kernel page table, and calls the kernel entry with
+# the processor index in `rdi`.
+#
+# Fields marked as "patched" hold absolute addresses which depend on where the
+# trampoline is copied to, and are filled by the BSP before startup.
+
+.section .text.ap_trampoline, "ax"
+.code16
+.global AP_TRAMPOLINE_START
+AP_TRAMPOLINE_START:
+    cli
+    cld
+    mov %cs, %ax
+    mov %ax, %ds
+    # ebx = physical address of the trampoline
+    xor %ebx, %ebx
+    mov %cs, %bx
+    shl $4, %ebx
+
+    lgdtl (AP_TRAMPOLINE_GDTR - AP_TRAMPOLINE_START)
+    mov %cr0, %eax
+    or $1, %eax                     # PE
+    mov %eax, %cr0
+    ljmpl *(AP_TRAMPOLINE_FAR32 - AP_TRAMPOLINE_START)
+
+.code32
+.global AP_TRAMPOLINE_PROTECTED
+AP_TRAMPOLINE_PROTECTED:
+    mov $0x10, %ax
+    mov %ax, %ds
+    mov %ax, %es
+    mov %ax, %ss
+
+    mov %cr4, %eax
+    or $(1 << 5), %eax              # PAE
+    mov %eax, %cr4
+
+    mov (AP_TRAMPOLINE_CR3 - AP_TRAMPOLINE_START)(%ebx), %eax
+    mov %eax, %cr3
+
+    mov $0xC0000080, %ecx           # IA32_EFER
+    rdmsr
+    or $((1 << 8) | (1 << 11)), %eax  # LME | NXE
+    wrmsr
+
+    mov %cr0, %eax
+    or $0x80010000, %eax            # PG | WP
+    mov %eax, %cr0
+    ljmpl *(AP_TRAMPOLINE_FAR64 - AP_TRAMPOLINE_START)(%ebx)
+
+.code64
+.global AP_TRAMPOLINE_LONG
+AP_TRAMPOLINE_LONG:
+    mov %ebx, %ebx
+    mov (AP_TRAMPOLINE_STACK - AP_TRAMPOLINE_START)(%rbx), %rsp
+    mov (AP_TRAMPOLINE_CPU - AP_TRAMPOLINE_START)(%rbx), %rdi
+    mov (AP_TRAMPOLINE_ENTRY - AP_TRAMPOLINE_START)(%rbx), %rax
+    xor %ebp, %ebp
+    call *%rax
+1:
+    hlt
+    jmp 1b
+
+.balign 8
+.global AP_TRAMPOLINE_GDT
+AP_TRAMPOLINE_GDT:
+    .quad 0
+    .quad 0x00CF9A000000FFFF        # 0x08: 32-bit code
+    .quad 0x00CF92000000FFFF        # 0x10: data
+    .quad 0x00AF9A000000FFFF        # 0x18: 64-bit code
+.global AP_TRAMPOLINE_GDTR
+AP_TRAMPOLINE_GDTR:
+    .word 4 * 8 - 1
+    .long 0                         # patched: address of AP_TRAMPOLINE_GDT
+.global AP_TRAMPOLINE_FAR32
+AP_TRAMPOLINE_FAR32:
+    .long 0                         # patched: address of AP_TRAMPOLINE_PROTECTED
+    .word 0x08
+.global AP_TRAMPOLINE_FAR64
+AP_TRAMPOLINE_FAR64:
+    .long 0                         # patched: address of AP_TRAMPOLINE_LONG
+    .word 0x18
+
+.balign 8
+.global AP_TRAMPOLINE_CR3
+AP_TRAMPOLINE_CR3:
+    .quad 0
+.global AP_TRAMPOLINE_STACK
+AP_TRAMPOLINE_STACK:
+    .quad 0
+.global AP_TRAMPOLINE_ENTRY
+AP_TRAMPOLINE_ENTRY:
+    .quad 0
+.global AP_TRAMPOLINE_CPU
+AP_TRAMPOLINE_CPU:
+    .quad 0
+.global AP_TRAMPOLINE_END
+AP_TRAMPOLINE_END:
@@ -0,0 +1,88 @@
+//! HPET 主计数器
+//!
+//! 只使用主计数器作为时钟源，不使用比较器产生中断。
+//! 32 位的主计数器在常见的 14.3 MHz 下约 5 分钟回绕一次，由软件扩展为 64 位，
+//! 为此每次时钟中断都会读取一次计数器。
+//!
+//! Reference: IA-PC HPET Specification 1.0a, Section 2.3
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// General Capabilities and ID Register
+const CAPABILITIES: usize = 0x000;
+/// General Configuration Register
+const CONFIG: usize = 0x010;
+/// Main Counter Value Register
+const MAIN_COUNTER: usize = 0x0F0;
+/// 使能主计数器
+const ENABLE_CNF: u64 = 1;
+/// 主计数器为 64 位
+const COUNT_SIZE_CAP: u64 = 1 << 13;
+
+pub struct Hpet {
+    base: usize,
+    /// 计数器周期，单位为飞秒
+    period_fs: u64,
+    /// 主计数器为 64 位，否则只有低 32 位有效
+    wide: bool,
+    /// 上次读到的计数，32 位计数器在此基础上检测回绕
+    last: AtomicU64,
+}
+
+impl Hpet {
+    /// 将主计数器清零并开始计数
+    ///
+    /// # Safety
+    ///
+    /// `base` 必须是 HPET 寄存器的虚拟地址。
+    pub unsafe fn init(base: usize) -> Self {
+        let mut hpet = Self {
+            base,
+            period_fs: 0,
+            wide: false,
+            last: AtomicU64::new(0),
+        };
+        unsafe {
+            let capabilities = hpet.read(CAPABILITIES);
+            hpet.period_fs = capabilities >> 32;
+            hpet.wide = capabilities & COUNT_SIZE_CAP != 0;
+            let config = hpet.read(CONFIG);
+            hpet.write(CONFIG, config & !ENABLE_CNF);
+            hpet.write(MAIN_COUNTER, 0);
+            hpet.write(CONFIG, config | ENABLE_CNF);
+        }
+        hpet
+    }
+
+    unsafe fn read(&self, reg: usize) -> u64 {
+        unsafe { read_volatile((self.base + reg) as *const u64) }
+    }
+
+    unsafe fn write(&mut self, reg: usize, value: u64) {
+        unsafe { write_volatile((self.base + reg) as *mut u64, value) }
+    }
+
+    /// 开始计数以来的纳秒数
+    pub fn nanos(&self) -> u64 {
+        (self.counter() as u128 * self.period_fs as u128 / 1_000_000) as u64
+    }
+
+    /// 扩展为 64 位的主计数器
+    ///
+    /// 32 位计数器的值小于上次读到的低 32 位时视为回绕了一次，
+    /// 因此两次读取的间隔不能超过一个回绕周期。
+    fn counter(&self) -> u64 {
+        let counter = unsafe { self.read(MAIN_COUNTER) };
+        if self.wide {
+            return counter;
+        }
+        let last = self.last.load(Ordering::Relaxed);
+        let mut now = (last & !0xFFFF_FFFF) | (counter & 0xFFFF_FFFF);
+        if now < last {
+            now += 1 << 32;
+        }
+        // 其他处理器可能同时更新，取较大的值保证单调
+        self.last.fetch_max(now, Ordering::Relaxed).max(now)
+    }
+}
@@ -0,0 +1,135 @@
+//! 内核计时
+//!
+//! 启动时以 PIT 为参照校准 Local APIC 定时器，使时钟中断的频率为 `TICK_HZ`。
+//! 单调时钟 `Instant` 优先使用 HPET 主计数器，没有 HPET 时退回到时钟中断计数，精度为一个周期。
+//! 墙上时间由启动时读取的 UEFI 时间加上单调时钟得到，之后不再访问 UEFI 运行时服务。
+
+mod hpet;
+mod pit;
+mod wheel;
+
+pub use wheel::TimerWheel;
+
+use core::ops::Add;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use spin::Once;
+
+/// 时钟中断的频率
+pub const TICK_HZ: u64 = 100;
+/// 时钟中断的周期
+pub const TICK_NS: u64 = 1_000_000_000 / TICK_HZ;
+/// 校准 Local APIC 定时器时等待的时间
+const CALIBRATE_US: u64 = 10_000;
+
+static HPET: Once<hpet::Hpet> = Once::new();
+/// 时钟中断次数
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// 单调时钟零点对应的 Unix 时间
+static BOOT_REALTIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 单调时钟上的时间点，从内核初始化计时开始计算
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        match HPET.get() {
+            Some(hpet) => Self(hpet.nanos()),
+            None => Self(ticks() * TICK_NS),
+        }
+    }
+
+    pub fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    /// 从 `earlier` 到现在经过的时间，`earlier` 较晚时为 0
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// 溢出时饱和
+    fn add(self, rhs: Duration) -> Instant {
+        let nanos = rhs.as_nanos().min(u64::MAX as u128) as u64;
+        Instant(self.0.saturating_add(nanos))
+    }
+}
+
+/// 初始化计时，需要在中断和 UEFI 时钟初始化之后、开中断之前调用
+pub fn init() {
+    if let Some(addr) = crate::acpi::hpet_addr() {
+        let base = crate::memory::physical_to_virtual(addr as usize);
+        HPET.call_once(|| unsafe { hpet::Hpet::init(base) });
+    } else {
+        warn!("no HPET, monotonic clock falls back to timer ticks");
+    }
+
+    // 从最大值开始倒数，等待一段时间后计算总线频率
+    crate::interrupts::set_timer_count(u32::MAX);
+    pit::wait_us(CALIBRATE_US);
+    let elapsed = u32::MAX - crate::interrupts::timer_count();
+    let count = elapsed as u64 * 1_000_000 / CALIBRATE_US / TICK_HZ;
+    crate::interrupts::set_timer_count(count.clamp(1, u32::MAX as u64) as u32);
+    info!(
+        "timer calibrated: bus {} MHz, {} Hz tick",
+        elapsed as u64 / CALIBRATE_US,
+        TICK_HZ
+    );
+
+    let realtime = crate::uefi_clock::get_clock_sure().now().timestamp_nanos();
+    let boot = (realtime.max(0) as u64).saturating_sub(Instant::now().as_nanos());
+    BOOT_REALTIME_NS.store(boot, Ordering::Relaxed);
+}
+
+/// 在时钟中断中调用
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    // 定期读取 HPET，使 32 位的主计数器不会在两次读取之间回绕多次
+    if let Some(hpet) = HPET.get() {
+        hpet.nanos();
+    }
+}
+
+/// 时钟中断次数
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// 到达 `deadline` 时的时钟中断序号，用于 `TimerWheel`
+///
+/// 时钟中断在开中断后才开始计数，因此以当前的计数为基准换算。
+pub fn deadline_tick(deadline: Instant) -> u64 {
+    let remaining = deadline.0.saturating_sub(Instant::now().0);
+    ticks().saturating_add(remaining.saturating_add(TICK_NS - 1) / TICK_NS)
+}
+
+/// 从 Unix 纪元开始的墙上时间
+pub fn realtime() -> Duration {
+    Duration::from_nanos(BOOT_REALTIME_NS.load(Ordering::Relaxed) + Instant::now().as_nanos())
+}
+
+/// 忙等待 `duration`，用于不能阻塞的内核代码，不依赖时钟中断
+pub fn busy_wait(duration: Duration) {
+    if HPET.get().is_none() {
+        let mut remaining = duration.as_micros() as u64;
+        while remaining > 0 {
+            let us = remaining.min(50_000);
+            pit::wait_us(us);
+            remaining -= us;
+        }
+        return;
+    }
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        core::hint::spin_loop();
+    }
+}
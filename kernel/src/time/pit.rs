@@ -0,0 +1,35 @@
+//! 使用 8254 PIT 的通道 2 忙等待，仅用于校准 Local APIC 定时器
+//!
+//! Reference: [OSDev Wiki](https://wiki.osdev.org/Programmable_Interval_Timer)
+
+use x86_64::instructions::port::Port;
+
+/// PIT 的输入频率
+const PIT_HZ: u64 = 1_193_182;
+const CHANNEL2_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+/// 键盘控制器端口 B：位 0 为通道 2 的门控，位 1 为扬声器，位 5 为通道 2 的输出
+const PORT_B: u16 = 0x61;
+
+/// 等待 `us` 微秒，最长约 54ms
+pub fn wait_us(us: u64) {
+    let count = (PIT_HZ * us / 1_000_000).min(0xFFFF) as u16;
+    let mut port_b = Port::<u8>::new(PORT_B);
+    let mut command = Port::<u8>::new(COMMAND_PORT);
+    let mut channel2 = Port::<u8>::new(CHANNEL2_PORT);
+    unsafe {
+        // 关闭门控和扬声器
+        let gate = port_b.read() & !0x03;
+        port_b.write(gate);
+        // 通道 2，先低后高字节，模式 0：计数到 0 时输出变为高电平
+        command.write(0b1011_0000);
+        channel2.write(count as u8);
+        channel2.write((count >> 8) as u8);
+        // 打开门控开始计数
+        port_b.write(gate | 0x01);
+        while port_b.read() & 0x20 == 0 {
+            core::hint::spin_loop();
+        }
+        port_b.write(gate);
+    }
+}
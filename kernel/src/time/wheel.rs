@@ -0,0 +1,53 @@
+//! 时间轮
+//!
+//! 定时器按到期的时钟中断序号放入 `SLOTS` 个槽中，每次时钟中断只检查经过的槽，
+//! 插入和到期的代价与定时器总数无关。到期时间超过一圈的定时器会在槽中停留多圈。
+
+use alloc::vec::Vec;
+
+const SLOTS: usize = 64;
+
+pub struct TimerWheel<T> {
+    slots: [Vec<(u64, T)>; SLOTS],
+    /// 已经检查过的时钟中断序号
+    current: u64,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new(now: u64) -> Self {
+        Self {
+            slots: [(); SLOTS].map(|_| Vec::new()),
+            current: now,
+        }
+    }
+
+    /// 加入在第 `tick` 次时钟中断到期的定时器，已经过去的时间视为下一次时钟中断
+    pub fn insert(&mut self, tick: u64, value: T) {
+        let tick = tick.max(self.current + 1);
+        self.slots[tick as usize % SLOTS].push((tick, value));
+    }
+
+    /// 前进到第 `now` 次时钟中断，取出所有到期的定时器
+    pub fn expire(&mut self, now: u64) -> Vec<T> {
+        let mut expired = Vec::new();
+        // 经过一圈以上时，所有槽都需要检查
+        let start = self.current.max(now.saturating_sub(SLOTS as u64 - 1));
+        for tick in start..=now {
+            let slot = &mut self.slots[tick as usize % SLOTS];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].0 <= now {
+                    expired.push(slot.swap_remove(i).1);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.current = self.current.max(now);
+        expired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Vec::is_empty)
+    }
+}
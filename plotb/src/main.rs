@@ -18,8 +18,8 @@ const COLORS: [u32; 19] = [
     0x00795548, 0x009e9e9e, 0x00607d8b,
 ];
 
-#[export_name = "_start"]
-pub extern "C" fn __impl_start() -> ! {
+#[no_mangle]
+pub fn main() -> i32 {
     let (base_x, base_y, max_x, max_y) = (400, 0, 800, 600);
     Rectangle::new(
         Point::new(base_x as i32, base_y as i32),
@@ -71,5 +71,5 @@ pub extern "C" fn __impl_start() -> ! {
         }
     }
 
-    xlibr::sys_exit()
+    0
 }
@@ -6,8 +6,12 @@ extern crate rlibc;
 #[macro_use]
 extern crate xlibr;
 
-#[export_name = "_start"]
-pub extern "C" fn __impl_start() -> ! {
+#[no_mangle]
+pub fn main() -> i32 {
+    for (i, arg) in xlibr::args().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
+    println!("PATH={}", xlibr::env("PATH").unwrap_or(""));
     println!("input a char, string, space and a integer");
     let ch = xlibr::read_char();
     let mut s = [0; 80];
@@ -19,5 +23,5 @@ pub extern "C" fn __impl_start() -> ! {
         a,
         core::str::from_utf8(&ss).unwrap()
     );
-    xlibr::sys_exit()
+    0
 }
@@ -0,0 +1,451 @@
+//! Definitions shared by the kernel and user programs for the syscall ABI
+//!
+//! A syscall passes its number in `rax` and up to three arguments in `rdi`,
+//! `rsi` and `rdx`, and returns a single `u64` in `rax`. Values in
+//! `-4095..=-1`, viewed as `i64`, are negated [`Errno`] codes, anything else is
+//! a successful result.
+//!
+//! All syscalls are listed once in the `syscalls!` invocation in `table.rs`,
+//! which generates the [`Syscall`] numbers, the [`Handler`] trait implemented by
+//! the kernel together with [`dispatch`], and the stubs in [`raw`] used by
+//! user programs.
+//!
+//! A new process starts with its stack pointer at `argc`, followed by the
+//! null-terminated `argv` and `envp` pointer arrays and the auxiliary vector of
+//! `(type, value)` pairs ending with [`AT_NULL`], as in the System V x86-64
+//! ABI. The strings are NUL-terminated and stay valid for the lifetime of the
+//! process.
+
+#![no_std]
+#![warn(unsafe_op_in_unsafe_fn)]
+
+#[macro_use]
+mod macros;
+mod table;
+
+use core::fmt;
+
+pub use table::*;
+
+/// Version of the ABI, bumped on every incompatible change
+pub const ABI_VERSION: u64 = 7;
+
+/// Size of the display returned by `DisplayResolution`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u64,
+    pub height: u64,
+}
+
+/// Pixel set by `PlotPixel`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pixel {
+    pub x: u64,
+    pub y: u64,
+    /// Color as `0xRRGGBB`
+    pub color: u32,
+}
+
+/// Blocks read by `ReadDisk`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskRead {
+    /// First block to read
+    pub block: u64,
+    /// Buffer in the memory of the calling process
+    pub ptr: u64,
+    /// Size of the buffer in bytes, a multiple of the block size
+    pub len: u64,
+}
+
+/// Wall-clock time since the Unix epoch
+pub const CLOCK_REALTIME: u64 = 0;
+/// Time since boot, never goes backwards
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/// Time returned by `ClockGetTime`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timespec {
+    pub sec: u64,
+    pub nsec: u64,
+}
+
+/// Signal number of an invalid memory access
+///
+/// A process killed by the kernel exits with the negated signal number as its
+/// exit code.
+pub const SIGSEGV: i32 = 11;
+/// Signal number of a process terminated by `Kill`
+pub const SIGKILL: i32 = 9;
+
+/// Bytes of the name in [`ProcessInfo`], longer names are truncated
+pub const PROCESS_NAME_LEN: usize = 16;
+
+/// Values of [`ProcessInfo::state`]
+pub const PROCESS_READY: u64 = 0;
+pub const PROCESS_RUNNING: u64 = 1;
+pub const PROCESS_BLOCKED: u64 = 2;
+pub const PROCESS_SLEEPING: u64 = 3;
+pub const PROCESS_ZOMBIE: u64 = 4;
+
+/// A process as listed by `ListProcesses`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u64,
+    pub parent: u64,
+    /// One of the `PROCESS_*` states
+    pub state: u64,
+    /// Priority, lower values run first
+    pub nice: i64,
+    /// CPU time used, in nanoseconds
+    pub cpu_time_ns: u64,
+    /// Resident memory in bytes
+    pub memory: u64,
+    /// The name, padded with NUL bytes
+    pub name: [u8; PROCESS_NAME_LEN],
+}
+
+impl ProcessInfo {
+    /// Set the name, truncated to [`PROCESS_NAME_LEN`] bytes.
+    pub fn set_name(&mut self, name: &str) {
+        let len = name.len().min(PROCESS_NAME_LEN);
+        self.name = [0; PROCESS_NAME_LEN];
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    }
+
+    /// The name, up to the first NUL byte or invalid UTF-8.
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(PROCESS_NAME_LEN);
+        match core::str::from_utf8(&self.name[..len]) {
+            Ok(name) => name,
+            Err(err) => core::str::from_utf8(&self.name[..err.valid_up_to()]).unwrap(),
+        }
+    }
+
+    /// The state as a lowercase word.
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            PROCESS_READY => "ready",
+            PROCESS_RUNNING => "running",
+            PROCESS_BLOCKED => "blocked",
+            PROCESS_SLEEPING => "sleeping",
+            PROCESS_ZOMBIE => "zombie",
+            _ => "unknown",
+        }
+    }
+}
+
+/// End of the auxiliary vector
+pub const AT_NULL: u64 = 0;
+/// Address of the program headers
+pub const AT_PHDR: u64 = 3;
+/// Size of one program header
+pub const AT_PHENT: u64 = 4;
+/// Number of program headers
+pub const AT_PHNUM: u64 = 5;
+/// Size of a page in bytes
+pub const AT_PAGESZ: u64 = 6;
+/// Entry point of the program
+pub const AT_ENTRY: u64 = 9;
+/// Address of 16 random bytes
+pub const AT_RANDOM: u64 = 25;
+
+/// A string in the memory of the calling process
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrRef {
+    pub ptr: u64,
+    pub len: u64,
+}
+
+impl StrRef {
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr() as u64,
+            len: s.len() as u64,
+        }
+    }
+}
+
+/// Result of a syscall as seen on either side of the ABI
+pub type SyscallResult = Result<u64, Errno>;
+
+/// The largest error code that can be encoded in a return value
+const MAX_ERRNO: u64 = 4095;
+
+/// Error codes, numbered as in Linux
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    /// Operation not permitted
+    EPERM = 1,
+    /// No such file or directory
+    ENOENT = 2,
+    /// No such process
+    ESRCH = 3,
+    /// I/O error
+    EIO = 5,
+    /// Argument list too long
+    E2BIG = 7,
+    /// Exec format error
+    ENOEXEC = 8,
+    /// No child processes
+    ECHILD = 10,
+    /// Out of memory
+    ENOMEM = 12,
+    /// Bad address
+    EFAULT = 14,
+    /// Invalid argument
+    EINVAL = 22,
+    /// Function not implemented
+    ENOSYS = 38,
+}
+
+impl Errno {
+    const ALL: [Errno; 11] = [
+        Errno::EPERM,
+        Errno::ENOENT,
+        Errno::ESRCH,
+        Errno::EIO,
+        Errno::E2BIG,
+        Errno::ENOEXEC,
+        Errno::ECHILD,
+        Errno::ENOMEM,
+        Errno::EFAULT,
+        Errno::EINVAL,
+        Errno::ENOSYS,
+    ];
+
+    pub fn from_code(code: u64) -> Option<Self> {
+        Self::ALL.iter().copied().find(|&e| e as u64 == code)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Errno::EPERM => "operation not permitted",
+            Errno::ENOENT => "no such file or directory",
+            Errno::ESRCH => "no such process",
+            Errno::EIO => "I/O error",
+            Errno::E2BIG => "argument list too long",
+            Errno::ENOEXEC => "exec format error",
+            Errno::ECHILD => "no child processes",
+            Errno::ENOMEM => "out of memory",
+            Errno::EFAULT => "bad address",
+            Errno::EINVAL => "invalid argument",
+            Errno::ENOSYS => "function not implemented",
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// Enter the kernel with `syscall`, which clobbers `rcx` and `r11`
+///
+/// # Safety
+///
+/// The arguments must be valid for syscall `id`.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn invoke(id: u64, args: [u64; 3]) -> SyscallResult {
+    let ret: u64;
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            inlateout("rax") id => ret,
+            in("rdi") args[0],
+            in("rsi") args[1],
+            in("rdx") args[2],
+            out("rcx") _,
+            out("r11") _,
+            options(nostack),
+        );
+    }
+    decode(ret)
+}
+
+/// Encode a result into the value returned in `rax`
+pub fn encode(result: SyscallResult) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(errno) => (errno as u64).wrapping_neg(),
+    }
+}
+
+/// Decode the value returned in `rax`
+///
+/// Unknown error codes are reported as `EINVAL`.
+pub fn decode(ret: u64) -> SyscallResult {
+    if ret.wrapping_neg() <= MAX_ERRNO && ret != 0 {
+        Err(Errno::from_code(ret.wrapping_neg()).unwrap_or(Errno::EINVAL))
+    } else {
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for &value in &[0, 1, 0x1000_0000_0000, u64::MAX - MAX_ERRNO] {
+            assert_eq!(decode(encode(Ok(value))), Ok(value));
+        }
+        for &errno in &Errno::ALL {
+            assert_eq!(decode(encode(Err(errno))), Err(errno));
+        }
+    }
+
+    #[test]
+    fn syscall_numbers() {
+        for &syscall in Syscall::ALL {
+            assert_eq!(Syscall::from_id(syscall as u64), Some(syscall));
+        }
+        assert_eq!(Syscall::from_id(0), None);
+        assert_eq!(Syscall::Brk.name(), "brk");
+    }
+
+    #[test]
+    fn process_info_name() {
+        let mut info = ProcessInfo::default();
+        info.set_name("sampleio");
+        assert_eq!(info.name(), "sampleio");
+        info.set_name("a-very-long-process-name");
+        assert_eq!(info.name(), "a-very-long-proc");
+        // a multi-byte character cut in half is dropped
+        info.set_name("abcdefghijklmno\u{e9}");
+        assert_eq!(info.name(), "abcdefghijklmno");
+        info.state = PROCESS_ZOMBIE;
+        assert_eq!(info.state_name(), "zombie");
+    }
+
+    struct Recorder(Option<(&'static str, [u64; 3])>);
+
+    impl Handler for Recorder {
+        fn spawn_process(&mut self) -> SyscallResult {
+            self.0 = Some(("spawn_process", [0; 3]));
+            Ok(0)
+        }
+        fn exit_process(&mut self, code: u64) -> SyscallResult {
+            self.0 = Some(("exit_process", [code, 0, 0]));
+            Ok(0)
+        }
+        fn print_str(&mut self, s: u64) -> SyscallResult {
+            self.0 = Some(("print_str", [s, 0, 0]));
+            Ok(0)
+        }
+        fn read_key(&mut self, ptr: u64) -> SyscallResult {
+            self.0 = Some(("read_key", [ptr, 0, 0]));
+            Ok(0)
+        }
+        fn plot_pixel(&mut self, pixel: u64) -> SyscallResult {
+            self.0 = Some(("plot_pixel", [pixel, 0, 0]));
+            Ok(0)
+        }
+        fn sleep(&mut self, ns: u64) -> SyscallResult {
+            self.0 = Some(("sleep", [ns, 0, 0]));
+            Ok(0)
+        }
+        fn display_resolution(&mut self, ptr: u64) -> SyscallResult {
+            self.0 = Some(("display_resolution", [ptr, 0, 0]));
+            Ok(0)
+        }
+        fn read_disk(&mut self, req: u64) -> SyscallResult {
+            self.0 = Some(("read_disk", [req, 0, 0]));
+            Err(Errno::EIO)
+        }
+        fn brk(&mut self, addr: u64) -> SyscallResult {
+            self.0 = Some(("brk", [addr, 0, 0]));
+            Ok(addr)
+        }
+        fn abi_version(&mut self) -> SyscallResult {
+            self.0 = Some(("abi_version", [0; 3]));
+            Ok(ABI_VERSION)
+        }
+        fn spawn(&mut self, path: u64, argv: u64, argc: u64) -> SyscallResult {
+            self.0 = Some(("spawn", [path, argv, argc]));
+            Ok(1)
+        }
+        fn exec(&mut self, path: u64, argv: u64, argc: u64) -> SyscallResult {
+            self.0 = Some(("exec", [path, argv, argc]));
+            Ok(0)
+        }
+        fn waitpid(&mut self, pid: u64) -> SyscallResult {
+            self.0 = Some(("waitpid", [pid, 0, 0]));
+            Err(Errno::ECHILD)
+        }
+        fn getpid(&mut self) -> SyscallResult {
+            self.0 = Some(("getpid", [0; 3]));
+            Ok(1)
+        }
+        fn clock_gettime(&mut self, clock: u64, ptr: u64) -> SyscallResult {
+            self.0 = Some(("clock_gettime", [clock, ptr, 0]));
+            Ok(0)
+        }
+        fn fork(&mut self) -> SyscallResult {
+            self.0 = Some(("fork", [0; 3]));
+            Ok(2)
+        }
+        fn list_processes(&mut self, ptr: u64, len: u64) -> SyscallResult {
+            self.0 = Some(("list_processes", [ptr, len, 0]));
+            Ok(len)
+        }
+        fn kill(&mut self, pid: u64) -> SyscallResult {
+            self.0 = Some(("kill", [pid, 0, 0]));
+            Err(Errno::ESRCH)
+        }
+        fn set_priority(&mut self, pid: u64, nice: u64) -> SyscallResult {
+            self.0 = Some(("set_priority", [pid, nice, 0]));
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn dispatch_arguments() {
+        let mut handler = Recorder(None);
+        let ret = dispatch(&mut handler, Syscall::PlotPixel as u64, [1, 2, 3]);
+        assert_eq!(ret, Ok(0));
+        assert_eq!(handler.0, Some(("plot_pixel", [1, 0, 0])));
+
+        let ret = dispatch(&mut handler, Syscall::Brk as u64, [0x1000, 7, 8]);
+        assert_eq!(ret, Ok(0x1000));
+        assert_eq!(handler.0, Some(("brk", [0x1000, 0, 0])));
+
+        let ret = dispatch(&mut handler, Syscall::ReadDisk as u64, [4, 5, 6]);
+        assert_eq!(ret, Err(Errno::EIO));
+        assert_eq!(handler.0, Some(("read_disk", [4, 0, 0])));
+
+        handler.0 = None;
+        assert_eq!(dispatch(&mut handler, 0xdead, [0; 3]), Err(Errno::ENOSYS));
+        assert_eq!(handler.0, None);
+    }
+
+    #[test]
+    fn argument_layouts() {
+        use core::mem::size_of;
+        assert_eq!(size_of::<StrRef>(), 16);
+        assert_eq!(size_of::<Pixel>(), 24);
+        assert_eq!(size_of::<DiskRead>(), 24);
+        assert_eq!(size_of::<Resolution>(), 16);
+        assert_eq!(size_of::<Timespec>(), 16);
+    }
+
+    #[test]
+    fn error_encoding() {
+        assert_eq!(encode(Err(Errno::ENOSYS)), -38_i64 as u64);
+        assert_eq!(decode(-14_i64 as u64), Err(Errno::EFAULT));
+        assert_eq!(decode(-4095_i64 as u64), Err(Errno::EINVAL));
+        assert_eq!(decode(-4096_i64 as u64), Ok(-4096_i64 as u64));
+    }
+}
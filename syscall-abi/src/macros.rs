@@ -0,0 +1,79 @@
+/// Generate the syscall numbers, the kernel handler trait and the user stubs
+/// from one table
+macro_rules! syscalls {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident = $id:literal => fn $func:ident($($arg:ident),*);
+    )*) => {
+        /// Syscall numbers
+        #[repr(u64)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Syscall {
+            $($(#[$meta])* $name = $id,)*
+        }
+
+        impl Syscall {
+            pub const ALL: &'static [Syscall] = &[$(Syscall::$name),*];
+
+            pub fn from_id(id: u64) -> Option<Self> {
+                match id {
+                    $($id => Some(Syscall::$name),)*
+                    _ => None,
+                }
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Syscall::$name => stringify!($func),)*
+                }
+            }
+        }
+
+        /// Kernel side of the syscalls, one method per syscall
+        pub trait Handler {
+            $($(#[$meta])* fn $func(&mut self, $($arg: u64),*) -> $crate::SyscallResult;)*
+        }
+
+        /// Call the method of `handler` for syscall `id`
+        ///
+        /// Unknown numbers return `ENOSYS`.
+        pub fn dispatch<H: Handler + ?Sized>(
+            handler: &mut H,
+            id: u64,
+            args: [u64; 3],
+        ) -> $crate::SyscallResult {
+            #[allow(unused_variables, unused_mut)]
+            let mut args = args.iter().copied();
+            match Syscall::from_id(id) {
+                $(Some(Syscall::$name) => handler.$func(
+                    $({
+                        let _ = stringify!($arg);
+                        args.next().unwrap_or(0)
+                    }),*
+                ),)*
+                None => Err($crate::Errno::ENOSYS),
+            }
+        }
+
+        /// User side stubs entering the kernel with `syscall`
+        pub mod raw {
+            $(
+                $(#[$meta])*
+                ///
+                /// # Safety
+                ///
+                /// Pointer arguments must be valid as described above.
+                #[allow(unused_mut, unused_assignments, unused_variables)]
+                pub unsafe fn $func($($arg: u64),*) -> $crate::SyscallResult {
+                    let mut args = [0; 3];
+                    let mut i = 0;
+                    $(
+                        args[i] = $arg;
+                        i += 1;
+                    )*
+                    unsafe { $crate::invoke(super::Syscall::$name as u64, args) }
+                }
+            )*
+        }
+    };
+}
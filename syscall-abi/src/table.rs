@@ -0,0 +1,73 @@
+//! The list of all syscalls
+//!
+//! Arguments are raw `u64` values in register order. Calls that need more
+//! than a few plain numbers take a pointer to one of the `#[repr(C)]` argument
+//! structs of this crate instead. Pointers refer to the memory of the calling
+//! process.
+
+syscalls! {
+    /// Resume the processes prepared by the kernel shell, only used by the kernel
+    SpawnProcess = 1 => fn spawn_process();
+    /// Terminate the calling process with exit `code`, never returns
+    ExitProcess = 2 => fn exit_process(code);
+    /// Print the UTF-8 string described by the [`StrRef`](crate::StrRef) at
+    /// `s`, returns its length
+    PrintStr = 5 => fn print_str(s);
+    /// Wait for a key press and write it as `Option<pc_keyboard::DecodedKey>`
+    /// to `ptr`
+    ///
+    /// The calling process is blocked until a key is available, only the
+    /// kernel gets `None` instead.
+    ReadKey = 6 => fn read_key(ptr);
+    /// Set the pixel described by the [`Pixel`](crate::Pixel) at `pixel`
+    PlotPixel = 7 => fn plot_pixel(pixel);
+    /// Block the calling process for at least `ns` nanoseconds
+    Sleep = 8 => fn sleep(ns);
+    /// Write the display size as a [`Resolution`](crate::Resolution) to `ptr`
+    DisplayResolution = 9 => fn display_resolution(ptr);
+    /// Read the blocks described by the [`DiskRead`](crate::DiskRead) at `req`
+    ReadDisk = 12 => fn read_disk(req);
+    /// Set the end of the process heap to `addr`, returns the new end
+    ///
+    /// The heap is left unchanged if `addr` is 0 or invalid.
+    Brk = 13 => fn brk(addr);
+    /// Returns [`ABI_VERSION`](crate::ABI_VERSION) of the kernel
+    AbiVersion = 14 => fn abi_version();
+    /// Start the program named by the [`StrRef`](crate::StrRef) at `path` as a
+    /// child process, with the `argc` arguments at `argv`, returns its pid
+    ///
+    /// The arguments follow the program name in the `argv` of the child, which
+    /// inherits the environment of the caller.
+    Spawn = 15 => fn spawn(path, argv, argc);
+    /// Replace the calling process with the program at `path`, like
+    /// [`Spawn`](Syscall::Spawn), only returns on failure
+    Exec = 16 => fn exec(path, argv, argc);
+    /// Wait for the child `pid` to exit, returns its exit code
+    WaitPid = 17 => fn waitpid(pid);
+    /// Returns the pid of the calling process
+    GetPid = 18 => fn getpid();
+    /// Write the time of `clock` as a [`Timespec`](crate::Timespec) to `ptr`
+    ///
+    /// `clock` is [`CLOCK_REALTIME`](crate::CLOCK_REALTIME) or
+    /// [`CLOCK_MONOTONIC`](crate::CLOCK_MONOTONIC).
+    ClockGetTime = 19 => fn clock_gettime(clock, ptr);
+    /// Duplicate the calling process, returns the pid of the child to the
+    /// parent and 0 to the child
+    ///
+    /// The memory of both processes is shared copy-on-write.
+    Fork = 20 => fn fork();
+    /// Write up to `len` [`ProcessInfo`](crate::ProcessInfo) entries to
+    /// `ptr`, returns the total number of processes
+    ListProcesses = 21 => fn list_processes(ptr, len);
+    /// Terminate the process `pid`, which exits with
+    /// `-`[`SIGKILL`](crate::SIGKILL)
+    ///
+    /// A process may only kill itself and its children, `EPERM` otherwise.
+    Kill = 22 => fn kill(pid);
+    /// Set the nice value of the process `pid`, clamped to `-20..=19`
+    ///
+    /// `nice` is an `i64` in two's complement. A process may only renice
+    /// itself and its children, and only to a larger value, `EPERM`
+    /// otherwise.
+    SetPriority = 23 => fn set_priority(pid, nice);
+}
@@ -1,16 +1,50 @@
-pub struct SystemAllocator;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+use linked_list_allocator::LockedHeap;
 
-unsafe impl alloc::alloc::GlobalAlloc for SystemAllocator {
-    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-        crate::sys_allocate(&layout)
+/// The minimal size to grow the heap by
+const GROW_SIZE: usize = 64 * 1024;
+
+/// Allocate from the process heap, which grows with `sys_brk` on demand
+pub struct SystemAllocator {
+    heap: LockedHeap,
+}
+
+unsafe impl GlobalAlloc for SystemAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.heap.lock();
+        loop {
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+            let size = (layout.size() + layout.align()).max(GROW_SIZE);
+            if heap.size() == 0 {
+                let start = crate::sys_brk(0) as usize;
+                if crate::sys_brk((start + size) as u64) as usize != start + size {
+                    return null_mut();
+                }
+                heap.init(start, size);
+            } else {
+                let top = heap.top();
+                if crate::sys_brk((top + size) as u64) as usize != top + size {
+                    return null_mut();
+                }
+                heap.extend(size);
+            }
+        }
     }
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
-        crate::sys_deallocate(ptr, &layout)
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap
+            .lock()
+            .deallocate(core::ptr::NonNull::new_unchecked(ptr), layout)
     }
 }
 
 #[global_allocator]
-static ALLOCATOR: SystemAllocator = SystemAllocator;
+static ALLOCATOR: SystemAllocator = SystemAllocator {
+    heap: LockedHeap::empty(),
+};
 
 #[cfg(not(test))]
 #[alloc_error_handler]
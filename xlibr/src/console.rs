@@ -4,8 +4,7 @@ pub struct Console;
 
 impl Write for Console {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        crate::sys_print_str(s);
-        Ok(())
+        crate::sys_print_str(s).map(|_| ()).map_err(|_| Error)
     }
 }
 
@@ -7,7 +7,7 @@ pub struct SysDisplay;
 
 impl Dimensions for SysDisplay {
     fn bounding_box(&self) -> embedded_graphics::primitives::Rectangle {
-        let (x, y) = crate::sys_display_resolution();
+        let (x, y) = crate::sys_display_resolution().unwrap_or((0, 0));
         Rectangle::new(Point::new(0, 0), Size::new(x as u32, y as u32))
     }
 }
@@ -23,9 +23,14 @@ impl<'a> DrawTarget for SysDisplay {
         for pixel in pixels {
             let Pixel(coord, color) = pixel;
 
-            crate::sys_plot_pixel(
-                if coord.x < 0 { 0 } else { coord.x as usize },
-                if coord.y < 0 { 0 } else { coord.y as usize },
+            // pixels out of the screen are skipped, the kernel fails with
+            // `EINVAL` for those past the right or bottom edge
+            if coord.x < 0 || coord.y < 0 {
+                continue;
+            }
+            let _ = crate::sys_plot_pixel(
+                coord.x as usize,
+                coord.y as usize,
                 (color.r() as u32) << 16 | (color.g() as u32) << 8 | (color.b() as u32),
             );
         }
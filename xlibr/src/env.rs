@@ -0,0 +1,117 @@
+//! Arguments, environment and auxiliary vector of the process
+//!
+//! All of them live on the initial stack set up by the kernel, see
+//! `syscall_abi`, and are recorded by `_start` before `main` runs.
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+pub use syscall_abi::{AT_ENTRY, AT_NULL, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_RANDOM};
+
+/// The initial stack pointer, which points to `argc`
+static INITIAL_SP: AtomicPtr<u64> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Record the initial stack of the process.
+///
+/// # Safety
+///
+/// `sp` must point to the initial stack laid out by the kernel.
+pub(crate) unsafe fn init(sp: *const u64) {
+    INITIAL_SP.store(sp as *mut u64, Ordering::Relaxed);
+}
+
+/// A null-terminated array of pointers, returns the array without the null
+/// pointer and the address right after it.
+///
+/// # Safety
+///
+/// `start` must point to a null-terminated array of pointers.
+unsafe fn null_terminated(start: *const u64) -> (&'static [*const u8], *const u64) {
+    let mut len = 0;
+    while *start.add(len) != 0 {
+        len += 1;
+    }
+    let ptrs = core::slice::from_raw_parts(start as *const *const u8, len);
+    (ptrs, start.add(len + 1))
+}
+
+/// The `argv` and `envp` arrays and the auxiliary vector, or `None` before
+/// `_start` has run.
+fn initial_stack() -> Option<(&'static [*const u8], &'static [*const u8], *const u64)> {
+    let sp = INITIAL_SP.load(Ordering::Relaxed) as *const u64;
+    if sp.is_null() {
+        return None;
+    }
+    let (argv, next) = unsafe { null_terminated(sp.add(1)) };
+    let (envp, auxv) = unsafe { null_terminated(next) };
+    Some((argv, envp, auxv))
+}
+
+/// The string at `ptr`, terminated by a NUL byte.
+fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while unsafe { *ptr.add(len) } != 0 {
+        len += 1;
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    core::str::from_utf8(bytes).unwrap_or("")
+}
+
+/// Iterator over strings in `argv` or `envp`, see [`args`].
+#[derive(Debug, Clone)]
+pub struct Args {
+    ptrs: core::slice::Iter<'static, *const u8>,
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        self.ptrs.next().map(|&ptr| c_str(ptr))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ptrs.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Args {
+    fn next_back(&mut self) -> Option<&'static str> {
+        self.ptrs.next_back().map(|&ptr| c_str(ptr))
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+/// The arguments of the process, starting with the program name.
+pub fn args() -> Args {
+    Args {
+        ptrs: initial_stack().map_or(&[][..], |(argv, _, _)| argv).iter(),
+    }
+}
+
+/// The environment variables of the process as `(name, value)` pairs.
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let vars = Args {
+        ptrs: initial_stack().map_or(&[][..], |(_, envp, _)| envp).iter(),
+    };
+    vars.map(|var| var.split_once('=').unwrap_or((var, "")))
+}
+
+/// The value of the environment variable `name`.
+pub fn env(name: &str) -> Option<&'static str> {
+    vars().find(|(key, _)| *key == name).map(|(_, value)| value)
+}
+
+/// The value of the auxiliary vector entry `key`, such as [`AT_PAGESZ`].
+pub fn auxv(key: u64) -> Option<u64> {
+    let (_, _, mut entry) = initial_stack()?;
+    loop {
+        let (k, v) = unsafe { (*entry, *entry.add(1)) };
+        if k == AT_NULL {
+            return None;
+        }
+        if k == key {
+            return Some(v);
+        }
+        entry = unsafe { entry.add(2) };
+    }
+}
@@ -1,7 +1,7 @@
 #![no_std]
-#![feature(core_intrinsics)]
 #![feature(alloc_error_handler)]
 #![feature(asm_const)]
+#![feature(naked_functions)]
 
 extern crate alloc;
 extern crate rlibc;
@@ -11,12 +11,15 @@ mod console;
 
 mod allocator;
 mod display;
+mod env;
 mod ide_device;
 mod input;
+mod start;
 mod syscall;
 
 pub use console::_print;
 pub use display::*;
+pub use env::*;
 pub use input::*;
 pub use pc_keyboard::{DecodedKey, KeyCode};
 pub use syscall::*;
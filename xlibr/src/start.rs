@@ -0,0 +1,37 @@
+//! Entry point of user programs
+//!
+//! The kernel starts a program at `_start` with the stack pointer at `argc`.
+//! `_start` records the initial stack for [`args`](crate::args) and
+//! [`env`](crate::env), calls the `main` function of the program and exits with
+//! the code it returns. A program defines it as
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub fn main() -> i32 {
+//!     0
+//! }
+//! ```
+
+extern "Rust" {
+    fn main() -> i32;
+}
+
+/// Pass the initial stack pointer to [`start`] with the stack aligned for a call.
+#[naked]
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    core::arch::asm!(
+        "mov rdi, rsp",
+        "and rsp, -16",
+        "call {}",
+        "ud2",
+        sym start,
+        options(noreturn)
+    );
+}
+
+extern "C" fn start(sp: *const u64) -> ! {
+    unsafe { crate::env::init(sp) };
+    let code = unsafe { main() };
+    crate::sys_exit(code)
+}
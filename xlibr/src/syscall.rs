@@ -1,87 +1,157 @@
-#[repr(u64)]
-pub enum Syscall {
-    SpawnProcess = 1,
-    ExitProcess = 2,
-    PrintStr = 5,
-    ReadKey = 6,
-    PlotPixel = 7,
-    Sleep = 8,
-    DisplayResolution = 9,
-    Allocate = 10,
-    Deallocate = 11,
-    ReadDisk = 12,
-}
-
-pub fn syscall(id: u64, arg0: u64, arg1: u64, arg2: u64) {
+//! Syscall wrappers, see `syscall_abi` for the ABI
+
+use alloc::vec::Vec;
+use syscall_abi::{raw, DiskRead, Pixel, StrRef};
+pub use syscall_abi::{
+    Errno, ProcessInfo, Resolution, Syscall, SyscallResult, Timespec, ABI_VERSION, CLOCK_MONOTONIC,
+    CLOCK_REALTIME, PROCESS_BLOCKED, PROCESS_READY, PROCESS_RUNNING, PROCESS_SLEEPING,
+    PROCESS_ZOMBIE, SIGKILL, SIGSEGV,
+};
+
+/// Enter the kernel with `syscall`, which clobbers `rcx` and `r11`.
+pub fn syscall(id: u64, arg0: u64, arg1: u64, arg2: u64) -> SyscallResult {
+    unsafe { syscall_abi::invoke(id, [arg0, arg1, arg2]) }
+}
+
+/// Enter the kernel with `int 0x80`, the slower path kept for compatibility.
+pub fn syscall_int80(id: u64, arg0: u64, arg1: u64, arg2: u64) -> SyscallResult {
+    let ret: u64;
     unsafe {
-        core::arch::asm!("int {id}", id = const 0x80, in("rax") id, in("rdi") arg0, in("rsi") arg1, in("rdx") arg2);
+        core::arch::asm!("int {id}", id = const 0x80, inlateout("rax") id => ret, in("rdi") arg0, in("rsi") arg1, in("rdx") arg2);
     }
+    syscall_abi::decode(ret)
+}
+
+/// Terminate the calling process, `code` is reported to a parent in [`sys_waitpid`].
+pub fn sys_exit(code: i32) -> ! {
+    let _ = unsafe { raw::exit_process(code as u32 as u64) };
+    // the kernel returns `EPERM` to its own pseudo-process, which cannot exit
+    loop {}
+}
+
+/// Start the program at `path` as a child process and return its pid.
+pub fn sys_spawn(path: &str, args: &[&str]) -> Result<usize, Errno> {
+    let path = StrRef::new(path);
+    let argv: Vec<StrRef> = args.iter().map(|arg| StrRef::new(arg)).collect();
+    let pid = unsafe {
+        raw::spawn(
+            &path as *const _ as u64,
+            argv.as_ptr() as u64,
+            argv.len() as u64,
+        )
+    }?;
+    Ok(pid as usize)
+}
+
+/// Replace the calling process with the program at `path`, only returns on error.
+pub fn sys_exec(path: &str, args: &[&str]) -> Errno {
+    let path = StrRef::new(path);
+    let argv: Vec<StrRef> = args.iter().map(|arg| StrRef::new(arg)).collect();
+    let ret = unsafe {
+        raw::exec(
+            &path as *const _ as u64,
+            argv.as_ptr() as u64,
+            argv.len() as u64,
+        )
+    };
+    ret.err().unwrap_or(Errno::EINVAL)
+}
+
+/// Duplicate the calling process, returns the pid of the child in the parent
+/// and 0 in the child.
+pub fn sys_fork() -> Result<usize, Errno> {
+    let pid = unsafe { raw::fork() }?;
+    Ok(pid as usize)
+}
+
+/// Wait for the child `pid` to exit and return its exit code, which is
+/// `-SIGSEGV` after a bad memory access and `-SIGKILL` after `sys_kill`.
+pub fn sys_waitpid(pid: usize) -> Result<i32, Errno> {
+    let code = unsafe { raw::waitpid(pid as u64) }?;
+    Ok(code as u32 as i32)
+}
+
+/// Read `clock`, one of [`CLOCK_REALTIME`] and [`CLOCK_MONOTONIC`].
+pub fn sys_clock_gettime(clock: u64) -> Result<core::time::Duration, Errno> {
+    let mut time = Timespec::default();
+    unsafe { raw::clock_gettime(clock, (&mut time) as *mut _ as u64) }?;
+    Ok(core::time::Duration::new(time.sec, time.nsec as u32))
+}
+
+/// Fill `buf` with the processes, returns the total number of processes,
+/// which may be larger than `buf`.
+pub fn sys_list_processes(buf: &mut [ProcessInfo]) -> Result<usize, Errno> {
+    let count = unsafe { raw::list_processes(buf.as_mut_ptr() as u64, buf.len() as u64) }?;
+    Ok(count as usize)
+}
+
+/// Terminate the process `pid`, which exits with `-SIGKILL`. Only the calling
+/// process and its children may be killed.
+pub fn sys_kill(pid: usize) -> Result<(), Errno> {
+    unsafe { raw::kill(pid as u64) }?;
+    Ok(())
+}
+
+/// Set the nice value of the process `pid`, from -20 (highest priority)
+/// to 19. Only the calling process and its children may be reniced, and only
+/// to a lower priority.
+pub fn sys_set_priority(pid: usize, nice: i64) -> Result<(), Errno> {
+    unsafe { raw::set_priority(pid as u64, nice as u64) }?;
+    Ok(())
 }
 
-pub fn sys_exit() -> ! {
-    syscall(Syscall::ExitProcess as u64, 0, 0, 0);
-    unsafe { core::intrinsics::unreachable() }
+pub fn sys_getpid() -> usize {
+    unsafe { raw::getpid() }.unwrap_or(0) as usize
 }
 
-pub fn sys_print_str(s: &str) {
-    syscall(
-        Syscall::PrintStr as u64,
-        s.as_ptr() as u64,
-        s.len() as u64,
-        0,
-    );
+/// Print `s` and return the number of bytes written.
+pub fn sys_print_str(s: &str) -> SyscallResult {
+    let s = StrRef::new(s);
+    unsafe { raw::print_str(&s as *const _ as u64) }
 }
 
+/// Wait for a key press, `None` is only returned on error.
 pub fn sys_read_key() -> Option<pc_keyboard::DecodedKey> {
     let mut s: Option<pc_keyboard::DecodedKey> = None;
-    syscall(Syscall::ReadKey as u64, (&mut s) as *mut _ as u64, 0, 0);
+    unsafe { raw::read_key((&mut s) as *mut _ as u64) }.ok()?;
     s
 }
 
-pub fn sys_plot_pixel(x: usize, y: usize, color: u32) {
-    syscall(Syscall::PlotPixel as u64, x as u64, y as u64, color as u64);
+pub fn sys_plot_pixel(x: usize, y: usize, color: u32) -> Result<(), Errno> {
+    let pixel = Pixel {
+        x: x as u64,
+        y: y as u64,
+        color,
+    };
+    unsafe { raw::plot_pixel(&pixel as *const _ as u64) }.map(|_| ())
 }
 
 pub fn sys_sleep(ns: u64) {
-    syscall(Syscall::Sleep as u64, ns, 0, 0);
-}
-
-pub fn sys_display_resolution() -> (u64, u64) {
-    let (mut x, mut y) = (0, 0);
-    syscall(
-        Syscall::DisplayResolution as u64,
-        (&mut x) as *mut _ as u64,
-        (&mut y) as *mut _ as u64,
-        0,
-    );
-    (x, y)
-}
-
-pub fn sys_allocate(layout: &core::alloc::Layout) -> *mut u8 {
-    let mut ptr = 0 as *mut u8;
-    syscall(
-        Syscall::Allocate as u64,
-        layout as *const _ as u64,
-        &mut ptr as *mut *mut u8 as u64,
-        0,
-    );
-    ptr
-}
-
-pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) {
-    syscall(
-        Syscall::Deallocate as u64,
-        ptr as u64,
-        layout as *const _ as u64,
-        0,
-    );
-}
-
-pub fn sys_read_disk(id: u64, dst: &mut [u8]) {
-    syscall(
-        Syscall::ReadDisk as u64,
-        id,
-        dst.as_mut_ptr() as u64,
-        dst.len() as u64,
-    )
+    let _ = unsafe { raw::sleep(ns) };
+}
+
+pub fn sys_display_resolution() -> Result<(u64, u64), Errno> {
+    let mut resolution = Resolution::default();
+    unsafe { raw::display_resolution((&mut resolution) as *mut _ as u64) }?;
+    Ok((resolution.width, resolution.height))
+}
+
+/// Set the end of the process heap to `addr` and return the new end.
+/// The heap is left unchanged if `addr` is 0 or invalid.
+pub fn sys_brk(addr: u64) -> u64 {
+    unsafe { raw::brk(addr) }.unwrap_or(0)
+}
+
+pub fn sys_read_disk(id: u64, dst: &mut [u8]) -> Result<(), Errno> {
+    let req = DiskRead {
+        block: id,
+        ptr: dst.as_mut_ptr() as u64,
+        len: dst.len() as u64,
+    };
+    unsafe { raw::read_disk(&req as *const _ as u64) }.map(|_| ())
+}
+
+/// The ABI version of the running kernel, to be compared with [`ABI_VERSION`].
+pub fn sys_abi_version() -> SyscallResult {
+    unsafe { raw::abi_version() }
 }